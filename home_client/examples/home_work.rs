@@ -5,7 +5,8 @@ async fn main() {
     let c = home_client::HomeClient::new("127.0.0.1:4083")
         .await
         .unwrap();
-    c.update_device("R", "S", Device::Socket(Socket::new(220., 5.5, true)))
+    let socket = Socket::new(220., 5.5, true).unwrap();
+    c.update_device("R", "S", Device::Socket(socket))
         .await
         .unwrap();
     for room in c.get_room_list().await.unwrap_or_default().iter() {
@@ -0,0 +1,337 @@
+//! Command-line client of the home server.
+//!
+//! Exit codes: 0 on success, 1 when the server refuses the request, 2 on bad usage,
+//! 3 when the server can't be reached or is lost and 4 on a response the client doesn't
+//! understand.
+
+use clap::{Parser, Subcommand, ValueEnum};
+use home_client::error::HomeError;
+use home_client::{HomeClient, HomeEvent};
+use serde_json::{json, Value};
+use smart_home::smart_device::Device;
+use std::process::ExitCode;
+
+#[derive(Debug, Parser)]
+#[command(name = "home-cli", about = "Talks to the home server.")]
+struct Cli {
+    /// Address of the server.
+    #[arg(long, env = "HOME_SERVER_ADDR", default_value = "127.0.0.1:4083")]
+    addr: String,
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Lists the rooms.
+    Rooms,
+    /// Lists the devices of the room.
+    Devices { room: String },
+    /// Shows the state of the device.
+    Get { room: String, device: String },
+    /// Changes the socket.
+    Socket {
+        room: String,
+        device: String,
+        #[arg(long, conflicts_with = "off")]
+        on: bool,
+        #[arg(long)]
+        off: bool,
+        /// Current drawn by the load when the socket is on, A.
+        #[arg(long)]
+        current: Option<f64>,
+        /// Voltage, V.
+        #[arg(long)]
+        voltage: Option<f64>,
+    },
+    /// Sets the temperature of the thermometer, °C.
+    Temperature {
+        room: String,
+        device: String,
+        #[arg(allow_negative_numbers = true)]
+        temperature: f64,
+    },
+    /// Adds an empty room.
+    AddRoom { room: String },
+    /// Removes the room with its devices.
+    RemoveRoom { room: String },
+    /// Adds a device in its default state.
+    AddDevice {
+        room: String,
+        device: String,
+        #[arg(value_enum)]
+        kind: Kind,
+    },
+    /// Removes the device.
+    RemoveDevice { room: String, device: String },
+    /// Shows the state of the device whenever it changes, until interrupted.
+    Watch { room: String, device: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Kind {
+    Socket,
+    Thermometer,
+    Lock,
+    WindowCovering,
+}
+
+#[derive(Debug)]
+enum Failure {
+    Connect(String),
+    Home(HomeError),
+    /// Request that makes no sense for the device.
+    Usage(String),
+}
+
+impl Failure {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Failure::Home(HomeError::ResponseErr(_)) => 1,
+            Failure::Usage(_) => 2,
+            Failure::Connect(_)
+            | Failure::Home(
+                HomeError::WhenRequested(_) | HomeError::Disconnected | HomeError::PoolTimeout,
+            ) => 3,
+            Failure::Home(HomeError::BadResponse) => 4,
+        }
+    }
+}
+
+impl From<HomeError> for Failure {
+    fn from(e: HomeError) -> Self {
+        Failure::Home(e)
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Failure::Connect(e) => write!(f, "Can't connect to the server: {e}"),
+            Failure::Home(e) => write!(f, "{e}"),
+            Failure::Usage(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(&cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("home-cli: {e}");
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+async fn connect(addr: &str) -> Result<HomeClient, Failure> {
+    HomeClient::new(addr)
+        .await
+        .map_err(|e| Failure::Connect(e.to_string()))
+}
+
+async fn run(cli: &Cli) -> Result<(), Failure> {
+    let client = connect(&cli.addr).await?;
+    match &cli.command {
+        Command::Rooms => print_list(cli.format, &client.get_room_list().await?),
+        Command::Devices { room } => {
+            let mut devices = client.get_device_list(room).await?;
+            devices.sort();
+            print_list(cli.format, &devices);
+        }
+        Command::Get { room, device } => {
+            print_device(cli.format, &client.get_device(room, device).await?)
+        }
+        Command::Socket {
+            room,
+            device,
+            on,
+            off,
+            current,
+            voltage,
+        } => {
+            let Device::Socket(mut socket) = client.get_device(room, device).await? else {
+                return Err(Failure::Usage(format!("'{room}/{device}' isn't a socket.")));
+            };
+            let invalid = |e: smart_home::error::SocketError| Failure::Usage(e.to_string());
+            if let Some(voltage) = voltage {
+                socket.set_voltage(*voltage).map_err(invalid)?;
+            }
+            if let Some(current) = current {
+                socket.set_current(*current).map_err(invalid)?;
+            }
+            if *on || *off {
+                socket.switch(*on);
+            }
+            client
+                .update_device(room, device, Device::Socket(socket))
+                .await?;
+            print_device(cli.format, &client.get_device(room, device).await?);
+        }
+        Command::Temperature {
+            room,
+            device,
+            temperature,
+        } => {
+            let Device::Thermometer(mut thermometer) = client.get_device(room, device).await?
+            else {
+                return Err(Failure::Usage(format!(
+                    "'{room}/{device}' isn't a thermometer."
+                )));
+            };
+            thermometer.set_temperature(*temperature);
+            client
+                .update_device(room, device, Device::Thermometer(thermometer))
+                .await?;
+            print_device(cli.format, &client.get_device(room, device).await?);
+        }
+        Command::AddRoom { room } => client.add_room(room).await?,
+        Command::RemoveRoom { room } => client.remove_room(room).await?,
+        Command::AddDevice { room, device, kind } => {
+            let new = match kind {
+                Kind::Socket => Device::new_socket(),
+                Kind::Thermometer => Device::new_thermometer(),
+                Kind::Lock => Device::new_lock(),
+                Kind::WindowCovering => Device::new_window_covering(),
+            };
+            client.add_device(room, device, new).await?;
+        }
+        Command::RemoveDevice { room, device } => client.remove_device(room, device).await?,
+        Command::Watch { room, device } => {
+            print_device(cli.format, &client.get_device(room, device).await?);
+            let subscription = connect(&cli.addr).await?.subscribe().await?;
+            loop {
+                if let HomeEvent::Device {
+                    room: changed_room,
+                    device: changed_device,
+                    state,
+                } = subscription.next_event().await?
+                {
+                    if changed_room == *room && changed_device == *device {
+                        if cli.format == Format::Table {
+                            println!();
+                        }
+                        print_device(cli.format, &state);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_list(format: Format, items: &[String]) {
+    match format {
+        Format::Table => items.iter().for_each(|item| println!("{item}")),
+        Format::Json => println!("{}", json!(items)),
+    }
+}
+
+/// A table of fields or a JSON object per line.
+fn print_device(format: Format, device: &Device) {
+    let fields = device_fields(device);
+    match format {
+        Format::Table => {
+            let width = fields.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+            for (name, value) in fields {
+                let value = match value {
+                    Value::String(value) => value,
+                    Value::Null => String::from("none"),
+                    value => value.to_string(),
+                };
+                println!("{name:width$}  {value}");
+            }
+        }
+        Format::Json => {
+            let object: serde_json::Map<_, _> = fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect();
+            println!("{}", Value::Object(object));
+        }
+    }
+}
+
+/// Values in °C, V, A, W and kWh.
+fn device_fields(device: &Device) -> Vec<(&'static str, Value)> {
+    let mut fields = vec![("kind", json!(device.kind()))];
+    match device {
+        Device::Socket(socket) => fields.extend([
+            ("on", json!(socket.is_on())),
+            ("voltage", json!(socket.get_voltage())),
+            ("current", json!(socket.get_current())),
+            ("load_current", json!(socket.get_load_current())),
+            ("power", json!(socket.get_current_power())),
+            ("energy", json!(socket.get_energy())),
+            ("peak_power", json!(socket.get_peak_power())),
+            ("power_limit", json!(socket.get_power_limit())),
+        ]),
+        Device::Thermometer(thermometer) => {
+            fields.push(("temperature", json!(thermometer.get_temperature())))
+        }
+        Device::Lock(lock) => fields.extend([
+            ("target", json!(lock.get_target().as_str())),
+            ("state", json!(lock.get_state().as_str())),
+        ]),
+        Device::WindowCovering(covering) => fields.extend([
+            ("position", json!(covering.get_position())),
+            ("target_position", json!(covering.get_target_position())),
+            ("tilt", json!(covering.get_tilt())),
+            ("target_tilt", json!(covering.get_target_tilt())),
+        ]),
+        _ => {}
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    use smart_home::smart_device::Thermometer;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+        let cli = Cli::try_parse_from([
+            "home-cli",
+            "--format",
+            "json",
+            "temperature",
+            "R",
+            "T",
+            "-5",
+        ])
+        .unwrap();
+        assert_eq!(Format::Json, cli.format);
+        assert!(matches!(
+            cli.command,
+            Command::Temperature { temperature, .. } if temperature == -5_f64
+        ));
+        assert!(Cli::try_parse_from(["home-cli", "socket", "R", "S", "--on", "--off"]).is_err());
+        assert_eq!(
+            vec![("kind", json!("thermometer")), ("temperature", json!(21.5))],
+            device_fields(&Device::Thermometer(Thermometer::new(21.5)))
+        );
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(
+            1,
+            Failure::Home(HomeError::ResponseErr("x".into())).exit_code()
+        );
+        assert_eq!(3, Failure::Connect("x".into()).exit_code());
+        assert_eq!(4, Failure::Home(HomeError::BadResponse).exit_code());
+    }
+}
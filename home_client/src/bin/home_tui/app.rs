@@ -0,0 +1,523 @@
+//! State of the console and everything it does without the terminal or the server.
+
+use smart_home::smart_device::{Device, LockState};
+use std::collections::BTreeMap;
+
+/// Kept lines of the message log.
+const MAX_MESSAGES: usize = 100;
+const TEMPERATURE_STEP: f64 = 0.5;
+const CURRENT_STEP: f64 = 0.5;
+const POSITION_STEP: f64 = 10.0;
+
+pub const COMMANDS: [&str; 15] = [
+    "on",
+    "off",
+    "lock",
+    "unlock",
+    "current",
+    "voltage",
+    "temperature",
+    "position",
+    "tilt",
+    "add-room",
+    "remove-room",
+    "add-device",
+    "remove-device",
+    "reload",
+    "quit",
+];
+pub const KINDS: [&str; 4] = ["socket", "thermometer", "lock", "window-covering"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    Switch(bool),
+    Lock(bool),
+    Current(f64),
+    Voltage(f64),
+    Temperature(f64),
+    Position(f64),
+    Tilt(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Change {
+        room: String,
+        device: String,
+        change: Change,
+    },
+    AddRoom(String),
+    RemoveRoom(String),
+    AddDevice {
+        room: String,
+        device: String,
+        kind: String,
+    },
+    RemoveDevice {
+        room: String,
+        device: String,
+    },
+    Reload,
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Command,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Path {
+    pub room: String,
+    pub device: String,
+}
+
+#[derive(Debug)]
+pub struct App {
+    /// Rooms with their devices, sorted.
+    pub rooms: Vec<(String, Vec<String>)>,
+    pub devices: BTreeMap<Path, Device>,
+    pub selected: Option<Path>,
+    pub mode: Mode,
+    pub input: String,
+    pub messages: Vec<String>,
+    history: Vec<String>,
+    /// Position while browsing the history.
+    history_index: Option<usize>,
+}
+
+impl App {
+    pub fn new() -> Self {
+        Self {
+            rooms: vec![],
+            devices: BTreeMap::new(),
+            selected: None,
+            mode: Mode::Normal,
+            input: String::new(),
+            messages: vec![],
+            history: vec![],
+            history_index: None,
+        }
+    }
+
+    /// Replaces the tree, keeping the selection if the device is still there.
+    pub fn set_tree(&mut self, rooms: Vec<(String, Vec<String>)>, devices: BTreeMap<Path, Device>) {
+        self.rooms = rooms;
+        self.devices = devices;
+        let paths = self.paths();
+        if self
+            .selected
+            .as_ref()
+            .is_none_or(|selected| !paths.contains(selected))
+        {
+            self.selected = paths.into_iter().next();
+        }
+    }
+
+    /// Devices in the order of the tree.
+    pub fn paths(&self) -> Vec<Path> {
+        self.rooms
+            .iter()
+            .flat_map(|(room, devices)| {
+                devices.iter().map(|device| Path {
+                    room: room.clone(),
+                    device: device.clone(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn select_next(&mut self, forward: bool) {
+        let paths = self.paths();
+        let current = self
+            .selected
+            .as_ref()
+            .and_then(|selected| paths.iter().position(|path| path == selected));
+        let next = match current {
+            None => 0,
+            Some(index) if forward => (index + 1).min(paths.len().saturating_sub(1)),
+            Some(index) => index.saturating_sub(1),
+        };
+        self.selected = paths.get(next).cloned();
+    }
+
+    pub fn selected_device(&self) -> Option<(&Path, &Device)> {
+        let path = self.selected.as_ref()?;
+        Some((path, self.devices.get(path)?))
+    }
+
+    pub fn message(&mut self, message: impl Into<String>) {
+        self.messages.push(message.into());
+        if self.messages.len() > MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+    }
+
+    /// Space on the selected device: switches a socket, locks or unlocks a lock.
+    pub fn toggle(&self) -> Option<Action> {
+        let (path, device) = self.selected_device()?;
+        let change = match device {
+            Device::Socket(socket) => Change::Switch(!socket.is_on()),
+            Device::Lock(lock) => Change::Lock(lock.get_target() != LockState::Locked),
+            _ => return None,
+        };
+        Some(change_action(path, change))
+    }
+
+    /// `+` or `-` on the selected device: changes its main value by a step.
+    pub fn adjust(&self, up: bool) -> Option<Action> {
+        let (path, device) = self.selected_device()?;
+        let sign = if up { 1.0 } else { -1.0 };
+        let change = match device {
+            Device::Socket(socket) => {
+                Change::Current((socket.get_load_current() + sign * CURRENT_STEP).max(0.0))
+            }
+            Device::Thermometer(thermometer) => {
+                Change::Temperature(thermometer.get_temperature() + sign * TEMPERATURE_STEP)
+            }
+            Device::WindowCovering(covering) => {
+                Change::Position(covering.get_target_position() + sign * POSITION_STEP)
+            }
+            _ => return None,
+        };
+        Some(change_action(path, change))
+    }
+
+    /// Starts a command which edits the main value of the selected device.
+    pub fn edit(&mut self) {
+        let Some((path, device)) = self.selected_device() else {
+            return;
+        };
+        let command = match device {
+            Device::Socket(_) => "current",
+            Device::Thermometer(_) => "temperature",
+            Device::WindowCovering(_) => "position",
+            Device::Lock(_) => "lock",
+            _ => return,
+        };
+        self.input = format!("{command} {} {} ", quote(&path.room), quote(&path.device));
+        self.mode = Mode::Command;
+    }
+
+    /// Remembers the entered command, repeats are skipped.
+    pub fn push_history(&mut self, line: &str) {
+        if !line.trim().is_empty() && self.history.last().is_none_or(|last| last != line) {
+            self.history.push(line.into());
+        }
+        self.history_index = None;
+    }
+
+    /// Up and down in the command line.
+    pub fn browse_history(&mut self, back: bool) {
+        if self.history.is_empty() {
+            return;
+        }
+        let index = match (self.history_index, back) {
+            (None, true) => Some(self.history.len() - 1),
+            (None, false) => None,
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index + 1 < self.history.len() => Some(index + 1),
+            (Some(_), false) => None,
+        };
+        self.history_index = index;
+        self.input = index
+            .map(|index| self.history[index].clone())
+            .unwrap_or_default();
+    }
+
+    /// Completes the last word of the command line to the common prefix of its candidates:
+    /// commands, then room names, then device names of the room, then device kinds.
+    pub fn complete(&mut self) {
+        let mut words = split(&self.input);
+        let partial = if self.input.is_empty() || self.input.ends_with(' ') {
+            String::new()
+        } else {
+            words.pop().unwrap_or_default()
+        };
+        let candidates: Vec<String> = match (words.first().map(String::as_str), words.len()) {
+            (_, 0) => COMMANDS.iter().map(|command| command.to_string()).collect(),
+            (Some("reload" | "quit"), _) => vec![],
+            (Some(_), 1) => self.rooms.iter().map(|(room, _)| room.clone()).collect(),
+            (Some("add-room" | "remove-room" | "add-device"), 2) => vec![],
+            (Some(_), 2) => self
+                .rooms
+                .iter()
+                .find(|(room, _)| *room == words[1])
+                .map(|(_, devices)| devices.clone())
+                .unwrap_or_default(),
+            (Some("add-device"), 3) => KINDS.iter().map(|kind| kind.to_string()).collect(),
+            _ => vec![],
+        };
+        let matching: Vec<_> = candidates
+            .iter()
+            .filter(|candidate| candidate.starts_with(&partial))
+            .collect();
+        let Some(first) = matching.first() else {
+            return;
+        };
+        let mut common = first.to_string();
+        for candidate in &matching[1..] {
+            while !candidate.starts_with(&common) {
+                common.pop();
+            }
+        }
+        words.push(common);
+        let mut input = words
+            .iter()
+            .map(|word| quote(word))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if matching.len() == 1 {
+            input.push(' ');
+        } else if input.ends_with('"') {
+            // The name goes on.
+            input.pop();
+        }
+        self.input = input;
+    }
+}
+
+fn change_action(path: &Path, change: Change) -> Action {
+    Action::Change {
+        room: path.room.clone(),
+        device: path.device.clone(),
+        change,
+    }
+}
+
+/// Words of the command line, double quotes keep names with spaces together.
+pub fn split(line: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut started = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            ' ' if !quoted => {
+                if started {
+                    words.push(std::mem::take(&mut word));
+                    started = false;
+                }
+            }
+            c => {
+                word.push(c);
+                started = true;
+            }
+        }
+    }
+    if started {
+        words.push(word);
+    }
+    words
+}
+
+fn quote(word: &str) -> String {
+    if word.contains(' ') {
+        format!("\"{word}\"")
+    } else {
+        word.into()
+    }
+}
+
+pub fn parse(line: &str) -> Result<Action, String> {
+    let words = split(line);
+    let words: Vec<_> = words.iter().map(String::as_str).collect();
+    let number = |value: &str| {
+        value
+            .parse::<f64>()
+            .map_err(|_| format!("'{value}' isn't a number."))
+    };
+    let change = |room: &str, device: &str, change| Action::Change {
+        room: room.into(),
+        device: device.into(),
+        change,
+    };
+    let action = match words.as_slice() {
+        ["on", room, device] => change(room, device, Change::Switch(true)),
+        ["off", room, device] => change(room, device, Change::Switch(false)),
+        ["lock", room, device] => change(room, device, Change::Lock(true)),
+        ["unlock", room, device] => change(room, device, Change::Lock(false)),
+        ["current", room, device, value] => change(room, device, Change::Current(number(value)?)),
+        ["voltage", room, device, value] => change(room, device, Change::Voltage(number(value)?)),
+        ["temperature", room, device, value] => {
+            change(room, device, Change::Temperature(number(value)?))
+        }
+        ["position", room, device, value] => change(room, device, Change::Position(number(value)?)),
+        ["tilt", room, device, value] => change(room, device, Change::Tilt(number(value)?)),
+        ["add-room", room] => Action::AddRoom(room.to_string()),
+        ["remove-room", room] => Action::RemoveRoom(room.to_string()),
+        ["add-device", room, device, kind] if KINDS.contains(kind) => Action::AddDevice {
+            room: room.to_string(),
+            device: device.to_string(),
+            kind: kind.to_string(),
+        },
+        ["add-device", _, _, kind] => return Err(format!("Unknown device kind '{kind}'.")),
+        ["remove-device", room, device] => Action::RemoveDevice {
+            room: room.to_string(),
+            device: device.to_string(),
+        },
+        ["reload"] => Action::Reload,
+        ["quit"] => Action::Quit,
+        [command, ..] if COMMANDS.contains(command) => {
+            return Err(format!("Wrong arguments of '{command}'."))
+        }
+        [command, ..] => return Err(format!("Unknown command '{command}'.")),
+        [] => return Err(String::from("Empty command.")),
+    };
+    Ok(action)
+}
+
+/// Applies the change to a copy of the device state before it's sent to the server.
+pub fn apply(device: &mut Device, change: Change) -> Result<(), String> {
+    match (device, change) {
+        (Device::Socket(socket), Change::Switch(on)) => socket.switch(on),
+        (Device::Socket(socket), Change::Current(current)) => {
+            socket.set_current(current).map_err(|e| e.to_string())?
+        }
+        (Device::Socket(socket), Change::Voltage(voltage)) => {
+            socket.set_voltage(voltage).map_err(|e| e.to_string())?
+        }
+        (Device::Thermometer(thermometer), Change::Temperature(temperature)) => {
+            thermometer.set_temperature(temperature)
+        }
+        (Device::Lock(lock), Change::Lock(true)) => lock.lock(),
+        (Device::Lock(lock), Change::Lock(false)) => lock.unlock(),
+        (Device::WindowCovering(covering), Change::Position(position)) => {
+            covering.set_target_position(position)
+        }
+        (Device::WindowCovering(covering), Change::Tilt(tilt)) => covering.set_target_tilt(tilt),
+        (device, _) => return Err(format!("Can't do that to a {}.", device.kind())),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smart_home::smart_device::Thermometer;
+
+    fn app() -> App {
+        let mut app = App::new();
+        let rooms = vec![
+            ("Kitchen".into(), vec!["Socket".into(), "Sensor".into()]),
+            ("Living room".into(), vec!["Lamp".into()]),
+        ];
+        let mut devices = BTreeMap::new();
+        let kitchen = |device: &str| Path {
+            room: "Kitchen".into(),
+            device: device.into(),
+        };
+        devices.insert(kitchen("Socket"), Device::new_socket());
+        devices.insert(
+            kitchen("Sensor"),
+            Device::Thermometer(Thermometer::new(20.0)),
+        );
+        app.set_tree(rooms, devices);
+        app
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut app = app();
+        assert_eq!(
+            Some("Socket"),
+            app.selected.as_ref().map(|path| path.device.as_str())
+        );
+        assert_eq!(
+            Some(Action::Change {
+                room: "Kitchen".into(),
+                device: "Socket".into(),
+                change: Change::Switch(true),
+            }),
+            app.toggle()
+        );
+        app.select_next(true);
+        app.select_next(true);
+        app.select_next(true);
+        assert_eq!(
+            Some("Lamp"),
+            app.selected.as_ref().map(|path| path.device.as_str())
+        );
+        app.select_next(false);
+        assert!(matches!(
+            app.adjust(true),
+            Some(Action::Change {
+                change: Change::Temperature(temperature),
+                ..
+            }) if temperature == 20.5
+        ));
+        app.edit();
+        assert_eq!(Mode::Command, app.mode);
+        assert_eq!("temperature Kitchen Sensor ", app.input);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Ok(Action::Change {
+                room: "Living room".into(),
+                device: "Lamp".into(),
+                change: Change::Current(1.5),
+            }),
+            parse("current \"Living room\" Lamp 1.5")
+        );
+        assert_eq!(Ok(Action::Quit), parse("quit"));
+        assert!(parse("current R S much").is_err());
+        assert!(parse("on R").is_err());
+        assert!(parse("add-device R D toaster").is_err());
+        assert!(parse("dance").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut device = Device::new_socket();
+        apply(&mut device, Change::Switch(true)).unwrap();
+        apply(&mut device, Change::Current(2.0)).unwrap();
+        match &device {
+            Device::Socket(socket) => assert!(socket.is_on() && socket.get_load_current() == 2.0),
+            _ => unreachable!(),
+        }
+        assert!(apply(&mut device, Change::Temperature(20.0)).is_err());
+    }
+
+    #[test]
+    fn test_history() {
+        let mut app = app();
+        app.push_history("on R S");
+        app.push_history("off R S");
+        app.push_history("off R S");
+        app.browse_history(true);
+        assert_eq!("off R S", app.input);
+        app.browse_history(true);
+        app.browse_history(true);
+        assert_eq!("on R S", app.input);
+        app.browse_history(false);
+        app.browse_history(false);
+        assert_eq!("", app.input);
+    }
+
+    #[test]
+    fn test_complete() {
+        let mut app = app();
+        app.input = "te".into();
+        app.complete();
+        assert_eq!("temperature ", app.input);
+        app.input.push('L');
+        app.complete();
+        assert_eq!("temperature \"Living room\" ", app.input);
+        app.input = "on Kitchen S".into();
+        app.complete();
+        assert_eq!("on Kitchen S", app.input);
+        app.input = "on Kitchen So".into();
+        app.complete();
+        assert_eq!("on Kitchen Socket ", app.input);
+        app.input = "add-device Kitchen Toaster w".into();
+        app.complete();
+        assert_eq!("add-device Kitchen Toaster window-covering ", app.input);
+    }
+}
@@ -0,0 +1,297 @@
+//! Interactive console of the home server: a tree of rooms and devices with live panels,
+//! keyboard shortcuts and a command line with history and completion.
+
+mod app;
+mod ui;
+
+use app::{Action, App, Mode, Path};
+use clap::Parser;
+use home_client::connection::{ConnectionState, Reconnect};
+use home_client::{HomeClient, HomeEvent};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::DefaultTerminal;
+use smart_home::smart_device::Device;
+use std::collections::BTreeMap;
+use std::error::Error;
+use tokio::sync::mpsc;
+
+#[derive(Debug, Parser)]
+#[command(name = "home-tui", about = "Interactive console of the home server.")]
+struct Cli {
+    /// Address of the server.
+    #[arg(long, env = "HOME_SERVER_ADDR", default_value = "127.0.0.1:4083")]
+    addr: String,
+}
+
+enum Input {
+    Key(KeyEvent),
+    Redraw,
+    Device(Path, Device),
+    Connection(ConnectionState),
+    /// The subscription ended.
+    Lost(String),
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let client = HomeClient::new(&cli.addr).await?;
+    // Live updates resume whenever the server is back.
+    let subscription = HomeClient::new(&cli.addr)
+        .await?
+        .reconnect(Reconnect::default().attempts(None))
+        .subscribe()
+        .await?;
+    let (inputs, mut received) = mpsc::unbounded_channel();
+
+    let mut state = subscription.connection_state();
+    let states = inputs.clone();
+    tokio::spawn(async move {
+        while state.changed().await.is_ok() {
+            let current = *state.borrow_and_update();
+            if states.send(Input::Connection(current)).is_err() {
+                return;
+            }
+        }
+    });
+    let keys = inputs.clone();
+    // Reading the terminal blocks.
+    std::thread::spawn(move || loop {
+        let input = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => Input::Key(key),
+            Ok(Event::Resize(..)) => Input::Redraw,
+            Ok(_) => continue,
+            Err(_) => return,
+        };
+        if keys.send(input).is_err() {
+            return;
+        }
+    });
+    tokio::spawn(async move {
+        loop {
+            match subscription.next_event().await {
+                Ok(HomeEvent::Device {
+                    room,
+                    device,
+                    state,
+                }) => {
+                    if inputs
+                        .send(Input::Device(Path { room, device }, state))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    let _ = inputs.send(Input::Lost(e.to_string()));
+                    return;
+                }
+            }
+        }
+    });
+
+    let mut app = App::new();
+    if let Err(e) = reload(&client, &mut app).await {
+        app.message(e);
+    }
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &client, &mut app, &mut received).await;
+    ratatui::restore();
+    result
+}
+
+async fn run(
+    terminal: &mut DefaultTerminal,
+    client: &HomeClient,
+    app: &mut App,
+    received: &mut mpsc::UnboundedReceiver<Input>,
+) -> Result<(), Box<dyn Error>> {
+    loop {
+        terminal.draw(|frame| ui::draw(frame, app))?;
+        let action = match received.recv().await {
+            Some(Input::Key(key)) => handle_key(app, key),
+            Some(Input::Redraw) => None,
+            Some(Input::Device(path, device)) => {
+                if let Some(state) = app.devices.get_mut(&path) {
+                    *state = device;
+                } else if let Err(e) = reload(client, app).await {
+                    // A device added by someone else.
+                    app.message(e);
+                }
+                None
+            }
+            Some(Input::Connection(state)) => {
+                match state {
+                    ConnectionState::Connected => {
+                        app.message("Connected again.");
+                        // Changes made while offline weren't pushed.
+                        if let Err(e) = reload(client, app).await {
+                            app.message(e);
+                        }
+                    }
+                    ConnectionState::Reconnecting { attempt } => {
+                        app.message(format!("Server offline, reconnecting ({attempt})..."))
+                    }
+                    ConnectionState::Disconnected => app.message("Server offline."),
+                }
+                None
+            }
+            Some(Input::Lost(e)) => {
+                app.message(format!("Live updates stopped: {e}"));
+                None
+            }
+            None => return Ok(()),
+        };
+        match action {
+            Some(Action::Quit) => return Ok(()),
+            Some(action) => {
+                if let Err(e) = execute(client, app, action).await {
+                    app.message(e);
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+fn handle_key(app: &mut App, key: KeyEvent) -> Option<Action> {
+    if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+        return Some(Action::Quit);
+    }
+    match app.mode {
+        Mode::Normal => match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => Some(Action::Quit),
+            KeyCode::Down | KeyCode::Char('j') => {
+                app.select_next(true);
+                None
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                app.select_next(false);
+                None
+            }
+            KeyCode::Char(' ') => app.toggle(),
+            KeyCode::Char('+') | KeyCode::Char('=') => app.adjust(true),
+            KeyCode::Char('-') => app.adjust(false),
+            KeyCode::Char('e') => {
+                app.edit();
+                None
+            }
+            KeyCode::Char(':') => {
+                app.mode = Mode::Command;
+                app.input.clear();
+                None
+            }
+            KeyCode::Char('r') => Some(Action::Reload),
+            _ => None,
+        },
+        Mode::Command => match key.code {
+            KeyCode::Esc => {
+                app.mode = Mode::Normal;
+                app.input.clear();
+                None
+            }
+            KeyCode::Enter => {
+                let line = std::mem::take(&mut app.input);
+                app.mode = Mode::Normal;
+                app.push_history(&line);
+                match app::parse(&line) {
+                    Ok(action) => Some(action),
+                    Err(e) => {
+                        app.message(e);
+                        None
+                    }
+                }
+            }
+            KeyCode::Tab => {
+                app.complete();
+                None
+            }
+            KeyCode::Up => {
+                app.browse_history(true);
+                None
+            }
+            KeyCode::Down => {
+                app.browse_history(false);
+                None
+            }
+            KeyCode::Backspace => {
+                app.input.pop();
+                None
+            }
+            KeyCode::Char(c) => {
+                app.input.push(c);
+                None
+            }
+            _ => None,
+        },
+    }
+}
+
+async fn execute(client: &HomeClient, app: &mut App, action: Action) -> Result<(), String> {
+    match action {
+        Action::Change {
+            room,
+            device,
+            change,
+        } => {
+            let mut state = client
+                .get_device(&room, &device)
+                .await
+                .map_err(|e| e.to_string())?;
+            app::apply(&mut state, change)?;
+            client
+                .update_device(&room, &device, state)
+                .await
+                .map_err(|e| e.to_string())?;
+            let state = client
+                .get_device(&room, &device)
+                .await
+                .map_err(|e| e.to_string())?;
+            app.devices.insert(Path { room, device }, state);
+            return Ok(());
+        }
+        Action::AddRoom(room) => client.add_room(&room).await,
+        Action::RemoveRoom(room) => client.remove_room(&room).await,
+        Action::AddDevice { room, device, kind } => {
+            let new = match kind.as_str() {
+                "socket" => Device::new_socket(),
+                "thermometer" => Device::new_thermometer(),
+                "lock" => Device::new_lock(),
+                _ => Device::new_window_covering(),
+            };
+            client.add_device(&room, &device, new).await
+        }
+        Action::RemoveDevice { room, device } => client.remove_device(&room, &device).await,
+        Action::Reload | Action::Quit => Ok(()),
+    }
+    .map_err(|e| e.to_string())?;
+    reload(client, app).await
+}
+
+/// Fetches the tree and the states of all devices.
+async fn reload(client: &HomeClient, app: &mut App) -> Result<(), String> {
+    let mut rooms = vec![];
+    let mut devices = BTreeMap::new();
+    let mut room_names = client.get_room_list().await.map_err(|e| e.to_string())?;
+    room_names.sort();
+    for room in room_names {
+        let mut names = client
+            .get_device_list(&room)
+            .await
+            .map_err(|e| e.to_string())?;
+        names.sort();
+        for device in &names {
+            if let Ok(state) = client.get_device(&room, device).await {
+                let path = Path {
+                    room: room.clone(),
+                    device: device.clone(),
+                };
+                devices.insert(path, state);
+            }
+        }
+        rooms.push((room, names));
+    }
+    app.set_tree(rooms, devices);
+    Ok(())
+}
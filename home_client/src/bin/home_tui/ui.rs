@@ -0,0 +1,174 @@
+//! Drawing of the console: the tree, device panels of the selected room and the command line.
+
+use crate::app::{App, Mode, Path};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+use smart_home::smart_device::Device;
+
+const HELP: &str =
+    "↑/↓ select  space toggle  +/- change  e edit  : command  tab complete  r reload  q quit";
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [main, messages, command] = Layout::vertical([
+        Constraint::Min(5),
+        Constraint::Length(4),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [tree, panels] =
+        Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)]).areas(main);
+
+    let mut items = vec![];
+    let mut selected = None;
+    for (room, devices) in &app.rooms {
+        items.push(ListItem::new(Line::from(room.clone()).bold()));
+        for device in devices {
+            let path = Path {
+                room: room.clone(),
+                device: device.clone(),
+            };
+            if app.selected.as_ref() == Some(&path) {
+                selected = Some(items.len());
+            }
+            let summary = app.devices.get(&path).map(summary).unwrap_or_default();
+            items.push(ListItem::new(format!("  {device}  {summary}")));
+        }
+    }
+    let list = List::new(items)
+        .block(Block::bordered().title("Home"))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(
+        list,
+        tree,
+        &mut ListState::default().with_selected(selected),
+    );
+
+    draw_panels(frame, app, panels);
+
+    let shown = messages.height.saturating_sub(2) as usize;
+    let lines: Vec<_> = app
+        .messages
+        .iter()
+        .skip(app.messages.len().saturating_sub(shown))
+        .map(|message| Line::from(message.as_str()))
+        .collect();
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title("Messages")),
+        messages,
+    );
+
+    let line = match app.mode {
+        Mode::Normal => Line::from(HELP).dim(),
+        Mode::Command => Line::from(format!(":{}", app.input)),
+    };
+    frame.render_widget(Paragraph::new(line), command);
+    if app.mode == Mode::Command {
+        let x = command.x + 1 + app.input.chars().count() as u16;
+        frame.set_cursor_position((x.min(command.right().saturating_sub(1)), command.y));
+    }
+}
+
+/// A panel per device of the selected room, the selected one is highlighted.
+fn draw_panels(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
+    let Some(selected) = &app.selected else {
+        frame.render_widget(Block::bordered().title("No devices"), area);
+        return;
+    };
+    let devices: Vec<_> = app
+        .rooms
+        .iter()
+        .find(|(room, _)| *room == selected.room)
+        .map(|(_, devices)| devices.clone())
+        .unwrap_or_default();
+    let panels: Vec<_> = devices
+        .iter()
+        .map(|device| {
+            let path = Path {
+                room: selected.room.clone(),
+                device: device.clone(),
+            };
+            let lines = app.devices.get(&path).map(fields).unwrap_or_default();
+            (path, lines)
+        })
+        .collect();
+    let constraints = panels
+        .iter()
+        .map(|(_, lines)| Constraint::Length(lines.len() as u16 + 2));
+    let areas = Layout::vertical(constraints).split(area);
+    for ((path, lines), area) in panels.into_iter().zip(areas.iter()) {
+        let kind = app
+            .devices
+            .get(&path)
+            .map(Device::kind)
+            .unwrap_or("unknown");
+        let mut block =
+            Block::bordered().title(format!("{} / {} ({kind})", path.room, path.device));
+        if path == *selected {
+            block = block.border_style(Style::new().bold());
+        }
+        let lines: Vec<_> = lines.into_iter().map(Line::from).collect();
+        frame.render_widget(Paragraph::new(lines).block(block), *area);
+    }
+}
+
+/// One-line state shown in the tree.
+fn summary(device: &Device) -> String {
+    match device {
+        Device::Socket(socket) if socket.is_on() => {
+            format!("on {:.1} W", socket.get_current_power())
+        }
+        Device::Socket(_) => String::from("off"),
+        Device::Thermometer(thermometer) => format!("{:.1} °C", thermometer.get_temperature()),
+        Device::Lock(lock) => lock.get_state().as_str().into(),
+        Device::WindowCovering(covering) => format!("{:.0} %", covering.get_position()),
+        _ => String::new(),
+    }
+}
+
+fn fields(device: &Device) -> Vec<String> {
+    match device {
+        Device::Socket(socket) => vec![
+            format!("{}", if socket.is_on() { "on" } else { "off" }),
+            format!(
+                "{:.1} V  {:.2} A, load {:.2} A",
+                socket.get_voltage(),
+                socket.get_current(),
+                socket.get_load_current()
+            ),
+            format!(
+                "{:.1} W, peak {:.1} W",
+                socket.get_current_power(),
+                socket.get_peak_power()
+            ),
+            format!("{:.4} kWh", socket.get_energy()),
+            match socket.get_power_limit() {
+                Some(limit) => format!("limit {limit:.1} W"),
+                None => String::from("no limit"),
+            },
+        ],
+        Device::Thermometer(thermometer) => {
+            vec![format!("{:.1} °C", thermometer.get_temperature())]
+        }
+        Device::Lock(lock) => vec![format!(
+            "{} → {}",
+            lock.get_state().as_str(),
+            lock.get_target().as_str()
+        )],
+        Device::WindowCovering(covering) => vec![
+            format!(
+                "position {:.0} % → {:.0} %",
+                covering.get_position(),
+                covering.get_target_position()
+            ),
+            format!(
+                "tilt {:.0} % → {:.0} %",
+                covering.get_tilt(),
+                covering.get_target_tilt()
+            ),
+        ],
+        _ => vec![],
+    }
+}
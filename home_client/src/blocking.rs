@@ -0,0 +1,201 @@
+//! Synchronous twin of [`crate::HomeClient`] for programs without an async runtime.
+//!
+//! Every client runs the async one on its own single-threaded runtime, so its methods must
+//! not be called from async code.
+
+use crate::connection::{ConnectionState, Reconnect};
+use crate::error::HomeResult;
+use crate::pool::PoolOptions;
+use crate::{
+    AlertInfo, AuditEntry, HistoryPoint, HistoryRange, HomeEvent, MemberResult, QueryAnswer,
+    RuleLogEntry, ScheduleInfo,
+};
+use chrono::NaiveDateTime;
+use smart_home::{
+    metadata::{DeviceMetadata, RoomMetadata},
+    scene::DeviceState,
+    smart_device::Device,
+    stats::Stats,
+    units::{Energy, Units},
+};
+use stp::error::ConnectResult;
+use tokio::net::ToSocketAddrs;
+use tokio::runtime::{Builder, Runtime};
+use tokio::sync::watch;
+
+/// Methods waiting for the namesake async methods of the client.
+macro_rules! blocking {
+    ($(fn $name:ident(&self $(, $arg:ident: $ty:ty)*) -> $ret:ty;)*) => {
+        $(
+            pub fn $name(&self $(, $arg: $ty)*) -> $ret {
+                self.runtime.block_on(self.client.$name($($arg),*))
+            }
+        )*
+    };
+}
+
+pub struct HomeClient {
+    client: crate::HomeClient,
+    runtime: Runtime,
+}
+
+impl HomeClient {
+    pub fn new<Addr>(addr: Addr) -> ConnectResult<Self>
+    where
+        Addr: ToSocketAddrs,
+    {
+        let runtime = runtime()?;
+        let client = runtime.block_on(crate::HomeClient::new(addr))?;
+        Ok(Self { client, runtime })
+    }
+
+    /// Client for requests from several threads, health checks run while requests are made.
+    pub fn pooled<Addr>(addr: Addr, options: PoolOptions) -> ConnectResult<Self>
+    where
+        Addr: ToSocketAddrs,
+    {
+        let runtime = runtime()?;
+        let client = runtime.block_on(crate::HomeClient::pooled(addr, options))?;
+        Ok(Self { client, runtime })
+    }
+
+    pub fn reconnect(mut self, reconnect: Reconnect) -> Self {
+        self.client = self.client.reconnect(reconnect);
+        self
+    }
+
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.client.connection_state()
+    }
+
+    pub fn units(&self) -> Units {
+        self.client.units()
+    }
+
+    pub fn set_units(&mut self, units: Units) -> HomeResult<()> {
+        self.runtime.block_on(self.client.set_units(units))
+    }
+
+    pub fn subscribe(self) -> HomeResult<Subscription> {
+        let subscription = self.runtime.block_on(self.client.subscribe())?;
+        Ok(Subscription {
+            subscription,
+            runtime: self.runtime,
+        })
+    }
+
+    blocking! {
+        fn get_room_list(&self) -> HomeResult<Vec<String>>;
+        fn get_device_list(&self, room_name: &str) -> HomeResult<Vec<String>>;
+        fn get_device(&self, room_name: &str, device_name: &str) -> HomeResult<Device>;
+        fn get_device_metadata(&self, room_name: &str, device_name: &str) -> HomeResult<DeviceMetadata>;
+        fn set_device_metadata(&self, room_name: &str, device_name: &str, metadata: &DeviceMetadata) -> HomeResult<()>;
+        fn get_room_metadata(&self, room_name: &str) -> HomeResult<RoomMetadata>;
+        fn set_room_metadata(&self, room_name: &str, metadata: &RoomMetadata) -> HomeResult<()>;
+        fn find_tagged(&self, tag: &str) -> HomeResult<Vec<(String, String)>>;
+        fn get_device_at(&self, path: &str) -> HomeResult<Device>;
+        fn get_zone_list(&self, path: &str) -> HomeResult<Vec<String>>;
+        fn get_zone_rooms(&self, path: &str) -> HomeResult<Vec<String>>;
+        fn get_zone_devices(&self, path: &str) -> HomeResult<Vec<String>>;
+        fn get_zone_energy(&self, path: &str) -> HomeResult<Energy>;
+        fn add_zone(&self, path: &str) -> HomeResult<()>;
+        fn remove_zone(&self, path: &str) -> HomeResult<()>;
+        fn place_room(&self, room_name: &str, zone_path: &str) -> HomeResult<()>;
+        fn get_room_zone(&self, room_name: &str) -> HomeResult<String>;
+        fn add_room(&self, room_name: &str) -> HomeResult<()>;
+        fn remove_room(&self, room_name: &str) -> HomeResult<()>;
+        fn add_device(&self, room_name: &str, device_name: &str, device: Device) -> HomeResult<()>;
+        fn remove_device(&self, room_name: &str, device_name: &str) -> HomeResult<()>;
+        fn get_group_list(&self) -> HomeResult<Vec<String>>;
+        fn add_group(&self, group_name: &str) -> HomeResult<()>;
+        fn remove_group(&self, group_name: &str) -> HomeResult<()>;
+        fn get_group_members(&self, group_name: &str) -> HomeResult<Vec<(String, String)>>;
+        fn add_to_group(&self, group_name: &str, room_name: &str, device_name: &str) -> HomeResult<()>;
+        fn remove_from_group(&self, group_name: &str, room_name: &str, device_name: &str) -> HomeResult<()>;
+        fn switch_group(&self, group_name: &str, on: bool) -> HomeResult<Vec<MemberResult<()>>>;
+        fn read_group(&self, group_name: &str) -> HomeResult<Vec<MemberResult<Device>>>;
+        fn query(&self, query: &str) -> HomeResult<QueryAnswer>;
+        fn get_stats(&self) -> HomeResult<Stats>;
+        fn get_room_stats(&self, room_name: &str) -> HomeResult<Stats>;
+        fn get_zone_stats(&self, zone_path: &str) -> HomeResult<Stats>;
+        fn get_total_energy(&self) -> HomeResult<Energy>;
+        fn get_room_energy(&self, room_name: &str) -> HomeResult<Energy>;
+        fn get_scene_list(&self) -> HomeResult<Vec<String>>;
+        fn get_scene(&self, scene_name: &str) -> HomeResult<Vec<(String, String, DeviceState)>>;
+        fn capture_scene(&self, scene_name: &str, devices: &[(&str, &str)]) -> HomeResult<()>;
+        fn apply_scene(&self, scene_name: &str) -> HomeResult<()>;
+        fn remove_scene(&self, scene_name: &str) -> HomeResult<()>;
+        fn get_rule_list(&self) -> HomeResult<Vec<(String, bool)>>;
+        fn get_rule(&self, rule_name: &str) -> HomeResult<Vec<String>>;
+        fn add_rule(&self, definition: &str) -> HomeResult<()>;
+        fn remove_rule(&self, rule_name: &str) -> HomeResult<()>;
+        fn enable_rule(&self, rule_name: &str, enabled: bool) -> HomeResult<()>;
+        fn get_rule_log(&self) -> HomeResult<Vec<RuleLogEntry>>;
+        fn get_schedule_list(&self) -> HomeResult<Vec<ScheduleInfo>>;
+        fn add_schedule(&self, name: &str, when: &str, action: &str) -> HomeResult<()>;
+        fn remove_schedule(&self, name: &str) -> HomeResult<()>;
+        fn history(&self, room_name: &str, device_name: &str, range: HistoryRange) -> HomeResult<Vec<HistoryPoint>>;
+        fn get_alert_list(&self) -> HomeResult<Vec<AlertInfo>>;
+        fn add_alert(&self, name: &str, rule: &str) -> HomeResult<()>;
+        fn remove_alert(&self, name: &str) -> HomeResult<()>;
+        fn acknowledge_alert(&self, name: &str) -> HomeResult<()>;
+        fn get_audit_log(&self, from: Option<NaiveDateTime>, to: Option<NaiveDateTime>, target: Option<&str>) -> HomeResult<Vec<AuditEntry>>;
+        fn update_device(&self, room_name: &str, device_name: &str, device: Device) -> HomeResult<String>;
+    }
+}
+
+/// See [`crate::Subscription`].
+pub struct Subscription {
+    subscription: crate::Subscription,
+    runtime: Runtime,
+}
+
+impl Subscription {
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.subscription.connection_state()
+    }
+
+    /// Waits for the next event of the server.
+    pub fn next_event(&self) -> HomeResult<HomeEvent> {
+        self.runtime.block_on(self.subscription.next_event())
+    }
+}
+
+fn runtime() -> std::io::Result<Runtime> {
+    Builder::new_current_thread().enable_all().build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stp_client() {
+        let c = stp::blocking::StpClient::connect("127.0.0.1:4083").unwrap();
+        let response = c.send_request("room list").unwrap();
+        assert!(response.starts_with("Ok///"));
+        assert!(response.split("///").any(|room| room == "R"));
+    }
+
+    #[test]
+    fn home_client() {
+        let c = HomeClient::new("127.0.0.1:4083").unwrap();
+        assert!(c.get_room_list().unwrap().contains(&"R".into()));
+        c.add_room("blocking room").unwrap();
+        c.add_device("blocking room", "S", Device::new_socket())
+            .unwrap();
+        let Device::Socket(mut socket) = c.get_device("blocking room", "S").unwrap() else {
+            panic!("Unexpected device comes from server.")
+        };
+        assert!(!socket.is_on());
+        socket.switch(true);
+        c.update_device("blocking room", "S", Device::Socket(socket))
+            .unwrap();
+        assert!(matches!(
+            c.get_device("blocking room", "S").unwrap(),
+            Device::Socket(socket) if socket.is_on()
+        ));
+        c.remove_room("blocking room").unwrap();
+        assert!(c.get_device("blocking room", "S").is_err());
+    }
+}
@@ -0,0 +1,329 @@
+//! Connection to the server that is re-established when the server goes away.
+
+use crate::error::{HomeError, HomeResult};
+use crate::{list_from_stp_response, SEPARATOR};
+use rand::Rng;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use stp::client::StpClient;
+use stp::error::ConnectResult;
+use tokio::net::ToSocketAddrs;
+use tokio::sync::{watch, Mutex};
+
+/// Cheap request answering whether the connection still works.
+const PROBE: &str = "room list";
+
+/// State of the connection, see [`crate::HomeClient::connection_state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    /// Waiting before the attempt to connect again, counting from 1.
+    Reconnecting {
+        attempt: u32,
+    },
+    /// The connection is lost, the next request tries to connect again.
+    Disconnected,
+}
+
+/// Exponential backoff with jitter between attempts to reconnect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reconnect {
+    initial: Duration,
+    max: Duration,
+    attempts: Option<u32>,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+            attempts: Some(10),
+        }
+    }
+}
+
+impl Reconnect {
+    /// Delay after the first failed attempt, doubled after each next one.
+    pub fn initial(mut self, initial: Duration) -> Self {
+        self.initial = initial;
+        self
+    }
+
+    pub fn max(mut self, max: Duration) -> Self {
+        self.max = max;
+        self
+    }
+
+    /// Attempts before giving up, `None` to try forever and `Some(0)` to never reconnect.
+    pub fn attempts(mut self, attempts: Option<u32>) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    /// A random delay between the half and the whole of the backoff of the attempt.
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial
+            .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max);
+        backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+struct Link {
+    stp: Option<StpClient>,
+    /// Requests replayed on every new connection, like the units or the subscription.
+    setup: Vec<String>,
+}
+
+pub(crate) struct Connection {
+    addrs: Vec<SocketAddr>,
+    link: Mutex<Link>,
+    reconnect: Reconnect,
+    /// Shared by the connections of a pool.
+    state: Arc<watch::Sender<ConnectionState>>,
+}
+
+impl Connection {
+    pub(crate) async fn open<Addr>(addr: Addr) -> ConnectResult<Self>
+    where
+        Addr: ToSocketAddrs,
+    {
+        let addrs: Vec<_> = tokio::net::lookup_host(addr).await?.collect();
+        let state = Arc::new(watch::Sender::new(ConnectionState::Connected));
+        Self::open_on(addrs, state).await
+    }
+
+    pub(crate) async fn open_on(
+        addrs: Vec<SocketAddr>,
+        state: Arc<watch::Sender<ConnectionState>>,
+    ) -> ConnectResult<Self> {
+        let stp = StpClient::connect(&addrs[..]).await?;
+        let mut connection = Self::lazy(addrs, state, vec![]);
+        connection.link.get_mut().stp = Some(stp);
+        Ok(connection)
+    }
+
+    /// Connection that connects and sends the setup on the first request.
+    pub(crate) fn lazy(
+        addrs: Vec<SocketAddr>,
+        state: Arc<watch::Sender<ConnectionState>>,
+        setup: Vec<String>,
+    ) -> Self {
+        Self {
+            addrs,
+            link: Mutex::new(Link { stp: None, setup }),
+            reconnect: Reconnect::default(),
+            state,
+        }
+    }
+
+    pub(crate) fn set_reconnect(&mut self, reconnect: Reconnect) {
+        self.reconnect = reconnect;
+    }
+
+    pub(crate) fn state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
+    /// Sends the request once, it may have reached the server if the connection breaks.
+    pub(crate) async fn send_request(&self, request: impl AsRef<str>) -> HomeResult<String> {
+        self.send(request.as_ref(), false).await
+    }
+
+    /// Sends the request that doesn't change anything, again after reconnecting if needed.
+    pub(crate) async fn send_query(&self, request: impl AsRef<str>) -> HomeResult<String> {
+        self.send(request.as_ref(), true).await
+    }
+
+    /// Sends the request now and on every new connection, replacing the earlier one
+    /// of the same command.
+    pub(crate) async fn send_setup(&self, request: String) -> HomeResult<String> {
+        let response = self.send(&request, false).await?;
+        remember(&mut self.link.lock().await.setup, request);
+        Ok(response)
+    }
+
+    /// Sends the probe without reconnecting, for health checks of idle connections.
+    pub(crate) async fn probe(&self) -> bool {
+        let mut link = self.link.lock().await;
+        let Some(stp) = &link.stp else {
+            return false;
+        };
+        match stp.send_request(PROBE).await {
+            Ok(response) => list_from_stp_response(&mut response.split(SEPARATOR)).is_ok(),
+            Err(_) => {
+                self.lost(&mut link);
+                false
+            }
+        }
+    }
+
+    /// Receives a message the server sends on its own, reconnecting as long as allowed.
+    pub(crate) async fn recv_message(&self) -> HomeResult<String> {
+        let mut link = self.link.lock().await;
+        loop {
+            let stp = self.connected(&mut link).await?;
+            match stp.recv_message().await {
+                Ok(message) => return Ok(message),
+                Err(_) => self.lost(&mut link),
+            }
+        }
+    }
+
+    async fn send(&self, request: &str, retry: bool) -> HomeResult<String> {
+        let mut link = self.link.lock().await;
+        let mut retried = false;
+        loop {
+            let stp = self.connected(&mut link).await?;
+            match stp.send_request(request).await {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    self.lost(&mut link);
+                    if !retry || retried {
+                        return Err(e.into());
+                    }
+                    retried = true;
+                }
+            }
+        }
+    }
+
+    fn lost(&self, link: &mut Link) {
+        link.stp = None;
+        self.state.send_replace(ConnectionState::Disconnected);
+    }
+
+    async fn connected<'a>(&self, link: &'a mut Link) -> HomeResult<&'a StpClient> {
+        let stp = match link.stp.take() {
+            Some(stp) => stp,
+            None => self.connect_again(&link.setup).await?,
+        };
+        Ok(link.stp.insert(stp))
+    }
+
+    async fn connect_again(&self, setup: &[String]) -> HomeResult<StpClient> {
+        let mut attempt = 0;
+        loop {
+            if self.reconnect.attempts.is_some_and(|max| attempt >= max) {
+                self.state.send_replace(ConnectionState::Disconnected);
+                return Err(HomeError::Disconnected);
+            }
+            if attempt > 0 {
+                self.state
+                    .send_replace(ConnectionState::Reconnecting { attempt });
+                tokio::time::sleep(self.reconnect.delay(attempt)).await;
+            }
+            attempt += 1;
+            if let Ok(stp) = self.try_connect(setup).await {
+                self.state.send_replace(ConnectionState::Connected);
+                return Ok(stp);
+            }
+        }
+    }
+
+    async fn try_connect(&self, setup: &[String]) -> HomeResult<StpClient> {
+        let stp = StpClient::connect(&self.addrs[..])
+            .await
+            .map_err(|_| HomeError::Disconnected)?;
+        for request in setup {
+            let response = stp.send_request(request).await?;
+            list_from_stp_response(&mut response.split(SEPARATOR))?;
+        }
+        Ok(stp)
+    }
+}
+
+/// Adds the setup request, replacing the earlier one of the same command.
+pub(crate) fn remember(setup: &mut Vec<String>, request: String) {
+    let command = request.split(SEPARATOR).next();
+    setup.retain(|earlier| earlier.split(SEPARATOR).next() != command);
+    setup.push(request);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stp::server::StpServer;
+
+    #[test]
+    fn test_delay() {
+        let reconnect = Reconnect::default()
+            .initial(Duration::from_millis(100))
+            .max(Duration::from_secs(1));
+        for _ in 0..20 {
+            let first = reconnect.delay(1);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+            let third = reconnect.delay(3);
+            assert!(third >= Duration::from_millis(200) && third <= Duration::from_millis(400));
+            assert!(reconnect.delay(30) <= Duration::from_secs(1));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconnect() {
+        let server = StpServer::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let serve = tokio::spawn(async move {
+            // Answers the first request and restarts on the second one.
+            let peer = server.accept().await.unwrap();
+            assert_eq!("room list", peer.recv_request().await.unwrap());
+            peer.send_response("Ok///R").await.unwrap();
+            assert_eq!("room list", peer.recv_request().await.unwrap());
+            drop(peer);
+
+            let peer = server.accept().await.unwrap();
+            assert_eq!("units///f///w///kwh", peer.recv_request().await.unwrap());
+            peer.send_response("Ok").await.unwrap();
+            assert_eq!("room list", peer.recv_request().await.unwrap());
+            peer.send_response("Ok///R///Hall").await.unwrap();
+            assert_eq!("add room///Hall", peer.recv_request().await.unwrap());
+        });
+        let mut connection = Connection::open(addr).await.unwrap();
+        connection.set_reconnect(Reconnect::default().initial(Duration::from_millis(10)));
+        let mut state = connection.state();
+
+        assert_eq!("Ok///R", connection.send_query("room list").await.unwrap());
+        connection
+            .link
+            .lock()
+            .await
+            .setup
+            .push(String::from("units///f///w///kwh"));
+        // Sent again on the new connection after the setup.
+        assert_eq!(
+            "Ok///R///Hall",
+            connection.send_query("room list").await.unwrap()
+        );
+        assert!(state.has_changed().unwrap());
+        assert_eq!(ConnectionState::Connected, *state.borrow_and_update());
+
+        // A changing request isn't sent twice.
+        assert!(connection.send_request("add room///Hall").await.is_err());
+        assert_eq!(ConnectionState::Disconnected, *state.borrow_and_update());
+        serve.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_give_up() {
+        let server = StpServer::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let serve = tokio::spawn(async move {
+            server.accept().await.unwrap();
+        });
+        let mut connection = Connection::open(addr).await.unwrap();
+        connection.set_reconnect(
+            Reconnect::default()
+                .initial(Duration::from_millis(1))
+                .attempts(Some(3)),
+        );
+        serve.await.unwrap();
+        assert!(matches!(
+            connection.send_query("room list").await,
+            Err(HomeError::Disconnected)
+        ));
+        assert_eq!(ConnectionState::Disconnected, *connection.state().borrow());
+    }
+}
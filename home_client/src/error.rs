@@ -9,6 +9,10 @@ pub enum HomeError {
     ResponseErr(String),
     #[error("Bad response.")]
     BadResponse,
+    #[error("Can't reconnect to the server.")]
+    Disconnected,
+    #[error("No connection of the pool is free.")]
+    PoolTimeout,
 }
 
 pub type HomeResult<T> = Result<T, HomeError>;
@@ -1,39 +1,256 @@
 #![allow(unused, dead_code)]
+use chrono::NaiveDateTime;
+use connection::{Connection, ConnectionState, Reconnect};
 use error::{HomeError, HomeResult};
-use smart_home::smart_device::{Device, DeviceInfo, Socket, Thermometer};
-use std::{fmt::Write, vec};
-use stp::{
-    client::{RequestError, RequestResult, StpClient},
-    error::ConnectResult,
+use pool::{Pool, PoolOptions};
+use smart_home::{
+    metadata::{DeviceMetadata, RoomMetadata},
+    scene::DeviceState,
+    smart_device::{Device, DeviceInfo, Lock, LockState, Socket, Thermometer, WindowCovering},
+    stats::Stats,
+    units::{Energy, EnergyUnit, Power, PowerUnit, Temperature, TemperatureUnit, Units},
 };
-use tokio::net::ToSocketAddrs;
+use std::{fmt::Write, sync::Arc, time::Duration, vec};
+use stp::error::ConnectResult;
+use tokio::{net::ToSocketAddrs, sync::watch};
+pub mod blocking;
+pub mod connection;
 pub mod error;
+pub mod pool;
 
 const OK_RESPONSE: &str = "Ok";
 const ERR_RESPONSE: &str = "Err";
 const SEPARATOR: &str = "///";
+const EVENT_MESSAGE: &str = "Event";
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 
-pub struct HomeClient(StpClient);
+/// Outcome of a bulk operation for a single member of a group.
+#[derive(Debug)]
+pub struct MemberResult<T> {
+    pub room: String,
+    pub device: String,
+    pub result: HomeResult<T>,
+}
+
+/// Answer to a query, see `smart_home::query` for the syntax.
+#[derive(Debug, PartialEq)]
+pub enum QueryAnswer {
+    /// `(room, device, state)` of every selected device.
+    Devices(Vec<(String, String, Device)>),
+    Count(usize),
+    Value(Option<f64>),
+}
+
+/// Action executed by an automation rule of the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleLogEntry {
+    /// Local time of the server, `YYYY-MM-DD HH:MM:SS`.
+    pub time: String,
+    pub rule: String,
+    pub message: String,
+}
+
+/// Schedule of the server, `when` and `action` are in the server's schedule syntax.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleInfo {
+    pub name: String,
+    pub when: String,
+    pub action: String,
+    /// Local time of the server, `YYYY-MM-DD HH:MM`, the schedule runs next.
+    pub next: Option<String>,
+}
+
+/// Time range of device history, local time of the server. Bounds are optional,
+/// with `step` points are merged per interval.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HistoryRange {
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub step: Option<Duration>,
+}
+
+/// Readings of the device in the interval starting at `time`, in the negotiated units.
+/// Temperature for a thermometer, power for a socket, position for a window covering,
+/// 1 for a locked lock and 0 otherwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryPoint {
+    pub time: NaiveDateTime,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub count: u32,
+}
+
+/// Mutating request received by the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Local time of the server.
+    pub time: NaiveDateTime,
+    pub peer: String,
+    pub user: Option<String>,
+    pub command: String,
+    /// Room, device or other names the command is applied to, joined by `/`.
+    pub target: String,
+    /// State of the target before and after the command, if it has one.
+    pub old: Option<String>,
+    pub new: Option<String>,
+    /// `Ok` or the error message.
+    pub outcome: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertState {
+    Raised,
+    Acknowledged,
+    Cleared,
+}
+
+/// Alert of the server, `rule` is in the server's alert syntax, e.g. `R/T temperature > 30`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertInfo {
+    pub name: String,
+    pub rule: String,
+    pub state: AlertState,
+    /// Local time of the server, `YYYY-MM-DD HH:MM:SS`, the state has changed.
+    pub since: Option<String>,
+    /// Latest reading in the negotiated units.
+    pub value: Option<f64>,
+}
+
+/// Event pushed by the server to a subscribed client.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HomeEvent {
+    Alert {
+        name: String,
+        room: String,
+        device: String,
+        state: AlertState,
+        value: Option<f64>,
+    },
+    Notification {
+        source: String,
+        message: String,
+    },
+    /// The state of the device changed.
+    Device {
+        room: String,
+        device: String,
+        state: Device,
+    },
+}
+
+/// Where the requests of a client go.
+enum Transport {
+    Single(Connection),
+    Pooled(Arc<Pool>),
+}
+
+impl Transport {
+    async fn send_request(&self, request: impl AsRef<str>) -> HomeResult<String> {
+        match self {
+            Transport::Single(connection) => connection.send_request(request).await,
+            Transport::Pooled(pool) => pool.send_request(request).await,
+        }
+    }
+
+    async fn send_query(&self, request: impl AsRef<str>) -> HomeResult<String> {
+        match self {
+            Transport::Single(connection) => connection.send_query(request).await,
+            Transport::Pooled(pool) => pool.send_query(request).await,
+        }
+    }
+
+    async fn send_setup(&self, request: String) -> HomeResult<String> {
+        match self {
+            Transport::Single(connection) => connection.send_setup(request).await,
+            Transport::Pooled(pool) => pool.send_setup(request).await,
+        }
+    }
+}
+
+/// Connection receiving events of the server, see [`HomeClient::subscribe`].
+pub struct Subscription {
+    connection: Connection,
+    units: Units,
+}
+
+/// Client that reconnects when the server restarts, see [`Reconnect`].
+pub struct HomeClient {
+    transport: Transport,
+    /// Units negotiated with the server.
+    units: Units,
+}
 
 impl HomeClient {
     pub async fn new<Addr>(addr: Addr) -> ConnectResult<Self>
     where
         Addr: ToSocketAddrs,
     {
-        let stp_client = StpClient::connect(addr).await?;
-        Ok(Self(stp_client))
+        Ok(Self {
+            transport: Transport::Single(Connection::open(addr).await?),
+            units: Units::default(),
+        })
+    }
+
+    /// Client whose requests from concurrent tasks go in parallel over several connections.
+    pub async fn pooled<Addr>(addr: Addr, options: PoolOptions) -> ConnectResult<Self>
+    where
+        Addr: ToSocketAddrs,
+    {
+        Ok(Self {
+            transport: Transport::Pooled(Pool::open(addr, options).await?),
+            units: Units::default(),
+        })
+    }
+
+    /// Replaces the default backoff between attempts to reconnect.
+    pub fn reconnect(mut self, reconnect: Reconnect) -> Self {
+        match &mut self.transport {
+            Transport::Single(connection) => connection.set_reconnect(reconnect),
+            Transport::Pooled(pool) => pool.set_reconnect(reconnect),
+        }
+        self
+    }
+
+    /// Follows the connection, for example to show that the server is offline.
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        match &self.transport {
+            Transport::Single(connection) => connection.state(),
+            Transport::Pooled(pool) => pool.state(),
+        }
+    }
+
+    pub fn units(&self) -> Units {
+        self.units
+    }
+
+    /// Asks the server to exchange values in the given units.
+    pub async fn set_units(&mut self, units: Units) -> HomeResult<()> {
+        let response = self
+            .transport
+            .send_setup(format!(
+                "units{SEPARATOR}{}{SEPARATOR}{}{SEPARATOR}{}",
+                units.temperature.as_str(),
+                units.power.as_str(),
+                units.energy.as_str()
+            ))
+            .await?;
+        let mut response = response.split(SEPARATOR);
+        list_from_stp_response(&mut response)?;
+        self.units = units;
+        Ok(())
     }
 
     pub async fn get_room_list(&self) -> HomeResult<Vec<String>> {
-        let response = self.0.send_request("room list").await?;
+        let response = self.transport.send_query("room list").await?;
         let mut response = response.split(SEPARATOR);
         list_from_stp_response(&mut response)
     }
 
     pub async fn get_device_list(&self, room_name: &str) -> HomeResult<Vec<String>> {
         let response = self
-            .0
-            .send_request(format!("device list{SEPARATOR}{room_name}"))
+            .transport
+            .send_query(format!("device list{SEPARATOR}{room_name}"))
             .await?;
         let mut response = response.split(SEPARATOR);
         list_from_stp_response(&mut response)
@@ -41,13 +258,667 @@ impl HomeClient {
 
     pub async fn get_device(&self, room_name: &str, device_name: &str) -> HomeResult<Device> {
         let response = self
-            .0
-            .send_request(format!(
+            .transport
+            .send_query(format!(
                 "get device{SEPARATOR}{room_name}{SEPARATOR}{device_name}"
             ))
             .await?;
         let mut response = response.split({ SEPARATOR });
-        device_from_stp_response(&mut response)
+        device_from_stp_response(&mut response, &self.units)
+    }
+
+    pub async fn get_device_metadata(
+        &self,
+        room_name: &str,
+        device_name: &str,
+    ) -> HomeResult<DeviceMetadata> {
+        let response = self
+            .transport
+            .send_query(format!(
+                "get metadata{SEPARATOR}{room_name}{SEPARATOR}{device_name}"
+            ))
+            .await?;
+        let mut response = response.split(SEPARATOR);
+        let fields = list_from_stp_response(&mut response)?;
+        Ok(DeviceMetadata::from_fields(
+            &mut fields.iter().map(String::as_str),
+        ))
+    }
+
+    pub async fn set_device_metadata(
+        &self,
+        room_name: &str,
+        device_name: &str,
+        metadata: &DeviceMetadata,
+    ) -> HomeResult<()> {
+        let fields = metadata.fields().join(SEPARATOR);
+        let response = self
+            .transport
+            .send_request(format!(
+                "set metadata{SEPARATOR}{room_name}{SEPARATOR}{device_name}{SEPARATOR}{fields}"
+            ))
+            .await?;
+        let mut response = response.split(SEPARATOR);
+        list_from_stp_response(&mut response)?;
+        Ok(())
+    }
+
+    pub async fn get_room_metadata(&self, room_name: &str) -> HomeResult<RoomMetadata> {
+        let response = self
+            .transport
+            .send_query(format!("get room metadata{SEPARATOR}{room_name}"))
+            .await?;
+        let mut response = response.split(SEPARATOR);
+        let fields = list_from_stp_response(&mut response)?;
+        RoomMetadata::from_fields(&mut fields.iter().map(String::as_str))
+            .map_err(|_| HomeError::BadResponse)
+    }
+
+    pub async fn set_room_metadata(
+        &self,
+        room_name: &str,
+        metadata: &RoomMetadata,
+    ) -> HomeResult<()> {
+        let fields = metadata.fields().join(SEPARATOR);
+        let response = self
+            .transport
+            .send_request(format!(
+                "set room metadata{SEPARATOR}{room_name}{SEPARATOR}{fields}"
+            ))
+            .await?;
+        let mut response = response.split(SEPARATOR);
+        list_from_stp_response(&mut response)?;
+        Ok(())
+    }
+
+    /// Paths `(room, device)` of devices labelled with the tag.
+    pub async fn find_tagged(&self, tag: &str) -> HomeResult<Vec<(String, String)>> {
+        let response = self
+            .transport
+            .send_query(format!("find tag{SEPARATOR}{tag}"))
+            .await?;
+        let mut response = response.split(SEPARATOR);
+        let items = list_from_stp_response(&mut response)?;
+        Ok(items
+            .chunks_exact(2)
+            .map(|path| (path[0].clone(), path[1].clone()))
+            .collect())
+    }
+
+    /// Finds the device by its full path like `floor2/kitchen/socket1`.
+    pub async fn get_device_at(&self, path: &str) -> HomeResult<Device> {
+        let response = self
+            .transport
+            .send_query(format!("get device at{SEPARATOR}{path}"))
+            .await?;
+        let mut response = response.split(SEPARATOR);
+        device_from_stp_response(&mut response, &self.units)
+    }
+
+    /// Zones nested directly in the zone, an empty path means the whole home.
+    pub async fn get_zone_list(&self, path: &str) -> HomeResult<Vec<String>> {
+        self.query_list(format!("zone list{SEPARATOR}{path}")).await
+    }
+
+    /// Rooms placed directly in the zone.
+    pub async fn get_zone_rooms(&self, path: &str) -> HomeResult<Vec<String>> {
+        self.query_list(format!("zone rooms{SEPARATOR}{path}"))
+            .await
+    }
+
+    /// Full paths of all devices in the zone and its nested zones.
+    pub async fn get_zone_devices(&self, path: &str) -> HomeResult<Vec<String>> {
+        self.query_list(format!("zone devices{SEPARATOR}{path}"))
+            .await
+    }
+
+    pub async fn get_zone_energy(&self, path: &str) -> HomeResult<Energy> {
+        let response = self
+            .transport
+            .send_query(format!("zone energy{SEPARATOR}{path}"))
+            .await?;
+        let mut response = response.split(SEPARATOR);
+        let energy = value_from_stp_response(&mut response)?;
+        Ok(Energy::new(energy, self.units.energy))
+    }
+
+    pub async fn add_zone(&self, path: &str) -> HomeResult<()> {
+        self.request_list(format!("add zone{SEPARATOR}{path}"))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_zone(&self, path: &str) -> HomeResult<()> {
+        self.request_list(format!("remove zone{SEPARATOR}{path}"))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn place_room(&self, room_name: &str, zone_path: &str) -> HomeResult<()> {
+        self.request_list(format!(
+            "place room{SEPARATOR}{room_name}{SEPARATOR}{zone_path}"
+        ))
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_room_zone(&self, room_name: &str) -> HomeResult<String> {
+        let mut response = self
+            .query_list(format!("room zone{SEPARATOR}{room_name}"))
+            .await?;
+        response.pop().ok_or(HomeError::BadResponse)
+    }
+
+    pub async fn add_room(&self, room_name: &str) -> HomeResult<()> {
+        self.request_list(format!("add room{SEPARATOR}{room_name}"))
+            .await?;
+        Ok(())
+    }
+
+    /// Removes the room with its devices.
+    pub async fn remove_room(&self, room_name: &str) -> HomeResult<()> {
+        self.request_list(format!("remove room{SEPARATOR}{room_name}"))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn add_device(
+        &self,
+        room_name: &str,
+        device_name: &str,
+        device: Device,
+    ) -> HomeResult<()> {
+        let info = device.device_info_in(&self.units).join(SEPARATOR);
+        self.request_list(format!(
+            "add device{SEPARATOR}{room_name}{SEPARATOR}{device_name}{SEPARATOR}{info}"
+        ))
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove_device(&self, room_name: &str, device_name: &str) -> HomeResult<()> {
+        self.request_list(format!(
+            "remove device{SEPARATOR}{room_name}{SEPARATOR}{device_name}"
+        ))
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_group_list(&self) -> HomeResult<Vec<String>> {
+        self.query_list(String::from("group list")).await
+    }
+
+    pub async fn add_group(&self, group_name: &str) -> HomeResult<()> {
+        self.request_list(format!("add group{SEPARATOR}{group_name}"))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_group(&self, group_name: &str) -> HomeResult<()> {
+        self.request_list(format!("remove group{SEPARATOR}{group_name}"))
+            .await?;
+        Ok(())
+    }
+
+    /// Paths `(room, device)` of the group members.
+    pub async fn get_group_members(&self, group_name: &str) -> HomeResult<Vec<(String, String)>> {
+        let items = self
+            .query_list(format!("group members{SEPARATOR}{group_name}"))
+            .await?;
+        Ok(items
+            .chunks_exact(2)
+            .map(|path| (path[0].clone(), path[1].clone()))
+            .collect())
+    }
+
+    pub async fn add_to_group(
+        &self,
+        group_name: &str,
+        room_name: &str,
+        device_name: &str,
+    ) -> HomeResult<()> {
+        self.request_list(format!(
+            "group add{SEPARATOR}{group_name}{SEPARATOR}{room_name}{SEPARATOR}{device_name}"
+        ))
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove_from_group(
+        &self,
+        group_name: &str,
+        room_name: &str,
+        device_name: &str,
+    ) -> HomeResult<()> {
+        self.request_list(format!(
+            "group remove{SEPARATOR}{group_name}{SEPARATOR}{room_name}{SEPARATOR}{device_name}"
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Switches every socket of the group in one round trip.
+    pub async fn switch_group(
+        &self,
+        group_name: &str,
+        on: bool,
+    ) -> HomeResult<Vec<MemberResult<()>>> {
+        let on_off = if on { "on" } else { "off" };
+        let items = self
+            .request_list(format!(
+                "switch group{SEPARATOR}{group_name}{SEPARATOR}{on_off}"
+            ))
+            .await?;
+        let mut items = items.iter().map(String::as_str);
+        let mut result = vec![];
+        while let Some(room) = items.next() {
+            let device = items.next().ok_or(HomeError::BadResponse)?;
+            let outcome = match items.next() {
+                Some(OK_RESPONSE) => Ok(()),
+                Some(ERR_RESPONSE) => Err(HomeError::ResponseErr(
+                    items.next().unwrap_or_default().into(),
+                )),
+                _ => return Err(HomeError::BadResponse),
+            };
+            result.push(MemberResult {
+                room: room.into(),
+                device: device.into(),
+                result: outcome,
+            });
+        }
+        Ok(result)
+    }
+
+    /// Reads all members of the group in one round trip.
+    pub async fn read_group(&self, group_name: &str) -> HomeResult<Vec<MemberResult<Device>>> {
+        let items = self
+            .query_list(format!("read group{SEPARATOR}{group_name}"))
+            .await?;
+        let mut items = items.iter().map(String::as_str);
+        let mut result = vec![];
+        while let Some(room) = items.next() {
+            let device = items.next().ok_or(HomeError::BadResponse)?;
+            let outcome = match items.next() {
+                Some(OK_RESPONSE) => {
+                    let count: usize = items
+                        .next()
+                        .and_then(|count| count.parse().ok())
+                        .ok_or(HomeError::BadResponse)?;
+                    let mut info = items.by_ref().take(count);
+                    Ok(device_from_ok_response(&mut info, &self.units))
+                }
+                Some(ERR_RESPONSE) => Err(HomeError::ResponseErr(
+                    items.next().unwrap_or_default().into(),
+                )),
+                _ => return Err(HomeError::BadResponse),
+            };
+            result.push(MemberResult {
+                room: room.into(),
+                device: device.into(),
+                result: outcome,
+            });
+        }
+        Ok(result)
+    }
+
+    /// Selects devices like `kind = socket and state = on and power > 1000`
+    /// or aggregates them like `avg temperature where zone = floor1`.
+    pub async fn query(&self, query: &str) -> HomeResult<QueryAnswer> {
+        let items = self.query_list(format!("query{SEPARATOR}{query}")).await?;
+        let mut items = items.iter().map(String::as_str);
+        match items.next() {
+            Some("devices") => {
+                let mut devices = vec![];
+                while let Some(room) = items.next() {
+                    let device_name = items.next().ok_or(HomeError::BadResponse)?;
+                    let count: usize = items
+                        .next()
+                        .and_then(|count| count.parse().ok())
+                        .ok_or(HomeError::BadResponse)?;
+                    let device =
+                        device_from_ok_response(&mut items.by_ref().take(count), &self.units);
+                    devices.push((room.into(), device_name.into(), device));
+                }
+                Ok(QueryAnswer::Devices(devices))
+            }
+            Some("count") => items
+                .next()
+                .and_then(|count| count.parse().ok())
+                .map(QueryAnswer::Count)
+                .ok_or(HomeError::BadResponse),
+            Some("value") => match items.next() {
+                Some("none") => Ok(QueryAnswer::Value(None)),
+                Some(value) => value
+                    .parse()
+                    .map(|value| QueryAnswer::Value(Some(value)))
+                    .map_err(|_| HomeError::BadResponse),
+                None => Err(HomeError::BadResponse),
+            },
+            _ => Err(HomeError::BadResponse),
+        }
+    }
+
+    /// Aggregated readings of the whole home in one round trip.
+    pub async fn get_stats(&self) -> HomeResult<Stats> {
+        let fields = self.query_list(String::from("stats")).await?;
+        Ok(Stats::from_fields(
+            &mut fields.iter().map(String::as_str),
+            &self.units,
+        ))
+    }
+
+    pub async fn get_room_stats(&self, room_name: &str) -> HomeResult<Stats> {
+        let fields = self
+            .query_list(format!("stats{SEPARATOR}{room_name}"))
+            .await?;
+        Ok(Stats::from_fields(
+            &mut fields.iter().map(String::as_str),
+            &self.units,
+        ))
+    }
+
+    pub async fn get_zone_stats(&self, zone_path: &str) -> HomeResult<Stats> {
+        let fields = self
+            .query_list(format!("zone stats{SEPARATOR}{zone_path}"))
+            .await?;
+        Ok(Stats::from_fields(
+            &mut fields.iter().map(String::as_str),
+            &self.units,
+        ))
+    }
+
+    /// Energy consumed by all sockets in the home.
+    pub async fn get_total_energy(&self) -> HomeResult<Energy> {
+        let response = self.transport.send_query("energy").await?;
+        let mut response = response.split(SEPARATOR);
+        let energy = value_from_stp_response(&mut response)?;
+        Ok(Energy::new(energy, self.units.energy))
+    }
+
+    /// Energy consumed by sockets in the room.
+    pub async fn get_room_energy(&self, room_name: &str) -> HomeResult<Energy> {
+        let response = self
+            .transport
+            .send_query(format!("energy{SEPARATOR}{room_name}"))
+            .await?;
+        let mut response = response.split(SEPARATOR);
+        let energy = value_from_stp_response(&mut response)?;
+        Ok(Energy::new(energy, self.units.energy))
+    }
+
+    pub async fn get_scene_list(&self) -> HomeResult<Vec<String>> {
+        self.query_list(String::from("scene list")).await
+    }
+
+    /// `(room, device, state)` of every device in the scene.
+    pub async fn get_scene(
+        &self,
+        scene_name: &str,
+    ) -> HomeResult<Vec<(String, String, DeviceState)>> {
+        let items = self
+            .query_list(format!("get scene{SEPARATOR}{scene_name}"))
+            .await?;
+        let mut items = items.iter().map(String::as_str);
+        let mut result = vec![];
+        while let Some(room) = items.next() {
+            let device = items.next().ok_or(HomeError::BadResponse)?;
+            let count: usize = items
+                .next()
+                .and_then(|count| count.parse().ok())
+                .ok_or(HomeError::BadResponse)?;
+            let mut fields = items.by_ref().take(count);
+            let state = DeviceState::from_fields(&mut fields).ok_or(HomeError::BadResponse)?;
+            fields.for_each(drop);
+            result.push((room.into(), device.into(), state));
+        }
+        Ok(result)
+    }
+
+    /// Captures the current states of the devices given by `(room, device)` into the scene.
+    pub async fn capture_scene(
+        &self,
+        scene_name: &str,
+        devices: &[(&str, &str)],
+    ) -> HomeResult<()> {
+        let mut request = format!("capture scene{SEPARATOR}{scene_name}");
+        for (room, device) in devices {
+            write!(request, "{SEPARATOR}{room}{SEPARATOR}{device}");
+        }
+        self.request_list(request).await?;
+        Ok(())
+    }
+
+    /// Applies all states of the scene or, if any device refuses its state, none of them.
+    pub async fn apply_scene(&self, scene_name: &str) -> HomeResult<()> {
+        self.request_list(format!("apply scene{SEPARATOR}{scene_name}"))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_scene(&self, scene_name: &str) -> HomeResult<()> {
+        self.request_list(format!("remove scene{SEPARATOR}{scene_name}"))
+            .await?;
+        Ok(())
+    }
+
+    /// Names of the automation rules with whether they are enabled.
+    pub async fn get_rule_list(&self) -> HomeResult<Vec<(String, bool)>> {
+        let items = self.query_list(String::from("rule list")).await?;
+        Ok(items
+            .chunks_exact(2)
+            .map(|rule| (rule[0].clone(), rule[1] == "enabled"))
+            .collect())
+    }
+
+    /// Definition lines of the rule.
+    pub async fn get_rule(&self, rule_name: &str) -> HomeResult<Vec<String>> {
+        self.query_list(format!("get rule{SEPARATOR}{rule_name}"))
+            .await
+    }
+
+    /// Adds an enabled rule, see the server's `rules` module for the syntax.
+    pub async fn add_rule(&self, definition: &str) -> HomeResult<()> {
+        let lines: Vec<&str> = definition.lines().collect();
+        self.request_list(format!("add rule{SEPARATOR}{}", lines.join(SEPARATOR)))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_rule(&self, rule_name: &str) -> HomeResult<()> {
+        self.request_list(format!("remove rule{SEPARATOR}{rule_name}"))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn enable_rule(&self, rule_name: &str, enabled: bool) -> HomeResult<()> {
+        let command = if enabled {
+            "enable rule"
+        } else {
+            "disable rule"
+        };
+        self.request_list(format!("{command}{SEPARATOR}{rule_name}"))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_rule_log(&self) -> HomeResult<Vec<RuleLogEntry>> {
+        let items = self.query_list(String::from("rule log")).await?;
+        Ok(items
+            .chunks_exact(3)
+            .map(|entry| RuleLogEntry {
+                time: entry[0].clone(),
+                rule: entry[1].clone(),
+                message: entry[2].clone(),
+            })
+            .collect())
+    }
+
+    pub async fn get_schedule_list(&self) -> HomeResult<Vec<ScheduleInfo>> {
+        let items = self.query_list(String::from("schedule list")).await?;
+        Ok(items
+            .chunks_exact(4)
+            .map(|schedule| ScheduleInfo {
+                name: schedule[0].clone(),
+                when: schedule[1].clone(),
+                action: schedule[2].clone(),
+                next: Some(schedule[3].clone()).filter(|next| next != "none"),
+            })
+            .collect())
+    }
+
+    /// Schedules the action, e.g. `switch R/S on` at `cron 30 6 * * 1-5`.
+    pub async fn add_schedule(&self, name: &str, when: &str, action: &str) -> HomeResult<()> {
+        self.request_list(format!(
+            "add schedule{SEPARATOR}{name}{SEPARATOR}{when}{SEPARATOR}{action}"
+        ))
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove_schedule(&self, name: &str) -> HomeResult<()> {
+        self.request_list(format!("remove schedule{SEPARATOR}{name}"))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn history(
+        &self,
+        room_name: &str,
+        device_name: &str,
+        range: HistoryRange,
+    ) -> HomeResult<Vec<HistoryPoint>> {
+        let time = |time: Option<NaiveDateTime>| {
+            time.map(|time| time.format(TIME_FORMAT).to_string())
+                .unwrap_or_default()
+        };
+        let step = range
+            .step
+            .map(|step| step.as_secs().to_string())
+            .unwrap_or_default();
+        let items = self
+            .query_list(format!(
+                "history{SEPARATOR}{room_name}{SEPARATOR}{device_name}{SEPARATOR}{}{SEPARATOR}{}{SEPARATOR}{step}",
+                time(range.from),
+                time(range.to)
+            ))
+            .await?;
+        items
+            .chunks_exact(5)
+            .map(|point| {
+                Some(HistoryPoint {
+                    time: NaiveDateTime::parse_from_str(&point[0], TIME_FORMAT).ok()?,
+                    min: point[1].parse().ok()?,
+                    max: point[2].parse().ok()?,
+                    avg: point[3].parse().ok()?,
+                    count: point[4].parse().ok()?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(HomeError::BadResponse)
+    }
+
+    /// Responds with alerts in the order of their names.
+    pub async fn get_alert_list(&self) -> HomeResult<Vec<AlertInfo>> {
+        let items = self.query_list(String::from("alert list")).await?;
+        items
+            .chunks_exact(5)
+            .map(|alert| {
+                Some(AlertInfo {
+                    name: alert[0].clone(),
+                    rule: alert[1].clone(),
+                    state: alert[2].parse().ok()?,
+                    since: Some(alert[3].clone()).filter(|since| since != "none"),
+                    value: alert[4].parse().ok(),
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(HomeError::BadResponse)
+    }
+
+    /// Adds the alert, e.g. `R/T temperature > 30 hysteresis 2 for 1m`.
+    /// Thresholds are in °C, W and kWh whatever the negotiated units are.
+    pub async fn add_alert(&self, name: &str, rule: &str) -> HomeResult<()> {
+        self.request_list(format!("add alert{SEPARATOR}{name}{SEPARATOR}{rule}"))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_alert(&self, name: &str) -> HomeResult<()> {
+        self.request_list(format!("remove alert{SEPARATOR}{name}"))
+            .await?;
+        Ok(())
+    }
+
+    /// Only a raised alert may be acknowledged.
+    pub async fn acknowledge_alert(&self, name: &str) -> HomeResult<()> {
+        self.request_list(format!("ack alert{SEPARATOR}{name}"))
+            .await?;
+        Ok(())
+    }
+
+    /// Audit log entries within the time range, targets within `target` if it is given.
+    pub async fn get_audit_log(
+        &self,
+        from: Option<NaiveDateTime>,
+        to: Option<NaiveDateTime>,
+        target: Option<&str>,
+    ) -> HomeResult<Vec<AuditEntry>> {
+        let time = |time: Option<NaiveDateTime>| {
+            time.map(|time| time.format(TIME_FORMAT).to_string())
+                .unwrap_or_default()
+        };
+        let items = self
+            .query_list(format!(
+                "audit log{SEPARATOR}{}{SEPARATOR}{}{SEPARATOR}{}",
+                time(from),
+                time(to),
+                target.unwrap_or_default()
+            ))
+            .await?;
+        let optional = |value: &String| Some(value.clone()).filter(|value| value != "none");
+        items
+            .chunks_exact(8)
+            .map(|entry| {
+                Some(AuditEntry {
+                    time: NaiveDateTime::parse_from_str(&entry[0], TIME_FORMAT).ok()?,
+                    peer: entry[1].clone(),
+                    user: optional(&entry[2]),
+                    command: entry[3].clone(),
+                    target: entry[4].clone(),
+                    old: optional(&entry[5]),
+                    new: optional(&entry[6]),
+                    outcome: entry[7].clone(),
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(HomeError::BadResponse)
+    }
+
+    /// Turns the connection into a subscription to events, values come in the negotiated units.
+    /// The subscription is renewed whenever the connection is.
+    pub async fn subscribe(self) -> HomeResult<Subscription> {
+        let connection = match self.transport {
+            Transport::Single(connection) => connection,
+            Transport::Pooled(pool) => pool.detach().await?,
+        };
+        let response = connection.send_setup(String::from("subscribe")).await?;
+        list_from_stp_response(&mut response.split(SEPARATOR))?;
+        Ok(Subscription {
+            connection,
+            units: self.units,
+        })
+    }
+
+    async fn request_list(&self, request: String) -> HomeResult<Vec<String>> {
+        let response = self.transport.send_request(request).await?;
+        let mut response = response.split(SEPARATOR);
+        list_from_stp_response(&mut response)
+    }
+
+    async fn query_list(&self, request: String) -> HomeResult<Vec<String>> {
+        let response = self.transport.send_query(request).await?;
+        let mut response = response.split(SEPARATOR);
+        list_from_stp_response(&mut response)
     }
 
     pub async fn update_device(
@@ -55,23 +926,71 @@ impl HomeClient {
         room_name: &str,
         device_name: &str,
         device: Device,
-    ) -> RequestResult {
-        let info = device.device_info().join(SEPARATOR);
+    ) -> HomeResult<String> {
+        let info = device.device_info_in(&self.units).join(SEPARATOR);
         let response = self
-            .0
+            .transport
             .send_request(format!(
                 "update device{SEPARATOR}{room_name}{SEPARATOR}{device_name}{SEPARATOR}{info}"
             ))
             .await?;
+        let mut response = response.split(SEPARATOR);
+        list_from_stp_response(&mut response)?;
         Ok(OK_RESPONSE.into())
     }
 }
 
+impl Subscription {
+    /// Follows the connection, events sent while it's down are lost.
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.connection.state()
+    }
+
+    /// Waits for the next event of the server.
+    pub async fn next_event(&self) -> HomeResult<HomeEvent> {
+        let message = self.connection.recv_message().await?;
+        let fields: Vec<_> = message.split(SEPARATOR).collect();
+        match fields.as_slice() {
+            [EVENT_MESSAGE, "alert", name, room, device, state, value] => Ok(HomeEvent::Alert {
+                name: String::from(*name),
+                room: String::from(*room),
+                device: String::from(*device),
+                state: state.parse().map_err(|_| HomeError::BadResponse)?,
+                value: value.parse().ok(),
+            }),
+            [EVENT_MESSAGE, "notification", source, message] => Ok(HomeEvent::Notification {
+                source: String::from(*source),
+                message: String::from(*message),
+            }),
+            [EVENT_MESSAGE, "device", room, device, state @ ..] => Ok(HomeEvent::Device {
+                room: String::from(*room),
+                device: String::from(*device),
+                state: device_from_ok_response(&mut state.iter().copied(), &self.units),
+            }),
+            _ => Err(HomeError::BadResponse),
+        }
+    }
+}
+
+impl std::str::FromStr for AlertState {
+    type Err = HomeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raised" => Ok(AlertState::Raised),
+            "acknowledged" => Ok(AlertState::Acknowledged),
+            "cleared" => Ok(AlertState::Cleared),
+            _ => Err(HomeError::BadResponse),
+        }
+    }
+}
+
 fn device_from_stp_response<'a>(
     response: &'a mut impl Iterator<Item = &'a str>,
+    units: &Units,
 ) -> HomeResult<Device> {
     match response.next() {
-        Some(s) if s == OK_RESPONSE => Ok(device_from_ok_response(response)),
+        Some(s) if s == OK_RESPONSE => Ok(device_from_ok_response(response, units)),
         Some(s) if s == ERR_RESPONSE => {
             let mut error_msg = String::new();
             for item in response {
@@ -83,7 +1002,11 @@ fn device_from_stp_response<'a>(
     }
 }
 
-fn device_from_ok_response<'a>(response: &'a mut impl Iterator<Item = &'a str>) -> Device {
+/// Values in the response are given in `units`, devices keep them in °C, W and kWh.
+fn device_from_ok_response<'a>(
+    response: &mut impl Iterator<Item = &'a str>,
+    units: &Units,
+) -> Device {
     let device = response.next().unwrap_or_default();
     match device {
         "socket" => {
@@ -95,22 +1018,89 @@ fn device_from_ok_response<'a>(response: &'a mut impl Iterator<Item = &'a str>)
                 .parse()
                 .unwrap_or_default();
             if let Ok(voltage) = response.next().unwrap_or_default().parse::<f64>() {
-                Device::Socket(Socket::new(voltage, current, on))
+                let energy = response
+                    .next()
+                    .unwrap_or_default()
+                    .parse()
+                    .unwrap_or_default();
+                let peak_power = response
+                    .next()
+                    .unwrap_or_default()
+                    .parse()
+                    .unwrap_or_default();
+                let energy = Energy::new(energy, units.energy).value(EnergyUnit::KilowattHour);
+                let peak_power = Power::new(peak_power, units.power).value(PowerUnit::Watt);
+                let power_limit = response
+                    .next()
+                    .and_then(|limit| limit.parse().ok())
+                    .map(|limit| Power::new(limit, units.power).value(PowerUnit::Watt));
+                let frequency = response.next().and_then(|f| f.parse().ok()).unwrap_or(50.);
+                let power_factor = response.next().and_then(|pf| pf.parse().ok()).unwrap_or(1.);
+                match Socket::new(voltage, current, on).and_then(|mut socket| {
+                    socket.update(voltage, current, frequency, power_factor)?;
+                    socket
+                        .energy(energy)?
+                        .peak_power(peak_power)?
+                        .power_limit(power_limit)
+                }) {
+                    Ok(socket) => Device::Socket(socket),
+                    Err(_) => Device::Unknown,
+                }
             } else {
                 Device::Unknown
             }
         }
         "thermometer" => {
             if let Ok(temperature) = response.next().unwrap_or_default().parse::<f64>() {
-                Device::Thermometer(Thermometer::new(temperature))
+                let temperature = Temperature::new(temperature, units.temperature);
+                Device::Thermometer(Thermometer::new(
+                    temperature.value(TemperatureUnit::Celsius),
+                ))
             } else {
                 Device::Unknown
             }
         }
+        "lock" => {
+            let target = response.next().unwrap_or_default().parse::<LockState>();
+            let state = response.next().unwrap_or_default().parse::<LockState>();
+            let remaining = response.next().unwrap_or_default().parse::<u64>();
+            match (target, state, remaining) {
+                (Ok(target), Ok(state), Ok(remaining)) => Device::Lock(Lock::from_state(
+                    target,
+                    state,
+                    Duration::from_millis(remaining),
+                )),
+                _ => Device::Unknown,
+            }
+        }
+        "window covering" => {
+            let mut values = response.map(|item| item.parse::<f64>());
+            match (values.next(), values.next(), values.next(), values.next()) {
+                (
+                    Some(Ok(target_position)),
+                    Some(Ok(position)),
+                    Some(Ok(target_tilt)),
+                    Some(Ok(tilt)),
+                ) => Device::WindowCovering(WindowCovering::from_state(
+                    target_position,
+                    position,
+                    target_tilt,
+                    tilt,
+                )),
+                _ => Device::Unknown,
+            }
+        }
         _ => Device::Unknown,
     }
 }
 
+fn value_from_stp_response<'a>(response: &'a mut impl Iterator<Item = &'a str>) -> HomeResult<f64> {
+    list_from_stp_response(response)?
+        .first()
+        .and_then(|value| value.parse().ok())
+        .ok_or(HomeError::BadResponse)
+}
+
 fn list_from_stp_response<'a>(
     response: &'a mut impl Iterator<Item = &'a str>,
 ) -> HomeResult<Vec<String>> {
@@ -168,7 +1158,7 @@ mod tests {
         let mut c = HomeClient::new("127.0.0.1:4083").await.unwrap();
         let mut response = c.get_device("R", "S").await.unwrap();
         if let Device::Socket(mut socket) = response {
-            socket.set_voltage(215.);
+            socket.set_voltage(215.).unwrap();
             let result = c.update_device("R", "S", Device::Socket(socket)).await;
             assert_eq!(OK_RESPONSE, result.unwrap());
             let response = c.get_device("R", "S").await.unwrap();
@@ -187,12 +1177,12 @@ mod tests {
         let mut c = HomeClient::new("127.0.0.1:4083").await.unwrap();
         let mut response = c.get_device("R", "S").await.unwrap();
         if let Device::Socket(mut socket) = response {
-            socket.set_current(5.);
+            socket.set_current(5.).unwrap();
             let result = c.update_device("R", "S", Device::Socket(socket)).await;
             assert_eq!(OK_RESPONSE, result.unwrap());
             let response = c.get_device("R", "S").await.unwrap();
             if let Device::Socket(socket) = response {
-                assert_eq!(5., socket.get_current());
+                assert_eq!(5., socket.get_load_current());
             } else {
                 panic!("Unexpected device after update.")
             }
@@ -206,8 +1196,9 @@ mod tests {
         let mut c = HomeClient::new("127.0.0.1:4083").await.unwrap();
         let mut response = c.get_device("R", "S").await.unwrap();
         if let Device::Socket(mut socket) = response {
-            socket.set_current(5.);
-            socket.set_voltage(200.);
+            socket.switch(true);
+            socket.set_current(5.).unwrap();
+            socket.set_voltage(200.).unwrap();
             let result = c.update_device("R", "S", Device::Socket(socket)).await;
             assert_eq!(OK_RESPONSE, result.unwrap());
             let response = c.get_device("R", "S").await.unwrap();
@@ -220,4 +1211,421 @@ mod tests {
             panic!("Unexpected device comes from server.")
         }
     }
+
+    #[tokio::test]
+    async fn fahrenheit() {
+        let mut c = HomeClient::new("127.0.0.1:4083").await.unwrap();
+        let units = Units {
+            temperature: TemperatureUnit::Fahrenheit,
+            ..Default::default()
+        };
+        c.set_units(units).await.unwrap();
+        let thermometer = Thermometer::new(
+            Temperature::new(68., units.temperature).value(TemperatureUnit::Celsius),
+        );
+        c.update_device("R", "T", Device::Thermometer(thermometer))
+            .await
+            .unwrap();
+        let celsius_client = HomeClient::new("127.0.0.1:4083").await.unwrap();
+        if let Device::Thermometer(thermometer) = celsius_client.get_device("R", "T").await.unwrap()
+        {
+            assert!((thermometer.get_temperature() - 20.).abs() < 1e-9);
+        } else {
+            panic!("Unexpected device comes from server.")
+        }
+    }
+
+    #[tokio::test]
+    async fn metadata() {
+        let c = HomeClient::new("127.0.0.1:4083").await.unwrap();
+        let mut metadata = c.get_device_metadata("R", "L").await.unwrap();
+        metadata.manufacturer = Some("ACME".into());
+        metadata.add_tag("entrance");
+        c.set_device_metadata("R", "L", &metadata).await.unwrap();
+        assert_eq!(metadata, c.get_device_metadata("R", "L").await.unwrap());
+        let tagged = c.find_tagged("entrance").await.unwrap();
+        assert_eq!(vec![(String::from("R"), String::from("L"))], tagged);
+        let room = RoomMetadata {
+            display_name: Some("Living room".into()),
+            area: Some(20.5),
+        };
+        c.set_room_metadata("R", &room).await.unwrap();
+        assert_eq!(room, c.get_room_metadata("R").await.unwrap());
+        let bad = RoomMetadata {
+            area: Some(-1.),
+            ..room.clone()
+        };
+        assert!(c.set_room_metadata("R", &bad).await.is_err());
+        assert_eq!(room, c.get_room_metadata("R").await.unwrap());
+        assert!(c.get_room_metadata("No room").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn zones() {
+        let c = HomeClient::new("127.0.0.1:4083").await.unwrap();
+        c.add_zone("client floor/west").await.unwrap();
+        assert!(c.add_zone("client floor").await.is_err());
+        c.place_room("R", "client floor/west").await.unwrap();
+        assert_eq!("client floor/west", c.get_room_zone("R").await.unwrap());
+        assert!(c
+            .get_zone_list("")
+            .await
+            .unwrap()
+            .contains(&String::from("client floor")));
+        assert_eq!(
+            vec!["R"],
+            c.get_zone_rooms("client floor/west").await.unwrap()
+        );
+        assert!(c
+            .get_zone_devices("client floor")
+            .await
+            .unwrap()
+            .contains(&String::from("client floor/west/R/T")));
+        assert!(matches!(
+            c.get_device_at("client floor/west/R/T").await.unwrap(),
+            Device::Thermometer(_)
+        ));
+        assert!(c.get_device_at("R/T").await.is_err());
+        assert!(c.get_zone_energy("client floor").await.is_ok());
+        assert!(c.remove_zone("client floor").await.is_err());
+        // Addressing by room and device keeps working.
+        assert!(c.get_device("R", "T").await.is_ok());
+
+        // Leaves the home as it was for the other tests.
+        c.place_room("R", "").await.unwrap();
+        c.remove_zone("client floor/west").await.unwrap();
+        c.remove_zone("client floor").await.unwrap();
+        assert_eq!("", c.get_room_zone("R").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn rooms_and_devices() {
+        let c = HomeClient::new("127.0.0.1:4083").await.unwrap();
+        c.add_room("client room").await.unwrap();
+        assert!(c.add_room("client room").await.is_err());
+        let thermometer = Device::Thermometer(Thermometer::new(18_f64));
+        c.add_device("client room", "T", thermometer.clone())
+            .await
+            .unwrap();
+        assert!(c
+            .add_device("client room", "T", Device::new_socket())
+            .await
+            .is_err());
+        assert!(c
+            .add_device("No room", "T", Device::new_socket())
+            .await
+            .is_err());
+        assert_eq!(thermometer, c.get_device("client room", "T").await.unwrap());
+        c.remove_device("client room", "T").await.unwrap();
+        assert!(c.remove_device("client room", "T").await.is_err());
+        c.remove_room("client room").await.unwrap();
+        assert!(!c
+            .get_room_list()
+            .await
+            .unwrap()
+            .contains(&"client room".into()));
+    }
+
+    #[tokio::test]
+    async fn pooled() {
+        let options = pool::PoolOptions::default().min(2).max(4);
+        let mut c = HomeClient::pooled("127.0.0.1:4083", options).await.unwrap();
+        c.add_room("pool room").await.unwrap();
+        let thermometer = Device::Thermometer(Thermometer::new(25_f64));
+        c.add_device("pool room", "T", thermometer.clone())
+            .await
+            .unwrap();
+        // Every connection of the pool exchanges values in °F from now on.
+        c.set_units(Units {
+            temperature: TemperatureUnit::Fahrenheit,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let c = std::sync::Arc::new(c);
+        let readings: Vec<_> = (0..8)
+            .map(|_| {
+                let c = std::sync::Arc::clone(&c);
+                tokio::spawn(async move { c.get_device("pool room", "T").await })
+            })
+            .collect();
+        for reading in readings {
+            let device = reading.await.unwrap().unwrap();
+            assert!(matches!(
+                device,
+                Device::Thermometer(t) if (t.get_temperature() - 25.).abs() < 1e-9
+            ));
+        }
+        c.remove_room("pool room").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn groups() {
+        let c = HomeClient::new("127.0.0.1:4083").await.unwrap();
+        c.add_group("all in R").await.unwrap();
+        assert!(c.add_group("all in R").await.is_err());
+        c.add_to_group("all in R", "R", "S").await.unwrap();
+        c.add_to_group("all in R", "R", "T").await.unwrap();
+        assert!(c.add_to_group("all in R", "R", "No device").await.is_err());
+        assert!(c
+            .get_group_list()
+            .await
+            .unwrap()
+            .contains(&"all in R".into()));
+        assert_eq!(2, c.get_group_members("all in R").await.unwrap().len());
+        let results = c.switch_group("all in R", true).await.unwrap();
+        assert_eq!(2, results.len());
+        for member in results {
+            match member.device.as_str() {
+                "S" => assert!(member.result.is_ok()),
+                _ => assert!(member.result.is_err()),
+            }
+        }
+        let results = c.read_group("all in R").await.unwrap();
+        assert!(results.iter().all(|member| member.result.is_ok()));
+        c.remove_from_group("all in R", "R", "T").await.unwrap();
+        c.remove_group("all in R").await.unwrap();
+        assert!(c.read_group("all in R").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn query() {
+        let c = HomeClient::new("127.0.0.1:4083").await.unwrap();
+        match c.query("room = R and kind = lock").await.unwrap() {
+            QueryAnswer::Devices(devices) => {
+                assert_eq!(1, devices.len());
+                assert_eq!(("R", "L"), (devices[0].0.as_str(), devices[0].1.as_str()));
+                assert!(matches!(devices[0].2, Device::Lock(_)));
+            }
+            answer => panic!("Unexpected answer {:?}", answer),
+        }
+        assert_eq!(
+            QueryAnswer::Count(1),
+            c.query("count where room = R and kind = thermometer")
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            QueryAnswer::Value(None),
+            c.query("avg temperature where room = No room")
+                .await
+                .unwrap()
+        );
+        assert!(c.query("colour = red").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn stats() {
+        let c = HomeClient::new("127.0.0.1:4083").await.unwrap();
+        // Other tests add rooms and devices, so only the room R is checked.
+        let stats = c.get_room_stats("R").await.unwrap();
+        assert_eq!(1, stats.count("thermometer"));
+        assert_eq!(1, stats.count("lock"));
+        assert!(stats.average_temperature.is_some());
+        assert!(c.get_stats().await.is_ok());
+        assert!(c.get_zone_stats("").await.is_ok());
+        assert!(c.get_room_stats("No room").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rules() {
+        let c = HomeClient::new("127.0.0.1:4083").await.unwrap();
+        let definition = "rule client test\nwhen every 1s\nthen notify tick";
+        c.add_rule(definition).await.unwrap();
+        assert!(c.add_rule(definition).await.is_err());
+        assert!(c.add_rule("rule broken\nwhen never").await.is_err());
+        assert!(c
+            .get_rule_list()
+            .await
+            .unwrap()
+            .contains(&("client test".into(), true)));
+        assert_eq!(3, c.get_rule("client test").await.unwrap().len());
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        c.enable_rule("client test", false).await.unwrap();
+        assert!(c
+            .get_rule_log()
+            .await
+            .unwrap()
+            .iter()
+            .any(|entry| entry.rule == "client test" && entry.message == "notify tick"));
+        c.remove_rule("client test").await.unwrap();
+        assert!(c.enable_rule("client test", true).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn scenes() {
+        let c = HomeClient::new("127.0.0.1:4083").await.unwrap();
+        c.capture_scene("client scene", &[("R", "L"), ("R", "W")])
+            .await
+            .unwrap();
+        assert!(c.capture_scene("bad scene", &[("R", "T")]).await.is_err());
+        assert!(c
+            .get_scene_list()
+            .await
+            .unwrap()
+            .contains(&"client scene".into()));
+        let states = c.get_scene("client scene").await.unwrap();
+        assert_eq!(2, states.len());
+        assert!(matches!(states[0].2, DeviceState::Lock { .. }));
+        c.apply_scene("client scene").await.unwrap();
+        c.remove_scene("client scene").await.unwrap();
+        assert!(c.apply_scene("client scene").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn schedules() {
+        let c = HomeClient::new("127.0.0.1:4083").await.unwrap();
+        c.add_schedule("client wake", "cron 30 6 * * 1-5", "switch R/S on")
+            .await
+            .unwrap();
+        assert!(c
+            .add_schedule("client wake", "cron 0 7 * * *", "switch R/S on")
+            .await
+            .is_err());
+        assert!(c
+            .add_schedule("client bad", "cron 30 6 * *", "switch R/S on")
+            .await
+            .is_err());
+        let schedules = c.get_schedule_list().await.unwrap();
+        let wake = schedules
+            .iter()
+            .find(|schedule| schedule.name == "client wake")
+            .unwrap();
+        assert_eq!("switch R/S on", wake.action);
+        assert!(wake.next.as_ref().unwrap().ends_with("06:30"));
+        c.remove_schedule("client wake").await.unwrap();
+        assert!(c.remove_schedule("client wake").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn history() {
+        let c = HomeClient::new("127.0.0.1:4083").await.unwrap();
+        c.update_device("R", "T", Device::Thermometer(Thermometer::new(23_f64)))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let points = c.history("R", "T", HistoryRange::default()).await.unwrap();
+        assert!(points.iter().any(|point| point.max == 23_f64));
+        let range = HistoryRange {
+            step: Some(Duration::from_secs(24 * 3600)),
+            ..Default::default()
+        };
+        let daily = c.history("R", "T", range).await.unwrap();
+        assert!(daily.len() <= 2);
+        let range = HistoryRange {
+            to: points.first().map(|point| point.time),
+            ..Default::default()
+        };
+        assert!(c.history("R", "T", range).await.unwrap().is_empty());
+        assert!(c
+            .history("R", "No device", HistoryRange::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn device_events() {
+        let c = HomeClient::new("127.0.0.1:4083").await.unwrap();
+        let subscription = HomeClient::new("127.0.0.1:4083")
+            .await
+            .unwrap()
+            .subscribe()
+            .await
+            .unwrap();
+        let thermometer = Device::Thermometer(Thermometer::new(31.5));
+        c.update_device("R", "T", thermometer.clone())
+            .await
+            .unwrap();
+        loop {
+            match subscription.next_event().await.unwrap() {
+                HomeEvent::Device {
+                    room,
+                    device,
+                    state,
+                } if room == "R" && device == "T" => {
+                    // Another test may have changed the thermometer meanwhile.
+                    if state == thermometer {
+                        break;
+                    }
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn alerts() {
+        let c = HomeClient::new("127.0.0.1:4083").await.unwrap();
+        let subscription = HomeClient::new("127.0.0.1:4083")
+            .await
+            .unwrap()
+            .subscribe()
+            .await
+            .unwrap();
+        c.update_device("R", "T", Device::Thermometer(Thermometer::new(20_f64)))
+            .await
+            .unwrap();
+        c.add_alert("client frost", "R/T temperature < -30")
+            .await
+            .unwrap();
+        assert!(c.add_alert("client bad", "R/T kind > 1").await.is_err());
+        assert!(c.acknowledge_alert("client frost").await.is_err());
+        c.update_device("R", "T", Device::Thermometer(Thermometer::new(-40_f64)))
+            .await
+            .unwrap();
+        let event = loop {
+            match subscription.next_event().await.unwrap() {
+                event @ HomeEvent::Alert { .. } => break event,
+                _ => continue,
+            }
+        };
+        assert_eq!(
+            HomeEvent::Alert {
+                name: "client frost".into(),
+                room: "R".into(),
+                device: "T".into(),
+                state: AlertState::Raised,
+                value: Some(-40_f64),
+            },
+            event
+        );
+        c.acknowledge_alert("client frost").await.unwrap();
+        let alerts = c.get_alert_list().await.unwrap();
+        let frost = alerts
+            .iter()
+            .find(|alert| alert.name == "client frost")
+            .unwrap();
+        assert_eq!(AlertState::Acknowledged, frost.state);
+        assert!(frost.since.is_some());
+        c.update_device("R", "T", Device::Thermometer(Thermometer::new(20_f64)))
+            .await
+            .unwrap();
+        c.remove_alert("client frost").await.unwrap();
+        assert!(c.remove_alert("client frost").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn audit_log() {
+        let c = HomeClient::new("127.0.0.1:4083").await.unwrap();
+        c.update_device("R", "T", Device::Thermometer(Thermometer::new(21_f64)))
+            .await
+            .unwrap();
+        assert!(c.remove_zone("Client audit zone").await.is_err());
+        let entries = c.get_audit_log(None, None, Some("R/T")).await.unwrap();
+        let update = entries.last().unwrap();
+        assert_eq!("update device", update.command);
+        assert_eq!("Ok", update.outcome);
+        assert!(update.new.as_ref().unwrap().starts_with("thermometer 21"));
+        let entries = c
+            .get_audit_log(None, None, Some("Client audit zone"))
+            .await
+            .unwrap();
+        assert_ne!("Ok", entries.last().unwrap().outcome);
+        assert!(c
+            .get_audit_log(None, None, None)
+            .await
+            .unwrap()
+            .iter()
+            .all(|entry| entry.command != "device list"));
+    }
 }
@@ -0,0 +1,376 @@
+//! Several connections to the same server, so that concurrent tasks don't wait for each other.
+
+use crate::connection::{remember, Connection, ConnectionState, Reconnect};
+use crate::error::{HomeError, HomeResult};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use stp::error::ConnectResult;
+use tokio::net::ToSocketAddrs;
+use tokio::sync::{watch, Semaphore, SemaphorePermit};
+
+/// Limits of a pool, see [`crate::HomeClient::pooled`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolOptions {
+    min: usize,
+    max: usize,
+    acquire_timeout: Duration,
+    health_check: Option<Duration>,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            min: 1,
+            max: 8,
+            acquire_timeout: Duration::from_secs(5),
+            health_check: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+impl PoolOptions {
+    /// Connections kept open even when idle.
+    pub fn min(mut self, min: usize) -> Self {
+        self.min = min;
+        self
+    }
+
+    /// Connections open at most, that is requests in parallel.
+    pub fn max(mut self, max: usize) -> Self {
+        self.max = max.max(1);
+        self
+    }
+
+    /// How long a request waits for a free connection.
+    pub fn acquire_timeout(mut self, acquire_timeout: Duration) -> Self {
+        self.acquire_timeout = acquire_timeout;
+        self
+    }
+
+    /// How often idle connections are probed, `None` to never probe them.
+    pub fn health_check(mut self, health_check: Option<Duration>) -> Self {
+        self.health_check = health_check.filter(|every| !every.is_zero());
+        self
+    }
+}
+
+/// Requests replayed on every connection, numbered to find connections that missed some.
+#[derive(Default)]
+struct Setup {
+    version: usize,
+    requests: Vec<String>,
+}
+
+struct Idle {
+    connection: Connection,
+    version: usize,
+}
+
+/// Connection taken from the pool. Unless released, it's closed, as a request interrupted
+/// halfway leaves its response on the connection.
+struct Lease<'a> {
+    pool: &'a Pool,
+    connection: Connection,
+    version: usize,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Lease<'_> {
+    fn release(self) {
+        let idle = Idle {
+            connection: self.connection,
+            version: self.version,
+        };
+        self.pool.lock_idle().push_back(idle);
+    }
+}
+
+pub(crate) struct Pool {
+    addrs: Vec<SocketAddr>,
+    options: PoolOptions,
+    reconnect: Mutex<Reconnect>,
+    /// Most recently used last.
+    idle: Mutex<VecDeque<Idle>>,
+    /// One for every connection in use.
+    permits: Semaphore,
+    setup: Mutex<Setup>,
+    state: Arc<watch::Sender<ConnectionState>>,
+}
+
+impl Pool {
+    /// Opens the minimum of connections and starts the health checks.
+    pub(crate) async fn open<Addr>(addr: Addr, options: PoolOptions) -> ConnectResult<Arc<Self>>
+    where
+        Addr: ToSocketAddrs,
+    {
+        let addrs: Vec<_> = tokio::net::lookup_host(addr).await?.collect();
+        let state = Arc::new(watch::Sender::new(ConnectionState::Connected));
+        let mut idle = VecDeque::new();
+        for _ in 0..options.min.min(options.max) {
+            let connection = Connection::open_on(addrs.clone(), Arc::clone(&state)).await?;
+            idle.push_back(Idle {
+                connection,
+                version: 0,
+            });
+        }
+        let pool = Arc::new(Self {
+            addrs,
+            options,
+            reconnect: Mutex::new(Reconnect::default()),
+            idle: Mutex::new(idle),
+            permits: Semaphore::new(options.max),
+            setup: Mutex::new(Setup::default()),
+            state,
+        });
+        if let Some(every) = options.health_check {
+            tokio::spawn(check_health(Arc::downgrade(&pool), every));
+        }
+        Ok(pool)
+    }
+
+    pub(crate) fn set_reconnect(&self, reconnect: Reconnect) {
+        *self.reconnect.lock().unwrap_or_else(|e| e.into_inner()) = reconnect;
+    }
+
+    pub(crate) fn state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
+    pub(crate) async fn send_request(&self, request: impl AsRef<str>) -> HomeResult<String> {
+        let lease = self.acquire().await?;
+        let response = lease.connection.send_request(request).await;
+        lease.release();
+        response
+    }
+
+    pub(crate) async fn send_query(&self, request: impl AsRef<str>) -> HomeResult<String> {
+        let lease = self.acquire().await?;
+        let response = lease.connection.send_query(request).await;
+        lease.release();
+        response
+    }
+
+    /// Sends the request now and on every other connection before its next request.
+    pub(crate) async fn send_setup(&self, request: String) -> HomeResult<String> {
+        let mut lease = self.acquire().await?;
+        let response = lease.connection.send_setup(request.clone()).await;
+        if response.is_ok() {
+            let mut setup = self.lock_setup();
+            remember(&mut setup.requests, request);
+            setup.version += 1;
+            lease.version = setup.version;
+        }
+        lease.release();
+        response
+    }
+
+    /// Takes a connection out of the pool for good, like for a subscription.
+    pub(crate) async fn detach(&self) -> HomeResult<Connection> {
+        Ok(self.acquire().await?.connection)
+    }
+
+    async fn acquire(&self) -> HomeResult<Lease<'_>> {
+        let permit = tokio::time::timeout(self.options.acquire_timeout, self.permits.acquire())
+            .await
+            .map_err(|_| HomeError::PoolTimeout)?
+            .map_err(|_| HomeError::PoolTimeout)?;
+        let idle = self.lock_idle().pop_back();
+        let (requests, version) = {
+            let setup = self.lock_setup();
+            (setup.requests.clone(), setup.version)
+        };
+        let mut connection = match idle {
+            Some(idle) if idle.version == version => idle.connection,
+            Some(idle) => {
+                for request in requests {
+                    idle.connection.send_setup(request).await?;
+                }
+                idle.connection
+            }
+            None => Connection::lazy(self.addrs.clone(), Arc::clone(&self.state), requests),
+        };
+        connection.set_reconnect(*self.reconnect.lock().unwrap_or_else(|e| e.into_inner()));
+        Ok(Lease {
+            pool: self,
+            connection,
+            version,
+            _permit: permit,
+        })
+    }
+
+    /// Closes idle connections that don't answer and opens new ones up to the minimum.
+    async fn check(&self) {
+        let idle = self.lock_idle().len();
+        for _ in 0..idle {
+            let Ok(_permit) = self.permits.try_acquire() else {
+                break;
+            };
+            let Some(idle) = self.lock_idle().pop_front() else {
+                break;
+            };
+            if idle.connection.probe().await {
+                self.lock_idle().push_back(idle);
+            }
+        }
+        loop {
+            let in_use = self.options.max - self.permits.available_permits();
+            if in_use + self.lock_idle().len() >= self.options.min.min(self.options.max) {
+                return;
+            }
+            let Ok(connection) =
+                Connection::open_on(self.addrs.clone(), Arc::clone(&self.state)).await
+            else {
+                return;
+            };
+            // Sends the setup before its first request.
+            self.lock_idle().push_front(Idle {
+                connection,
+                version: 0,
+            });
+        }
+    }
+
+    fn lock_idle(&self) -> std::sync::MutexGuard<'_, VecDeque<Idle>> {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_setup(&self) -> std::sync::MutexGuard<'_, Setup> {
+        self.setup.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+async fn check_health(pool: Weak<Pool>, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let Some(pool) = pool.upgrade() else {
+            return;
+        };
+        pool.check().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use stp::server::StpServer;
+    use tokio::sync::Barrier;
+
+    /// Answers `Ok///<request>` on every connection once the barrier is passed.
+    async fn serve(barrier: usize) -> (SocketAddr, Arc<AtomicUsize>) {
+        let server = StpServer::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&accepted);
+        let barrier = Arc::new(Barrier::new(barrier));
+        tokio::spawn(async move {
+            while let Ok(peer) = server.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let barrier = Arc::clone(&barrier);
+                tokio::spawn(async move {
+                    while let Ok(request) = peer.recv_request().await {
+                        barrier.wait().await;
+                        let response = format!("Ok///{request}");
+                        if peer.send_response(response).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        (addr, accepted)
+    }
+
+    #[tokio::test]
+    async fn test_parallel() {
+        let (addr, accepted) = serve(4).await;
+        let pool = Pool::open(addr, PoolOptions::default().min(2).max(4))
+            .await
+            .unwrap();
+        assert_eq!(2, accepted.load(Ordering::SeqCst));
+
+        // Every request waits for the others, so they can't go one after another.
+        let requests: Vec<_> = (0..4)
+            .map(|i| {
+                let pool = Arc::clone(&pool);
+                tokio::spawn(async move { pool.send_query(format!("get {i}")).await })
+            })
+            .collect();
+        for (i, request) in requests.into_iter().enumerate() {
+            let response = tokio::time::timeout(Duration::from_secs(5), request)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(format!("Ok///get {i}"), response.unwrap());
+        }
+        assert_eq!(4, accepted.load(Ordering::SeqCst));
+        assert_eq!(4, pool.lock_idle().len());
+    }
+
+    #[tokio::test]
+    async fn test_acquire_timeout() {
+        let (addr, _) = serve(2).await;
+        let options = PoolOptions::default()
+            .max(1)
+            .acquire_timeout(Duration::from_millis(50));
+        let pool = Pool::open(addr, options).await.unwrap();
+        let waiting = {
+            let pool = Arc::clone(&pool);
+            tokio::spawn(async move { pool.send_query("first").await })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(matches!(
+            pool.send_query("second").await,
+            Err(HomeError::PoolTimeout)
+        ));
+        // The interrupted request closes its connection instead of returning it.
+        waiting.abort();
+        assert!(waiting.await.is_err());
+        assert!(pool.lock_idle().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_setup() {
+        let (addr, accepted) = serve(1).await;
+        let pool = Pool::open(addr, PoolOptions::default().min(2))
+            .await
+            .unwrap();
+        pool.send_setup(String::from("units///f")).await.unwrap();
+        pool.send_setup(String::from("units///c")).await.unwrap();
+        assert_eq!(vec!["units///c"], pool.lock_setup().requests);
+
+        // The connection that missed the setup catches up before its request.
+        let first = pool.acquire().await.unwrap();
+        let second = pool.acquire().await.unwrap();
+        assert_eq!(2, first.version);
+        assert_eq!(2, second.version);
+        first.release();
+        second.release();
+        assert_eq!(2, accepted.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_health_check() {
+        let (addr, accepted) = serve(1).await;
+        let options = PoolOptions::default()
+            .min(1)
+            .health_check(Some(Duration::from_millis(10)));
+        let pool = Pool::open(addr, options).await.unwrap();
+        assert!(pool.lock_idle().pop_back().is_some());
+
+        // The health check opens the connection again and then keeps it.
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while accepted.load(Ordering::SeqCst) < 2 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(2, accepted.load(Ordering::SeqCst));
+    }
+}
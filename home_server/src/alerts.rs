@@ -0,0 +1,463 @@
+//! Alerts on numeric device readings.
+//!
+//! ```text
+//! R/T temperature > 30 hysteresis 2 for 1m
+//! R/S power > 2000
+//! R/S power > 90% limit
+//! ```
+//!
+//! An alert is raised once the reading stays beyond the threshold for the debounce time
+//! (`for`, zero by default) and cleared once it returns past the threshold by the hysteresis.
+//! Thresholds are in °C, W and kWh. The power of a socket may be compared with a share
+//! of its power limit instead, `limit` alone meaning all of it; the socket switches itself
+//! off above the limit, and the alert is never raised while the socket has no limit.
+//! An alert on a device which is gone, or no longer has the reading, is cleared.
+
+use crate::events::{Event, EventSender};
+use crate::rules::{parse_path, parse_period};
+use chrono::{NaiveDateTime, TimeDelta};
+use smart_home::{group::DevicePath, home::Home, query::Field, smart_device::Device, units::Units};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    pub path: DevicePath,
+    pub field: Field,
+    /// Alert on readings above the threshold, otherwise below it.
+    pub above: bool,
+    pub threshold: Threshold,
+    pub hysteresis: f64,
+    pub debounce: TimeDelta,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Threshold {
+    Value(f64),
+    /// Share of the power limit of the socket, 1 for the whole limit.
+    PowerLimit(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertState {
+    Raised,
+    Acknowledged,
+    Cleared,
+}
+
+#[derive(Debug)]
+pub struct Alert {
+    name: String,
+    rule: AlertRule,
+    /// Text the rule was parsed from.
+    spec: String,
+    state: AlertState,
+    /// When the state has changed last, `None` if the alert has never been raised.
+    since: Option<NaiveDateTime>,
+    /// Latest reading, °C, W or kWh.
+    value: Option<f64>,
+    /// When the reading went beyond the threshold.
+    pending: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Default)]
+pub struct Alerts {
+    alerts: BTreeMap<String, Alert>,
+}
+
+impl std::str::FromStr for AlertRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = s.split_whitespace();
+        let path = parse_path(tokens.next().unwrap_or_default())?;
+        let field = tokens.next().unwrap_or_default();
+        let field: Field = field.parse().map_err(|e| format!("{e}"))?;
+        if !field.is_numeric() {
+            return Err(format!("Field '{field:?}' is not numeric."));
+        }
+        let above = match tokens.next() {
+            Some(">") => true,
+            Some("<") => false,
+            _ => return Err(format!("Expected '>' or '<' in '{s}'.")),
+        };
+        let threshold = match tokens.next().unwrap_or_default() {
+            "limit" => Threshold::PowerLimit(1_f64),
+            share if share.ends_with('%') => {
+                let percent = share
+                    .trim_end_matches('%')
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|percent| *percent > 0_f64)
+                    .ok_or_else(|| format!("Bad threshold '{share}'."))?;
+                if tokens.next() != Some("limit") {
+                    return Err(format!("Expected 'limit' after '{share}'."));
+                }
+                Threshold::PowerLimit(percent / 100_f64)
+            }
+            value => Threshold::Value(
+                value
+                    .parse()
+                    .map_err(|_| format!("Bad threshold '{value}'."))?,
+            ),
+        };
+        if matches!(threshold, Threshold::PowerLimit(_)) && field != Field::Power {
+            return Err(String::from("Only power may be compared with the limit."));
+        }
+        let mut rule = Self {
+            path,
+            field,
+            above,
+            threshold,
+            hysteresis: 0_f64,
+            debounce: TimeDelta::zero(),
+        };
+        while let Some(option) = tokens.next() {
+            let value = tokens.next().unwrap_or_default();
+            match option {
+                "hysteresis" => {
+                    rule.hysteresis = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|hysteresis| *hysteresis >= 0_f64)
+                        .ok_or_else(|| format!("Bad hysteresis '{value}'."))?
+                }
+                "for" => {
+                    rule.debounce = TimeDelta::from_std(parse_period(value)?)
+                        .map_err(|_| format!("Bad period '{value}'."))?
+                }
+                _ => return Err(format!("Unknown option '{option}'.")),
+            }
+        }
+        Ok(rule)
+    }
+}
+
+impl AlertState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertState::Raised => "raised",
+            AlertState::Acknowledged => "acknowledged",
+            AlertState::Cleared => "cleared",
+        }
+    }
+}
+
+impl Alert {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_rule(&self) -> &AlertRule {
+        &self.rule
+    }
+
+    pub fn get_spec(&self) -> &str {
+        &self.spec
+    }
+
+    pub fn get_state(&self) -> AlertState {
+        self.state
+    }
+
+    pub fn get_since(&self) -> Option<NaiveDateTime> {
+        self.since
+    }
+
+    pub fn get_value(&self) -> Option<f64> {
+        self.value
+    }
+
+    /// New state if it changes with the reading.
+    fn check(&mut self, home: &Home, now: NaiveDateTime) -> Option<AlertState> {
+        let rule = &self.rule;
+        let reading = home
+            .get_device_by_path(&rule.path.room, &rule.path.device)
+            .and_then(|device| Some((device, rule.field.number(device, &Units::default())?)));
+        let Some((device, value)) = reading else {
+            self.value = None;
+            self.pending = None;
+            if self.state == AlertState::Cleared {
+                return None;
+            }
+            self.state = AlertState::Cleared;
+            self.since = Some(now);
+            return Some(AlertState::Cleared);
+        };
+        self.value = Some(value);
+        let threshold = match (rule.threshold, device) {
+            (Threshold::Value(threshold), _) => threshold,
+            (Threshold::PowerLimit(share), Device::Socket(socket)) => {
+                socket.get_power_limit()? * share
+            }
+            (Threshold::PowerLimit(_), _) => return None,
+        };
+        let (beyond, back) = if rule.above {
+            (value > threshold, value <= threshold - rule.hysteresis)
+        } else {
+            (value < threshold, value >= threshold + rule.hysteresis)
+        };
+        if !beyond {
+            self.pending = None;
+        }
+        let state = match self.state {
+            AlertState::Cleared if beyond => {
+                let pending = *self.pending.get_or_insert(now);
+                if now - pending < rule.debounce {
+                    return None;
+                }
+                AlertState::Raised
+            }
+            AlertState::Raised | AlertState::Acknowledged if back => AlertState::Cleared,
+            _ => return None,
+        };
+        self.state = state;
+        self.since = Some(now);
+        self.pending = None;
+        Some(state)
+    }
+
+    fn event(&self) -> Event {
+        Event::Alert {
+            name: self.name.clone(),
+            path: self.rule.path.clone(),
+            field: self.rule.field,
+            state: self.state,
+            value: self.value,
+        }
+    }
+}
+
+impl Alerts {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn alert_list(&self) -> impl Iterator<Item = &Alert> {
+        self.alerts.values()
+    }
+
+    pub fn add(&mut self, name: &str, spec: &str) -> Result<(), String> {
+        if name.is_empty() {
+            return Err(String::from("Alert name is missing."));
+        }
+        if self.alerts.contains_key(name) {
+            return Err(format!("Alert '{name}' already exists."));
+        }
+        let alert = Alert {
+            name: name.into(),
+            rule: spec.parse()?,
+            spec: spec.trim().into(),
+            state: AlertState::Cleared,
+            since: None,
+            value: None,
+            pending: None,
+        };
+        self.alerts.insert(name.into(), alert);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Alert> {
+        self.alerts.remove(name)
+    }
+
+    /// Only a raised alert may be acknowledged, it stays so until it is cleared.
+    pub fn acknowledge(
+        &mut self,
+        name: &str,
+        now: NaiveDateTime,
+        events: &EventSender,
+    ) -> Result<(), String> {
+        let alert = self
+            .alerts
+            .get_mut(name)
+            .ok_or_else(|| format!("Alert '{name}' not found."))?;
+        if alert.state != AlertState::Raised {
+            return Err(format!("Alert '{name}' is not raised."));
+        }
+        alert.state = AlertState::Acknowledged;
+        alert.since = Some(now);
+        // Nobody may be listening, that is fine.
+        let _ = events.send(alert.event());
+        Ok(())
+    }
+
+    /// Checks readings against the thresholds and sends an event for every changed state.
+    pub fn evaluate(&mut self, home: &Home, now: NaiveDateTime, events: &EventSender) {
+        for alert in self.alerts.values_mut() {
+            if alert.check(home, now).is_some() {
+                let _ = events.send(alert.event());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events;
+    use smart_home::smart_device::{Device, Socket};
+
+    fn at(second: u32) -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2022, 1, 31)
+            .and_then(|date| date.and_hms_opt(10, 0, second))
+            .unwrap()
+    }
+
+    fn set_temperature(home: &mut Home, temperature: f64) {
+        if let Some(Device::Thermometer(t)) = home.get_device_by_path_mut("R", "T") {
+            t.set_temperature(temperature);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let rule: AlertRule = "R/T temperature > 30 hysteresis 2 for 1m".parse().unwrap();
+        assert_eq!(Field::Temperature, rule.field);
+        assert!(rule.above);
+        assert_eq!(Threshold::Value(30_f64), rule.threshold);
+        assert_eq!(2_f64, rule.hysteresis);
+        assert_eq!(TimeDelta::minutes(1), rule.debounce);
+        assert!("R/T temperature >".parse::<AlertRule>().is_err());
+        assert!("R/T kind > 1".parse::<AlertRule>().is_err());
+        assert!("R/T temperature = 1".parse::<AlertRule>().is_err());
+        assert!("R/T temperature > 1 for ever".parse::<AlertRule>().is_err());
+        let rule: AlertRule = "R/S power > 90% limit".parse().unwrap();
+        assert_eq!(Threshold::PowerLimit(0.9), rule.threshold);
+        let rule: AlertRule = "R/S power > limit".parse().unwrap();
+        assert_eq!(Threshold::PowerLimit(1_f64), rule.threshold);
+        assert!("R/S power > 90%".parse::<AlertRule>().is_err());
+        assert!("R/S power > -5% limit".parse::<AlertRule>().is_err());
+        assert!("R/T temperature > limit".parse::<AlertRule>().is_err());
+        assert!("R/T temperature > 1 for 9223372036854775807h"
+            .parse::<AlertRule>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_hysteresis_and_debounce() {
+        let mut home = Home::restore();
+        let events = events::channel();
+        let mut received = events.subscribe();
+        let mut alerts = Alerts::new();
+        alerts
+            .add("hot", "R/T temperature > 30 hysteresis 2 for 2s")
+            .unwrap();
+        set_temperature(&mut home, 31_f64);
+        alerts.evaluate(&home, at(0), &events);
+        alerts.evaluate(&home, at(1), &events);
+        assert!(received.try_recv().is_err());
+        // The debounce starts over once the reading returns.
+        set_temperature(&mut home, 29_f64);
+        alerts.evaluate(&home, at(2), &events);
+        set_temperature(&mut home, 31_f64);
+        alerts.evaluate(&home, at(3), &events);
+        alerts.evaluate(&home, at(4), &events);
+        assert!(received.try_recv().is_err());
+        alerts.evaluate(&home, at(5), &events);
+        assert!(matches!(
+            received.try_recv(),
+            Ok(Event::Alert {
+                state: AlertState::Raised,
+                value: Some(value),
+                ..
+            }) if value == 31_f64
+        ));
+        assert!(alerts.acknowledge("hot", at(6), &events).is_ok());
+        assert!(alerts.acknowledge("hot", at(6), &events).is_err());
+        assert!(received.try_recv().is_ok());
+        // Within the hysteresis.
+        set_temperature(&mut home, 29_f64);
+        alerts.evaluate(&home, at(7), &events);
+        let alert = alerts.alert_list().next().unwrap();
+        assert_eq!(AlertState::Acknowledged, alert.get_state());
+        set_temperature(&mut home, 28_f64);
+        alerts.evaluate(&home, at(8), &events);
+        let alert = alerts.alert_list().next().unwrap();
+        assert_eq!(AlertState::Cleared, alert.get_state());
+        assert_eq!(Some(at(8)), alert.get_since());
+    }
+
+    #[test]
+    fn test_rated_power() {
+        let mut home = Home::restore();
+        let socket = Socket::new(220_f64, 10_f64, true).unwrap();
+        home.add_device("R", "Heater", socket.into());
+        let events = events::channel();
+        let mut alerts = Alerts::new();
+        alerts.add("overload", "R/Heater power > 3000").unwrap();
+        assert!(alerts.add("overload", "R/Heater power > 2000").is_err());
+        alerts.evaluate(&home, at(0), &events);
+        assert_eq!(
+            AlertState::Cleared,
+            alerts.alert_list().next().unwrap().get_state()
+        );
+        if let Some(Device::Socket(socket)) = home.get_device_by_path_mut("R", "Heater") {
+            socket.set_current(15_f64).unwrap();
+        }
+        alerts.evaluate(&home, at(1), &events);
+        assert_eq!(
+            AlertState::Raised,
+            alerts.alert_list().next().unwrap().get_state()
+        );
+    }
+
+    #[test]
+    fn test_power_limit() {
+        let mut home = Home::restore();
+        let socket = Socket::new(220_f64, 8_f64, true).unwrap();
+        home.add_device("R", "Heater", socket.into());
+        let events = events::channel();
+        let mut alerts = Alerts::new();
+        alerts
+            .add("near limit", "R/Heater power > 90% limit")
+            .unwrap();
+        let state = |alerts: &Alerts| alerts.alert_list().next().unwrap().get_state();
+        // Not raised without a limit.
+        alerts.evaluate(&home, at(0), &events);
+        assert_eq!(AlertState::Cleared, state(&alerts));
+        let heater = home.get_device_by_path_mut("R", "Heater");
+        if let Some(Device::Socket(socket)) = heater {
+            socket.set_power_limit(Some(1900_f64)).unwrap();
+        }
+        alerts.evaluate(&home, at(1), &events);
+        assert_eq!(AlertState::Raised, state(&alerts));
+        // The threshold follows the limit.
+        let heater = home.get_device_by_path_mut("R", "Heater");
+        if let Some(Device::Socket(socket)) = heater {
+            socket.set_power_limit(Some(2200_f64)).unwrap();
+        }
+        alerts.evaluate(&home, at(2), &events);
+        assert_eq!(AlertState::Cleared, state(&alerts));
+    }
+
+    #[test]
+    fn test_device_removed() {
+        let mut home = Home::restore();
+        let events = events::channel();
+        let mut received = events.subscribe();
+        let mut alerts = Alerts::new();
+        alerts.add("hot", "R/T temperature > 30").unwrap();
+        set_temperature(&mut home, 31_f64);
+        alerts.evaluate(&home, at(0), &events);
+        assert!(received.try_recv().is_ok());
+        home.remove_device("R", "T");
+        alerts.evaluate(&home, at(1), &events);
+        assert!(matches!(
+            received.try_recv(),
+            Ok(Event::Alert {
+                state: AlertState::Cleared,
+                value: None,
+                ..
+            })
+        ));
+        let alert = alerts.alert_list().next().unwrap();
+        assert_eq!(Some(at(1)), alert.get_since());
+        // Cleared once only.
+        alerts.evaluate(&home, at(2), &events);
+        assert!(received.try_recv().is_err());
+        // A device of another kind under the same name has no temperature.
+        home.add_device("R", "T", Device::new_lock());
+        alerts.evaluate(&home, at(3), &events);
+        assert!(received.try_recv().is_err());
+    }
+}
@@ -0,0 +1,351 @@
+//! Append-only log of mutating requests.
+//!
+//! A persistent log appends entries to its file as
+//! `time///peer///user///command///target///old///new///outcome` and rotates it once it
+//! grows over the size limit: `audit.log` becomes `audit.log.1`, `audit.log.1` becomes
+//! `audit.log.2` and so on, the oldest file is dropped. Lines that can't be read are skipped.
+
+use crate::history::TIME_FORMAT;
+use crate::request_handler::SEPARATOR;
+use crate::storage::{escape, unescape};
+use chrono::NaiveDateTime;
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use tracing::warn;
+
+/// Entries kept by a log without a file.
+const MEMORY_ENTRIES: usize = 1000;
+const NONE: &str = "none";
+
+/// Commands changing the state of the server with the number of request fields naming the target.
+const MUTATING_COMMANDS: [(&str, usize); 27] = [
+    ("update device", 2),
+    ("add room", 1),
+    ("remove room", 1),
+    ("add device", 2),
+    ("remove device", 2),
+    ("set metadata", 2),
+    ("set room metadata", 1),
+    ("add zone", 1),
+    ("remove zone", 1),
+    ("place room", 2),
+    ("add group", 1),
+    ("remove group", 1),
+    ("group add", 3),
+    ("group remove", 3),
+    ("switch group", 1),
+    ("capture scene", 1),
+    ("apply scene", 1),
+    ("remove scene", 1),
+    ("add rule", 1),
+    ("remove rule", 1),
+    ("enable rule", 1),
+    ("disable rule", 1),
+    ("add schedule", 1),
+    ("remove schedule", 1),
+    ("add alert", 1),
+    ("remove alert", 1),
+    ("ack alert", 1),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub time: NaiveDateTime,
+    /// Address of the client.
+    pub peer: String,
+    /// Authenticated user, `None` for anonymous clients.
+    pub user: Option<String>,
+    pub command: String,
+    /// Request fields naming what is changed joined by `/`, e.g. `R/S` for a device.
+    pub target: String,
+    /// State of the target before and after the command, `None` if it has none.
+    pub old: Option<String>,
+    pub new: Option<String>,
+    /// `Ok` or the error message.
+    pub outcome: String,
+}
+
+/// Size limit of the log file and the number of rotated files kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rotation {
+    pub max_bytes: u64,
+    pub keep: usize,
+}
+
+#[derive(Debug)]
+pub struct AuditLog {
+    rotation: Rotation,
+    /// Entries of a log without a file, the oldest first.
+    entries: VecDeque<AuditEntry>,
+    file: Option<PathBuf>,
+}
+
+/// Number of request fields naming the target of the command, `None` if the command
+/// does not change anything.
+pub fn target_fields(command: &str) -> Option<usize> {
+    MUTATING_COMMANDS
+        .iter()
+        .find(|(mutating, _)| *mutating == command)
+        .map(|(_, fields)| *fields)
+}
+
+/// Whether the target is the scope itself or lies in it, comparing whole `/` segments.
+fn within(target: &str, scope: &str) -> bool {
+    target
+        .strip_prefix(scope)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+impl AuditEntry {
+    pub fn fields(&self) -> Vec<String> {
+        let optional = |value: &Option<String>| value.clone().unwrap_or_else(|| NONE.into());
+        vec![
+            self.time.format(TIME_FORMAT).to_string(),
+            self.peer.clone(),
+            optional(&self.user),
+            self.command.clone(),
+            self.target.clone(),
+            optional(&self.old),
+            optional(&self.new),
+            self.outcome.clone(),
+        ]
+    }
+
+    pub fn from_fields<'a>(fields: &mut impl Iterator<Item = &'a str>) -> Option<Self> {
+        let mut next = || fields.next().map(String::from);
+        let optional = |value: String| Some(value).filter(|value| value != NONE);
+        Some(Self {
+            time: NaiveDateTime::parse_from_str(&next()?, TIME_FORMAT).ok()?,
+            peer: next()?,
+            user: optional(next()?),
+            command: next()?,
+            target: next()?,
+            old: optional(next()?),
+            new: optional(next()?),
+            outcome: next()?,
+        })
+    }
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self {
+            max_bytes: 1024 * 1024,
+            keep: 5,
+        }
+    }
+}
+
+impl AuditLog {
+    pub fn new(rotation: Rotation) -> Self {
+        Self {
+            rotation,
+            entries: VecDeque::new(),
+            file: None,
+        }
+    }
+
+    /// Keeps entries in the file instead of memory.
+    pub fn persistent(mut self, file: PathBuf) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    pub fn record(&mut self, entry: AuditEntry) -> Result<(), String> {
+        let Some(file) = &self.file else {
+            if self.entries.len() == MEMORY_ENTRIES {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(entry);
+            return Ok(());
+        };
+        let fields: Vec<_> = entry.fields().iter().map(|field| escape(field)).collect();
+        let line = fields.join(SEPARATOR) + "\n";
+        let size = fs::metadata(file).map(|meta| meta.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 > self.rotation.max_bytes {
+            self.rotate()?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(|e| format!("Can't write '{}': {e}", file.display()))
+    }
+
+    /// Entries within the time range, targets within `target` if it is given, the oldest
+    /// first. `R` matches the targets `R` and `R/S` but not `R2`.
+    pub fn entries(
+        &self,
+        from: Option<NaiveDateTime>,
+        to: Option<NaiveDateTime>,
+        target: Option<&str>,
+    ) -> Result<Vec<AuditEntry>, String> {
+        let entries = match &self.file {
+            Some(_) => self.read()?,
+            None => self.entries.iter().cloned().collect(),
+        };
+        Ok(entries
+            .into_iter()
+            .filter(|entry| from.is_none_or(|from| entry.time >= from))
+            .filter(|entry| to.is_none_or(|to| entry.time < to))
+            .filter(|entry| target.is_none_or(|target| within(&entry.target, target)))
+            .collect())
+    }
+
+    fn rotated(&self, number: usize) -> Option<PathBuf> {
+        let mut name = OsString::from(self.file.as_ref()?);
+        name.push(format!(".{number}"));
+        Some(name.into())
+    }
+
+    fn rotate(&self) -> Result<(), String> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let rename = |from: &PathBuf, to: &PathBuf| match fs::rename(from, to) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Can't rotate '{}': {e}", from.display()))
+            }
+            _ => Ok(()),
+        };
+        if self.rotation.keep == 0 {
+            return fs::remove_file(file)
+                .map_err(|e| format!("Can't rotate '{}': {e}", file.display()));
+        }
+        for number in (1..self.rotation.keep).rev() {
+            if let (Some(from), Some(to)) = (self.rotated(number), self.rotated(number + 1)) {
+                rename(&from, &to)?;
+            }
+        }
+        match self.rotated(1) {
+            Some(to) => rename(file, &to),
+            None => Ok(()),
+        }
+    }
+
+    /// Entries of the rotated files and the current one.
+    fn read(&self) -> Result<Vec<AuditEntry>, String> {
+        let mut files: Vec<_> = (1..=self.rotation.keep)
+            .rev()
+            .filter_map(|number| self.rotated(number))
+            .collect();
+        files.extend(self.file.clone());
+        let mut entries = vec![];
+        for file in files {
+            let text = match fs::read_to_string(&file) {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("Can't read '{}': {e}", file.display())),
+            };
+            for line in text.lines().filter(|line| !line.trim().is_empty()) {
+                let fields: Vec<_> = line.split(SEPARATOR).map(unescape).collect();
+                match AuditEntry::from_fields(&mut fields.iter().map(String::as_str)) {
+                    Some(entry) => entries.push(entry),
+                    None => warn!(line, "bad audit line"),
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(second: u32, target: &str) -> AuditEntry {
+        AuditEntry {
+            time: chrono::NaiveDate::from_ymd_opt(2022, 1, 31)
+                .and_then(|date| date.and_hms_opt(10, 0, second))
+                .unwrap(),
+            peer: "127.0.0.1:50000".into(),
+            user: None,
+            command: "update device".into(),
+            target: target.into(),
+            old: Some("socket off 0 220".into()),
+            new: Some("socket on 0 220".into()),
+            outcome: "Ok".into(),
+        }
+    }
+
+    #[test]
+    fn test_fields() {
+        let entry = entry(0, "R/S");
+        let line = entry.fields().join(SEPARATOR);
+        assert_eq!(
+            Some(entry),
+            AuditEntry::from_fields(&mut line.split(SEPARATOR))
+        );
+        assert_eq!(Some(2), target_fields("update device"));
+        assert_eq!(None, target_fields("device list"));
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut log = AuditLog::new(Rotation::default());
+        for second in 0..3 {
+            log.record(entry(second, "R/S")).unwrap();
+        }
+        log.record(entry(3, "Kitchen/S")).unwrap();
+        log.record(entry(4, "R2")).unwrap();
+        assert_eq!(5, log.entries(None, None, None).unwrap().len());
+        assert_eq!(1, log.entries(None, None, Some("Kitchen")).unwrap().len());
+        assert_eq!(3, log.entries(None, None, Some("R")).unwrap().len());
+        assert_eq!(3, log.entries(None, None, Some("R/S")).unwrap().len());
+        assert_eq!(0, log.entries(None, None, Some("R/")).unwrap().len());
+        assert_eq!(0, log.entries(None, None, Some("Kitch")).unwrap().len());
+        let from = Some(entry(1, "").time);
+        let to = Some(entry(3, "").time);
+        assert_eq!(2, log.entries(from, to, None).unwrap().len());
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("audit-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("audit.log");
+        let line_bytes = entry(0, "R/S").fields().join(SEPARATOR).len() as u64 + 1;
+        let rotation = Rotation {
+            max_bytes: 2 * line_bytes,
+            keep: 2,
+        };
+        let mut log = AuditLog::new(rotation).persistent(file.clone());
+        for second in 0..7 {
+            log.record(entry(second, "R/S")).unwrap();
+        }
+        // Two entries per file, the oldest file is dropped.
+        assert!(dir.join("audit.log.2").exists());
+        assert!(!dir.join("audit.log.3").exists());
+        let entries = log.entries(None, None, None).unwrap();
+        let seconds: Vec<_> = entries
+            .iter()
+            .map(|entry| chrono::Timelike::second(&entry.time))
+            .collect();
+        assert_eq!(vec![2, 3, 4, 5, 6], seconds);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_line_breaks() {
+        let dir = std::env::temp_dir().join(format!("audit-lines-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("audit.log");
+        let mut log = AuditLog::new(Rotation::default()).persistent(file.clone());
+        let mut broken = entry(0, "Two\nlines/S");
+        broken.new = Some(String::from("back\\slash"));
+        log.record(broken.clone()).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&file)
+            .and_then(|mut file| file.write_all(b"2022-01-31 10:00:01///cut\n"))
+            .unwrap();
+        log.record(entry(2, "R/S")).unwrap();
+        let entries = log.entries(None, None, None).unwrap();
+        assert_eq!(vec![broken, entry(2, "R/S")], entries);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
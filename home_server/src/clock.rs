@@ -0,0 +1,38 @@
+use chrono::{DateTime, FixedOffset, Local};
+
+/// Source of the current local time for rules and schedules.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<FixedOffset>;
+}
+
+/// Wall clock of the machine running the server.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<FixedOffset> {
+        Local::now().fixed_offset()
+    }
+}
+
+/// Clock which stands still until it is moved, for tests.
+#[cfg(test)]
+pub struct ManualClock(std::sync::Mutex<DateTime<FixedOffset>>);
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new(now: DateTime<FixedOffset>) -> Self {
+        Self(std::sync::Mutex::new(now))
+    }
+
+    pub fn advance(&self, elapsed: chrono::Duration) {
+        let mut now = self.0.lock().unwrap();
+        *now += elapsed;
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<FixedOffset> {
+        *self.0.lock().unwrap()
+    }
+}
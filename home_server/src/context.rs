@@ -0,0 +1,68 @@
+use crate::alerts::Alerts;
+use crate::audit::AuditLog;
+use crate::clock::Clock;
+use crate::events::{self, EventSender};
+use crate::history::History;
+use crate::metrics::Metrics;
+use crate::rules::Rules;
+use crate::schedule::Scheduler;
+use smart_home::home::Home;
+use std::sync::Arc;
+use tokio::sync::{Mutex, RwLock};
+
+/// State shared by connections and background tasks of the server.
+#[derive(Clone)]
+pub struct Context {
+    pub home: Arc<RwLock<Home>>,
+    pub rules: Arc<Mutex<Rules>>,
+    pub scheduler: Arc<Mutex<Scheduler>>,
+    pub history: Arc<Mutex<History>>,
+    pub alerts: Arc<Mutex<Alerts>>,
+    pub audit: Arc<Mutex<AuditLog>>,
+    pub events: EventSender,
+    pub metrics: Arc<Metrics>,
+    pub clock: Arc<dyn Clock>,
+}
+
+impl Context {
+    pub fn new(
+        home: Home,
+        rules: Rules,
+        scheduler: Scheduler,
+        history: History,
+        alerts: Alerts,
+        audit: AuditLog,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            home: Arc::new(RwLock::new(home)),
+            rules: Arc::new(Mutex::new(rules)),
+            scheduler: Arc::new(Mutex::new(scheduler)),
+            history: Arc::new(Mutex::new(history)),
+            alerts: Arc::new(Mutex::new(alerts)),
+            audit: Arc::new(Mutex::new(audit)),
+            events: events::channel(),
+            metrics: Arc::default(),
+            clock,
+        }
+    }
+
+    /// Restored home with in-memory state and the system clock.
+    #[cfg(test)]
+    pub fn for_tests() -> Self {
+        use crate::audit::Rotation;
+        use crate::clock::SystemClock;
+        use crate::history::Retention;
+
+        let clock = Arc::new(SystemClock);
+        Self::new(
+            Home::restore(),
+            Rules::new(),
+            Scheduler::new(clock.clone(), None),
+            History::new(Retention::default()),
+            Alerts::new(),
+            AuditLog::new(Rotation::default()),
+            clock,
+        )
+    }
+}
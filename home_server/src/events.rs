@@ -0,0 +1,91 @@
+use crate::alerts::AlertState;
+use smart_home::{group::DevicePath, home::Home, query::Field, smart_device::Device};
+use std::collections::BTreeMap;
+use tokio::sync::broadcast;
+
+const EVENTS_CAPACITY: usize = 64;
+
+/// Something that happened in the home which the outside world may want to know about.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// Sent by the `notify` action of an automation rule or a schedule.
+    Notification { source: String, message: String },
+    /// Sent when an alert is raised, acknowledged or cleared. The value is in °C, W or kWh.
+    Alert {
+        name: String,
+        path: DevicePath,
+        field: Field,
+        state: AlertState,
+        value: Option<f64>,
+    },
+    /// Sent when the state of a device changes, including by the simulation.
+    Device { path: DevicePath, device: Device },
+}
+
+pub type EventSender = broadcast::Sender<Event>;
+
+pub fn channel() -> EventSender {
+    broadcast::channel(EVENTS_CAPACITY).0
+}
+
+/// Finds devices whose state changed since the previous check.
+#[derive(Debug, Default)]
+pub struct DeviceWatch {
+    /// States seen by the previous check.
+    devices: Option<BTreeMap<DevicePath, Device>>,
+}
+
+impl DeviceWatch {
+    /// Sends an event per changed or added device. Nothing is sent on the first check.
+    pub fn check(&mut self, home: &Home, events: &EventSender) {
+        let mut devices = BTreeMap::new();
+        for room in home.room_names_list() {
+            for name in home.device_names_list(room).unwrap_or_default() {
+                if let Some(device) = home.get_device_by_path(room, name) {
+                    devices.insert(DevicePath::new(room, name), device.clone());
+                }
+            }
+        }
+        if let Some(previous) = &self.devices {
+            for (path, device) in &devices {
+                if previous.get(path) != Some(device) {
+                    // No subscribers is not an error.
+                    let _ = events.send(Event::Device {
+                        path: path.clone(),
+                        device: device.clone(),
+                    });
+                }
+            }
+        }
+        self.devices = Some(devices);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smart_home::smart_device::Thermometer;
+
+    #[test]
+    fn test_device_watch() {
+        let mut home = Home::restore();
+        let events = channel();
+        let mut received = events.subscribe();
+        let mut watch = DeviceWatch::default();
+        watch.check(&home, &events);
+        watch.check(&home, &events);
+        assert!(received.try_recv().is_err());
+
+        let thermometer = Device::Thermometer(Thermometer::new(42.5));
+        *home.get_device_by_path_mut("R", "T").unwrap() = thermometer.clone();
+        watch.check(&home, &events);
+        assert_eq!(
+            Ok(Event::Device {
+                path: DevicePath::new("R", "T"),
+                device: thermometer,
+            }),
+            received.try_recv()
+        );
+        assert!(received.try_recv().is_err());
+    }
+}
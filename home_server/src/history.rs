@@ -0,0 +1,408 @@
+//! Time series of device readings.
+//!
+//! A reading is recorded whenever it changes. Points older than the raw retention are
+//! downsampled to min/max/avg per interval, points older than the full retention are dropped.
+//! A persistent history appends points to its file as `room///device///kind///point`
+//! and rewrites the file when it downsamples. Lines that can't be read are skipped.
+
+use crate::request_handler::SEPARATOR;
+use crate::storage::{escape, unescape};
+use chrono::{NaiveDateTime, TimeDelta};
+use smart_home::{group::DevicePath, home::Home, smart_device::Device};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use tracing::warn;
+
+pub const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Readings of a time interval starting at `time`, a single reading if `count` is 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub time: NaiveDateTime,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Retention {
+    /// Age after which points are dropped.
+    pub keep: TimeDelta,
+    /// Age after which points are downsampled.
+    pub raw: TimeDelta,
+    /// Interval of downsampled points.
+    pub step: TimeDelta,
+    /// How often downsampling runs.
+    pub compact_every: TimeDelta,
+}
+
+#[derive(Debug)]
+pub struct Series {
+    /// Kind of the device, it tells the unit of the readings.
+    kind: String,
+    points: VecDeque<Point>,
+}
+
+#[derive(Debug)]
+pub struct History {
+    series: HashMap<DevicePath, Series>,
+    last: HashMap<DevicePath, f64>,
+    retention: Retention,
+    compacted: Option<NaiveDateTime>,
+    file: Option<PathBuf>,
+}
+
+/// Temperature in °C for a thermometer, power in W for a socket, position in % for
+/// a window covering, 1 for a locked lock and 0 otherwise.
+pub fn reading(device: &Device) -> Option<f64> {
+    match device {
+        Device::Thermometer(thermometer) => Some(thermometer.get_temperature()),
+        Device::Socket(socket) => Some(socket.get_current_power()),
+        Device::WindowCovering(covering) => Some(covering.get_position()),
+        Device::Lock(lock) => Some(if lock.is_locked() { 1_f64 } else { 0_f64 }),
+        _ => None,
+    }
+}
+
+impl Point {
+    pub fn new(time: NaiveDateTime, value: f64) -> Self {
+        Self {
+            time,
+            min: value,
+            max: value,
+            avg: value,
+            count: 1,
+        }
+    }
+
+    fn merge(&mut self, other: &Point) {
+        let count = self.count + other.count;
+        self.avg = (self.avg * f64::from(self.count) + other.avg * f64::from(other.count))
+            / f64::from(count);
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.count = count;
+    }
+
+    /// `time///min///max///avg///count`
+    pub fn fields(&self) -> Vec<String> {
+        vec![
+            self.time.format(TIME_FORMAT).to_string(),
+            self.min.to_string(),
+            self.max.to_string(),
+            self.avg.to_string(),
+            self.count.to_string(),
+        ]
+    }
+
+    /// Inverse of [`Point::fields`].
+    pub fn from_fields<'a>(fields: &mut impl Iterator<Item = &'a str>) -> Option<Self> {
+        Some(Self {
+            time: NaiveDateTime::parse_from_str(fields.next()?, TIME_FORMAT).ok()?,
+            min: fields.next()?.parse().ok()?,
+            max: fields.next()?.parse().ok()?,
+            avg: fields.next()?.parse().ok()?,
+            count: fields.next()?.parse().ok()?,
+        })
+    }
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            keep: TimeDelta::days(30),
+            raw: TimeDelta::days(1),
+            step: TimeDelta::hours(1),
+            compact_every: TimeDelta::minutes(10),
+        }
+    }
+}
+
+impl Series {
+    pub fn get_kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// Points in `[from, to)`, merged per `step` if it is given.
+    pub fn points(
+        &self,
+        from: Option<NaiveDateTime>,
+        to: Option<NaiveDateTime>,
+        step: Option<TimeDelta>,
+    ) -> Vec<Point> {
+        let points = self.points.iter().filter(|point| {
+            from.is_none_or(|from| point.time >= from) && to.is_none_or(|to| point.time < to)
+        });
+        match step {
+            Some(step) => downsample(points, step),
+            None => points.copied().collect(),
+        }
+    }
+}
+
+impl History {
+    pub fn new(retention: Retention) -> Self {
+        Self {
+            series: HashMap::new(),
+            last: HashMap::new(),
+            retention,
+            compacted: None,
+            file: None,
+        }
+    }
+
+    /// Keeps points in the file and loads the ones saved there before.
+    pub fn persistent(mut self, file: PathBuf) -> Result<Self, String> {
+        let text = match fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Can't read '{}': {e}", file.display())),
+        };
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let mut fields = line.split(SEPARATOR);
+            let (Some(room), Some(device), Some(kind), Some(point)) = (
+                fields.next(),
+                fields.next(),
+                fields.next(),
+                Point::from_fields(&mut fields),
+            ) else {
+                warn!(line, "bad history line");
+                continue;
+            };
+            let path = DevicePath::new(&unescape(room), &unescape(device));
+            self.push(path, kind, point);
+        }
+        self.file = Some(file);
+        Ok(self)
+    }
+
+    pub fn get_series(&self, room_name: &str, device_name: &str) -> Option<&Series> {
+        self.series.get(&DevicePath::new(room_name, device_name))
+    }
+
+    /// Records readings of the devices which have changed since the previous call.
+    pub fn record(&mut self, home: &Home, now: NaiveDateTime) -> Result<(), String> {
+        let mut lines = String::new();
+        for room_name in home.room_names_list() {
+            for device_name in home.device_names_list(room_name).unwrap_or_default() {
+                let Some(device) = home.get_device_by_path(room_name, device_name) else {
+                    continue;
+                };
+                let Some(value) = reading(device) else {
+                    continue;
+                };
+                let path = DevicePath::new(room_name, device_name);
+                if self.last.get(&path) == Some(&value) {
+                    continue;
+                }
+                let point = Point::new(now, value);
+                if self.file.is_some() {
+                    lines.push_str(&line(&path, device.kind(), &point));
+                }
+                self.push(path, device.kind(), point);
+            }
+        }
+        if !lines.is_empty() {
+            self.append(&lines)?;
+        }
+        if self
+            .compacted
+            .is_none_or(|compacted| now - compacted >= self.retention.compact_every)
+        {
+            self.compact(now)?;
+        }
+        Ok(())
+    }
+
+    /// Drops expired points and downsamples old ones.
+    pub fn compact(&mut self, now: NaiveDateTime) -> Result<(), String> {
+        let keep_from = now - self.retention.keep;
+        let raw_from = now - self.retention.raw;
+        for series in self.series.values_mut() {
+            let recent = series.points.partition_point(|point| point.time < raw_from);
+            let old: Vec<Point> = series
+                .points
+                .drain(..recent)
+                .filter(|point| point.time >= keep_from)
+                .collect();
+            for point in downsample(old.iter(), self.retention.step)
+                .into_iter()
+                .rev()
+            {
+                series.points.push_front(point);
+            }
+        }
+        self.compacted = Some(now);
+        self.save()
+    }
+
+    fn push(&mut self, path: DevicePath, kind: &str, point: Point) {
+        self.last.insert(path.clone(), point.avg);
+        let series = self.series.entry(path).or_insert_with(|| Series {
+            kind: kind.into(),
+            points: VecDeque::new(),
+        });
+        series.kind = kind.into();
+        series.points.push_back(point);
+    }
+
+    fn append(&self, lines: &str) -> Result<(), String> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file)
+            .and_then(|mut file| file.write_all(lines.as_bytes()))
+            .map_err(|e| format!("Can't write '{}': {e}", file.display()))
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let mut text = String::new();
+        for (path, series) in &self.series {
+            for point in &series.points {
+                text.push_str(&line(path, &series.kind, point));
+            }
+        }
+        let temporary = file.with_extension("tmp");
+        fs::write(&temporary, text)
+            .and_then(|_| fs::rename(&temporary, file))
+            .map_err(|e| format!("Can't write '{}': {e}", file.display()))
+    }
+}
+
+fn line(path: &DevicePath, kind: &str, point: &Point) -> String {
+    let mut fields = vec![escape(&path.room), escape(&path.device), kind.into()];
+    fields.extend(point.fields());
+    fields.join(SEPARATOR) + "\n"
+}
+
+/// Merges points per interval of `step` counted from the Unix epoch.
+fn downsample<'a>(points: impl Iterator<Item = &'a Point>, step: TimeDelta) -> Vec<Point> {
+    let step = step.num_seconds().max(1);
+    let mut buckets: BTreeMap<i64, Point> = BTreeMap::new();
+    for point in points {
+        let seconds = point.time.and_utc().timestamp();
+        let start = seconds - seconds.rem_euclid(step);
+        buckets
+            .entry(start)
+            .and_modify(|bucket| bucket.merge(point))
+            .or_insert_with(|| Point {
+                time: chrono::DateTime::from_timestamp(start, 0)
+                    .map(|time| time.naive_utc())
+                    .unwrap_or(point.time),
+                ..*point
+            });
+    }
+    buckets.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, TIME_FORMAT).unwrap()
+    }
+
+    fn set_temperature(home: &mut Home, temperature: f64) {
+        if let Some(Device::Thermometer(t)) = home.get_device_by_path_mut("R", "T") {
+            t.set_temperature(temperature);
+        }
+    }
+
+    #[test]
+    fn test_record_changes() {
+        let mut home = Home::restore();
+        let mut history = History::new(Retention::default());
+        set_temperature(&mut home, 20_f64);
+        history.record(&home, at("2022-01-31 10:00:00")).unwrap();
+        history.record(&home, at("2022-01-31 10:00:01")).unwrap();
+        set_temperature(&mut home, 21_f64);
+        history.record(&home, at("2022-01-31 10:30:00")).unwrap();
+        set_temperature(&mut home, 25_f64);
+        history.record(&home, at("2022-01-31 11:10:00")).unwrap();
+        let series = history.get_series("R", "T").unwrap();
+        assert_eq!("thermometer", series.get_kind());
+        assert_eq!(3, series.points(None, None, None).len());
+        assert_eq!(
+            vec![Point::new(at("2022-01-31 10:30:00"), 21_f64)],
+            series.points(
+                Some(at("2022-01-31 10:10:00")),
+                Some(at("2022-01-31 11:10:00")),
+                None
+            )
+        );
+        let hourly = series.points(None, None, Some(TimeDelta::hours(1)));
+        assert_eq!(
+            vec![
+                Point {
+                    time: at("2022-01-31 10:00:00"),
+                    min: 20_f64,
+                    max: 21_f64,
+                    avg: 20.5,
+                    count: 2
+                },
+                Point::new(at("2022-01-31 11:00:00"), 25_f64)
+            ],
+            hourly
+        );
+        assert_eq!(1, history.get_series("R", "L").unwrap().points.len());
+    }
+
+    #[test]
+    fn test_retention_and_persistence() {
+        let file = std::env::temp_dir().join(format!("history-{}.txt", std::process::id()));
+        let retention = Retention {
+            keep: TimeDelta::days(2),
+            ..Default::default()
+        };
+        let mut home = Home::restore();
+        let mut history = History::new(retention).persistent(file.clone()).unwrap();
+        for (time, temperature) in [
+            ("2022-01-28 10:00:00", 10_f64),
+            ("2022-01-30 10:10:00", 20_f64),
+            ("2022-01-30 10:20:00", 22_f64),
+            ("2022-01-31 10:00:00", 18_f64),
+        ] {
+            set_temperature(&mut home, temperature);
+            history.record(&home, at(time)).unwrap();
+        }
+        history.compact(at("2022-01-31 11:00:00")).unwrap();
+        let expected = vec![
+            Point {
+                time: at("2022-01-30 10:00:00"),
+                min: 20_f64,
+                max: 22_f64,
+                avg: 21_f64,
+                count: 2,
+            },
+            Point::new(at("2022-01-31 10:00:00"), 18_f64),
+        ];
+        let series = history.get_series("R", "T").unwrap();
+        assert_eq!(expected, series.points(None, None, None));
+
+        // A room named with a line break stays on its line, a broken line is skipped.
+        home.add_room("Two\nlines");
+        home.add_device("Two\nlines", "T", Device::new_thermometer());
+        history.record(&home, at("2022-01-31 10:30:00")).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&file)
+            .and_then(|mut file| file.write_all(b"R///T\n"))
+            .unwrap();
+
+        let restored = History::new(retention).persistent(file.clone()).unwrap();
+        let series = restored.get_series("R", "T").unwrap();
+        assert_eq!(expected, series.points(None, None, None));
+        assert!(restored.get_series("Two\nlines", "T").is_some());
+        fs::remove_file(file).unwrap();
+    }
+}
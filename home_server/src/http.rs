@@ -0,0 +1,241 @@
+//! Just enough HTTP/1.1 for the metrics endpoint and the REST gateway,
+//! a single request per connection.
+
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, warn};
+
+/// Largest accepted request line and headers.
+const MAX_HEAD: u64 = 8 * 1024;
+/// Largest accepted request body.
+const MAX_BODY: usize = 64 * 1024;
+/// How long the client may take to send the request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    /// Percent-decoded path segments, the query is dropped.
+    pub segments: Vec<String>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &'static str, body: String) -> Self {
+        Self {
+            status,
+            content_type,
+            body,
+        }
+    }
+
+    pub fn json(status: u16, body: &serde_json::Value) -> Self {
+        Self::new(status, "application/json", body.to_string())
+    }
+
+    /// JSON `{"error": message}`.
+    pub fn error(status: u16, message: &str) -> Self {
+        Self::json(status, &serde_json::json!({ "error": message }))
+    }
+}
+
+/// Calls `handle` for every request, the address is the one of the client.
+pub async fn serve<H, F>(listener: TcpListener, handle: H)
+where
+    H: Fn(Request, SocketAddr) -> F + Clone + Send + 'static,
+    F: Future<Output = Response> + Send,
+{
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!(error = %e, "HTTP connection failed");
+                continue;
+            }
+        };
+        let handle = handle.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, peer, handle).await {
+                debug!(%peer, error = %e, "HTTP request failed");
+            }
+        });
+    }
+}
+
+async fn respond<H, F>(stream: TcpStream, peer: SocketAddr, handle: H) -> io::Result<()>
+where
+    H: Fn(Request, SocketAddr) -> F,
+    F: Future<Output = Response>,
+{
+    let mut stream = BufReader::new(stream);
+    let response = match tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream)).await {
+        Ok(request) => match request? {
+            Ok(request) => handle(request, peer).await,
+            Err(response) => response,
+        },
+        Err(_) => Response::error(408, "Request timed out."),
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    );
+    let stream = stream.get_mut();
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// A malformed request is answered with the error response.
+async fn read_request(stream: &mut BufReader<TcpStream>) -> io::Result<Result<Request, Response>> {
+    let mut head = (&mut *stream).take(MAX_HEAD);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line).await?;
+    let mut length = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if head.read_line(&mut line).await? == 0 {
+            if head.limit() == 0 {
+                return Ok(Err(Response::error(431, "Request head is too large.")));
+            }
+            break;
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                match value.trim().parse() {
+                    Ok(value) => length = value,
+                    Err(_) => return Ok(Err(Response::error(400, "Bad Content-Length."))),
+                }
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Ok(Err(Response::error(413, "Request body is too large.")));
+    }
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Ok(Err(Response::error(400, "Bad request line.")));
+    };
+    let path = target.split('?').next().unwrap_or_default();
+    let Some(segments) = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(percent_decode)
+        .collect::<Option<Vec<_>>>()
+    else {
+        return Ok(Err(Response::error(400, "Bad path.")));
+    };
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).await?;
+    let Ok(body) = String::from_utf8(body) else {
+        return Ok(Err(Response::error(400, "Request body is not UTF-8.")));
+    };
+    Ok(Ok(Request {
+        method: method.into(),
+        segments,
+        body,
+    }))
+}
+
+fn percent_decode(segment: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut rest = segment.bytes();
+    while let Some(byte) = rest.next() {
+        match byte {
+            b'%' => {
+                let high = (rest.next()? as char).to_digit(16)?;
+                let low = (rest.next()? as char).to_digit(16)?;
+                bytes.push((high * 16 + low) as u8);
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+/// Sends a request and returns the status and the body of the response.
+#[cfg(test)]
+pub async fn send(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!(
+        "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (status, body.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(Some("Living room".into()), percent_decode("Living%20room"));
+        assert_eq!(
+            Some("Кухня".into()),
+            percent_decode("%D0%9A%D1%83%D1%85%D0%BD%D1%8F")
+        );
+        assert_eq!(None, percent_decode("%2"));
+        assert_eq!(None, percent_decode("%zz"));
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, |request: Request, _| async move {
+            let body = format!("{} {:?} {}", request.method, request.segments, request.body);
+            Response::new(200, "text/plain", body)
+        }));
+        assert_eq!(
+            (200, String::from("PUT [\"a b\", \"c\"] {}")),
+            send(addr, "PUT", "/a%20b/c?x=1", "{}").await
+        );
+        assert_eq!(400, send(addr, "GET", "/%zz", "").await.0);
+
+        // The head is cut off at the limit.
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut head = String::from("GET / HTTP/1.1\r\nX-Padding: ");
+        head.push_str(&"a".repeat(MAX_HEAD as usize - head.len()));
+        stream.write_all(head.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 431 "));
+    }
+}
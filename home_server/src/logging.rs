@@ -0,0 +1,60 @@
+//! Log output of the server and the `stp` crate.
+
+use std::str::FromStr;
+use tracing_subscriber::EnvFilter;
+
+/// Environment variable with the filter, e.g. `debug` or `info,stp=trace`.
+const LOG_VAR: &str = "HOME_SERVER_LOG";
+/// Environment variable with the output format, `human` or `json`.
+const LOG_FORMAT_VAR: &str = "HOME_SERVER_LOG_FORMAT";
+const DEFAULT_FILTER: &str = "info";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Human,
+    /// A JSON object per line.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Format::Human),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Unknown log format '{s}'.")),
+        }
+    }
+}
+
+/// Sets up the output configured by the environment, writes to stderr.
+pub fn init() -> Result<(), String> {
+    let filter = std::env::var(LOG_VAR).unwrap_or_else(|_| DEFAULT_FILTER.into());
+    let filter = EnvFilter::try_new(&filter).map_err(|e| format!("Bad log filter: {e}"))?;
+    let format = match std::env::var(LOG_FORMAT_VAR) {
+        Ok(format) => format.parse()?,
+        Err(_) => Format::default(),
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    let installed = match format {
+        Format::Human => builder.try_init(),
+        Format::Json => builder.json().with_current_span(true).try_init(),
+    };
+    installed.map_err(|e| format!("Can't set up logging: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(Ok(Format::Human), "human".parse());
+        assert_eq!(Ok(Format::Json), "json".parse());
+        assert!("xml".parse::<Format>().is_err());
+    }
+}
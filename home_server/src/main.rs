@@ -1,47 +1,366 @@
-use std::{error::Error, sync::Arc};
-use tokio::sync::RwLock;
+use std::{env, error::Error, fs, sync::Arc};
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast, RwLock},
+    time::{self, Duration, Instant},
+};
+use tracing::{debug, debug_span, error, info, info_span, trace, warn, Instrument};
 
+mod alerts;
+mod audit;
+mod clock;
+mod context;
+mod events;
+mod history;
+mod http;
+mod logging;
+mod metrics;
+mod mqtt;
 mod request_handler;
-use request_handler::{Handler, Request};
-use smart_home::home::Home;
+mod rest;
+mod rules;
+mod schedule;
+mod storage;
+mod sun;
+mod websocket;
+use alerts::Alerts;
+use audit::{AuditLog, Rotation};
+use clock::{Clock, SystemClock};
+use context::Context;
+use events::{DeviceWatch, Event};
+use history::{History, Retention};
+use request_handler::{
+    event_message, Handler, Request, BAD_COMMAND_RESPONSE, OK_RESPONSE, SEPARATOR, SUBSCRIBE,
+};
+use rules::Rules;
+use schedule::Scheduler;
+use smart_home::{home::Home, units::Units};
 use stp::server::{StpConnection, StpServer};
+use sun::Location;
+
+const SIMULATION_STEP: Duration = Duration::from_millis(100);
+const RULES_STEP: Duration = Duration::from_millis(500);
+const SCHEDULE_STEP: Duration = Duration::from_secs(1);
+const HISTORY_STEP: Duration = Duration::from_secs(1);
+const ALERTS_STEP: Duration = Duration::from_millis(500);
+const DEVICES_STEP: Duration = Duration::from_millis(500);
+/// Environment variable with the path of the automation rules file.
+const RULES_FILE_VAR: &str = "HOME_SERVER_RULES";
+/// Environment variable with the path of the file schedules are kept in.
+const SCHEDULES_FILE_VAR: &str = "HOME_SERVER_SCHEDULES";
+/// Environment variable with the path of the file device history is kept in.
+const HISTORY_FILE_VAR: &str = "HOME_SERVER_HISTORY";
+/// Environment variable with the path of the alerts file, `name///rule` per line.
+const ALERTS_FILE_VAR: &str = "HOME_SERVER_ALERTS";
+/// Environment variable with the path of the audit log file.
+const AUDIT_FILE_VAR: &str = "HOME_SERVER_AUDIT";
+/// Environment variable with the local port of the metrics endpoint.
+const METRICS_PORT_VAR: &str = "HOME_SERVER_METRICS_PORT";
+const DEFAULT_METRICS_PORT: u16 = 4084;
+/// Environment variable with the local port of the REST gateway.
+const HTTP_PORT_VAR: &str = "HOME_SERVER_HTTP_PORT";
+const DEFAULT_HTTP_PORT: u16 = 4085;
+/// Environment variable with the local port of the WebSocket bridge.
+const WS_PORT_VAR: &str = "HOME_SERVER_WS_PORT";
+const DEFAULT_WS_PORT: u16 = 4086;
+/// Environment variable with the `host:port` of the MQTT broker, the bridge is off without it.
+const MQTT_BROKER_VAR: &str = "HOME_SERVER_MQTT";
+/// Environment variable with the first level of MQTT topics.
+const MQTT_PREFIX_VAR: &str = "HOME_SERVER_MQTT_PREFIX";
+/// Environment variables with the coordinates of the home, degrees.
+const LATITUDE_VAR: &str = "HOME_SERVER_LATITUDE";
+const LONGITUDE_VAR: &str = "HOME_SERVER_LONGITUDE";
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let home = Arc::new(RwLock::new(Home::restore()));
+    logging::init()?;
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+    let scheduler = load_scheduler(Arc::clone(&clock))?;
+    let context = Context::new(
+        Home::restore(),
+        load_rules()?,
+        scheduler,
+        load_history()?,
+        load_alerts()?,
+        load_audit(),
+        clock,
+    );
+    tokio::spawn(simulate_devices(Arc::clone(&context.home)));
+    tokio::spawn(run_rules(context.clone()));
+    tokio::spawn(run_schedules(context.clone()));
+    tokio::spawn(record_history(context.clone()));
+    tokio::spawn(watch_alerts(context.clone()));
+    tokio::spawn(watch_devices(context.clone()));
+    tokio::spawn(print_events(context.events.subscribe()));
+    let metrics_listener = bind_local(METRICS_PORT_VAR, DEFAULT_METRICS_PORT).await?;
+    tokio::spawn(metrics::serve(
+        metrics_listener,
+        Arc::clone(&context.metrics),
+        Arc::clone(&context.home),
+    ));
+    let http_listener = bind_local(HTTP_PORT_VAR, DEFAULT_HTTP_PORT).await?;
+    tokio::spawn(rest::serve(http_listener, context.clone()));
+    let ws_listener = bind_local(WS_PORT_VAR, DEFAULT_WS_PORT).await?;
+    tokio::spawn(websocket::serve(ws_listener, context.clone()));
+    if let Some(config) = load_mqtt()? {
+        tokio::spawn(mqtt::run(config, context.clone()));
+    }
     let addr = String::from("127.0.0.1:4083");
-    let server = StpServer::bind(addr).await?;
+    let server = StpServer::bind(addr).await?.hooks(context.metrics.clone());
+    loop {
+        match server.accept().await {
+            Ok(connection) => work_with(connection, context.clone()),
+            // Already logged by `stp`, a bad client must not stop the server.
+            Err(_) => continue,
+        }
+    }
+}
+
+/// Listens on the local port given by the environment variable.
+async fn bind_local(port_var: &str, default_port: u16) -> Result<TcpListener, Box<dyn Error>> {
+    let port = match env::var(port_var) {
+        Ok(port) => port.parse()?,
+        Err(_) => default_port,
+    };
+    Ok(TcpListener::bind(("127.0.0.1", port)).await?)
+}
+
+fn load_mqtt() -> Result<Option<mqtt::Config>, Box<dyn Error>> {
+    let Ok(broker) = env::var(MQTT_BROKER_VAR) else {
+        return Ok(None);
+    };
+    let (host, port) = broker
+        .rsplit_once(':')
+        .ok_or_else(|| format!("MQTT broker '{broker}' must be host:port."))?;
+    let config = mqtt::Config::new(host, port.parse()?);
+    match env::var(MQTT_PREFIX_VAR) {
+        Ok(prefix) => Ok(Some(config.prefix(&prefix))),
+        Err(_) => Ok(Some(config)),
+    }
+}
+
+fn load_rules() -> Result<Rules, Box<dyn Error>> {
+    match env::var(RULES_FILE_VAR) {
+        Ok(path) => Ok(Rules::parse(&fs::read_to_string(path)?)?),
+        Err(_) => Ok(Rules::new()),
+    }
+}
+
+fn load_scheduler(clock: Arc<dyn Clock>) -> Result<Scheduler, Box<dyn Error>> {
+    let location = match (env::var(LATITUDE_VAR), env::var(LONGITUDE_VAR)) {
+        (Ok(latitude), Ok(longitude)) => Some(Location {
+            latitude: latitude.parse()?,
+            longitude: longitude.parse()?,
+        }),
+        _ => None,
+    };
+    let scheduler = Scheduler::new(clock, location);
+    match env::var(SCHEDULES_FILE_VAR) {
+        Ok(path) => Ok(scheduler.persistent(path.into())?),
+        Err(_) => Ok(scheduler),
+    }
+}
+
+fn load_history() -> Result<History, Box<dyn Error>> {
+    let history = History::new(Retention::default());
+    match env::var(HISTORY_FILE_VAR) {
+        Ok(path) => Ok(history.persistent(path.into())?),
+        Err(_) => Ok(history),
+    }
+}
+
+fn load_alerts() -> Result<Alerts, Box<dyn Error>> {
+    let mut alerts = Alerts::new();
+    if let Ok(path) = env::var(ALERTS_FILE_VAR) {
+        for line in fs::read_to_string(path)?.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (name, rule) = line.split_once(SEPARATOR).unwrap_or((line, ""));
+            alerts.add(name.trim(), rule)?;
+        }
+    }
+    Ok(alerts)
+}
+
+fn load_audit() -> AuditLog {
+    let audit = AuditLog::new(Rotation::default());
+    match env::var(AUDIT_FILE_VAR) {
+        Ok(path) => audit.persistent(path.into()),
+        Err(_) => audit,
+    }
+}
+
+/// Actuators (locks, window coverings) reach their commanded state over time,
+/// sockets meter consumed energy.
+async fn simulate_devices(home: Arc<RwLock<Home>>) {
+    let mut interval = time::interval(SIMULATION_STEP);
+    let mut last = interval.tick().await;
     loop {
-        let connection = server.accept().await?;
-        work_with(connection, Arc::clone(&home))?;
+        let now = interval.tick().await;
+        home.write().await.advance(now - last);
+        last = now;
     }
 }
 
-fn work_with(connection: StpConnection, home: Arc<RwLock<Home>>) -> Result<(), Box<dyn Error>> {
+async fn run_rules(context: Context) {
+    let mut interval = time::interval(RULES_STEP);
+    let mut last = interval.tick().await;
+    loop {
+        let now = interval.tick().await;
+        let mut rules = context.rules.lock().await;
+        let mut home = context.home.write().await;
+        rules.evaluate(
+            &mut home,
+            context.clock.now().naive_local(),
+            now - last,
+            &context.events,
+        );
+        last = now;
+    }
+}
+
+async fn run_schedules(context: Context) {
+    let mut interval = time::interval(SCHEDULE_STEP);
+    loop {
+        interval.tick().await;
+        let mut scheduler = context.scheduler.lock().await;
+        let mut home = context.home.write().await;
+        for (schedule, message) in scheduler.run_due(&mut home, &context.events) {
+            info!(schedule, message, "schedule ran");
+        }
+    }
+}
+
+async fn record_history(context: Context) {
+    let mut interval = time::interval(HISTORY_STEP);
+    loop {
+        interval.tick().await;
+        let mut history = context.history.lock().await;
+        let home = context.home.read().await;
+        if let Err(e) = history.record(&home, context.clock.now().naive_local()) {
+            error!(error = e, "can't record history");
+        }
+    }
+}
+
+async fn watch_alerts(context: Context) {
+    let mut interval = time::interval(ALERTS_STEP);
+    loop {
+        interval.tick().await;
+        let mut alerts = context.alerts.lock().await;
+        let home = context.home.read().await;
+        alerts.evaluate(&home, context.clock.now().naive_local(), &context.events);
+    }
+}
+
+async fn watch_devices(context: Context) {
+    let mut interval = time::interval(DEVICES_STEP);
+    let mut watch = DeviceWatch::default();
+    loop {
+        interval.tick().await;
+        watch.check(&*context.home.read().await, &context.events);
+    }
+}
+
+async fn print_events(mut events: broadcast::Receiver<Event>) {
+    loop {
+        match events.recv().await {
+            Ok(Event::Notification { source, message }) => info!(source, message, "notification"),
+            Ok(Event::Alert {
+                name, path, state, ..
+            }) => warn!(alert = name, device = %path, state = state.as_str(), "alert"),
+            Ok(Event::Device { path, .. }) => trace!(device = %path, "device changed"),
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+fn work_with(connection: StpConnection, context: Context) {
     let addr = match connection.peer_addr() {
         Ok(addr) => addr.to_string(),
         Err(_) => String::from("Unknown addr"),
     };
-    println!("connection from: {}", addr);
-
-    tokio::spawn(async move {
-        if handle_connection(connection, home).await.is_err() {
-            eprintln!("Client disconnected: {}", addr);
+    let span = info_span!("connection", peer = %addr);
+    tokio::spawn(
+        async move {
+            info!("connected");
+            match handle_connection(connection, context.clone(), &addr).await {
+                Ok(()) => info!("disconnected"),
+                Err(e) if is_disconnect(e.as_ref()) => info!("disconnected"),
+                Err(e) => {
+                    context.metrics.error("connection");
+                    warn!(error = %e, "connection failed");
+                }
+            }
         }
-    });
-    Ok(())
+        .instrument(span),
+    );
+}
+
+/// Whether the peer has just closed the connection.
+fn is_disconnect(error: &(dyn Error + 'static)) -> bool {
+    let io = match error.downcast_ref::<stp::error::RecvError>() {
+        Some(stp::error::RecvError::Io(io)) => io,
+        _ => return false,
+    };
+    io.kind() == std::io::ErrorKind::UnexpectedEof
 }
 
 async fn handle_connection(
     connection: StpConnection,
-    home: Arc<RwLock<Home>>,
+    context: Context,
+    addr: &str,
 ) -> Result<(), Box<dyn Error>> {
-    let mut handler = Handler::new(home);
+    let mut handler = Handler::new(&context, addr);
     loop {
         let req_str = connection.recv_request().await?;
+        if req_str.trim() == SUBSCRIBE {
+            // Subscribed connections only receive events from now on.
+            let events = context.events.subscribe();
+            connection.send_response(OK_RESPONSE).await?;
+            info!("subscribed");
+            return push_events(connection, events, handler.get_units()).await;
+        }
         let mut req = Request::new(&req_str);
-        connection
-            .send_response(handler.respond(&mut req).await)
-            .await?;
+        let command = req.clone().next().unwrap_or_default();
+        let span = debug_span!("request", command);
+        let response = async {
+            let started = Instant::now();
+            let response = handler.respond(&mut req).await;
+            let latency = started.elapsed();
+            let ok = response.starts_with(OK_RESPONSE);
+            // Whatever clients send must not add metrics.
+            let known = if response == BAD_COMMAND_RESPONSE {
+                "unknown"
+            } else {
+                command
+            };
+            context.metrics.request(known, latency, ok);
+            debug!(latency_us = latency.as_micros() as u64, ok, "handled");
+            response
+        }
+        .instrument(span)
+        .await;
+        connection.send_response(response).await?;
+    }
+}
+
+async fn push_events(
+    connection: StpConnection,
+    mut events: broadcast::Receiver<Event>,
+    units: Units,
+) -> Result<(), Box<dyn Error>> {
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                connection
+                    .send_response(event_message(&event, &units))
+                    .await?
+            }
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        }
     }
 }
@@ -0,0 +1,284 @@
+//! Server metrics in the Prometheus text format, served over HTTP at `/metrics`.
+
+use crate::http::{self, Request, Response};
+use smart_home::{home::Home, smart_device::Device};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use stp::hooks::StpHooks;
+use tokio::net::TcpListener;
+use tokio::sync::RwLock;
+
+/// Upper bounds of the request latency buckets, seconds.
+const LATENCY_BUCKETS: [f64; 9] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0];
+const METRICS_PATH: &str = "metrics";
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    connections: AtomicI64,
+    received_bytes: AtomicU64,
+    sent_bytes: AtomicU64,
+    requests: Mutex<BTreeMap<String, u64>>,
+    /// Error counts by type: `handshake`, `connection` or `response`.
+    errors: Mutex<BTreeMap<&'static str, u64>>,
+    latencies: Mutex<BTreeMap<String, Histogram>>,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|le| seconds <= *le) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+impl Metrics {
+    /// Counts a handled request, `ok` is false for an error response.
+    pub fn request(&self, command: &str, latency: Duration, ok: bool) {
+        *lock(&self.requests).entry(command.into()).or_default() += 1;
+        lock(&self.latencies)
+            .entry(command.into())
+            .or_default()
+            .observe(latency.as_secs_f64());
+        if !ok {
+            self.error("response");
+        }
+    }
+
+    pub fn error(&self, kind: &'static str) {
+        *lock(&self.errors).entry(kind).or_default() += 1;
+    }
+
+    pub fn render(&self, home: &Home) -> String {
+        let mut text = String::new();
+        let header = |text: &mut String, name: &str, kind: &str, help: &str| {
+            writeln!(text, "# HELP {name} {help}").unwrap_or_default();
+            writeln!(text, "# TYPE {name} {kind}").unwrap_or_default();
+        };
+        header(
+            &mut text,
+            "home_server_connections",
+            "gauge",
+            "Open STP connections.",
+        );
+        let connections = self.connections.load(Ordering::Relaxed);
+        writeln!(text, "home_server_connections {connections}").unwrap_or_default();
+        for (name, bytes, help) in [
+            (
+                "home_server_received_bytes_total",
+                &self.received_bytes,
+                "Bytes of received STP frames.",
+            ),
+            (
+                "home_server_sent_bytes_total",
+                &self.sent_bytes,
+                "Bytes of sent STP frames.",
+            ),
+        ] {
+            header(&mut text, name, "counter", help);
+            writeln!(text, "{name} {}", bytes.load(Ordering::Relaxed)).unwrap_or_default();
+        }
+        header(
+            &mut text,
+            "home_server_requests_total",
+            "counter",
+            "Handled requests by command.",
+        );
+        for (command, count) in lock(&self.requests).iter() {
+            let command = escape(command);
+            writeln!(
+                text,
+                "home_server_requests_total{{command=\"{command}\"}} {count}"
+            )
+            .unwrap_or_default();
+        }
+        header(
+            &mut text,
+            "home_server_errors_total",
+            "counter",
+            "Errors by type.",
+        );
+        for (kind, count) in lock(&self.errors).iter() {
+            writeln!(text, "home_server_errors_total{{type=\"{kind}\"}} {count}")
+                .unwrap_or_default();
+        }
+        let name = "home_server_request_duration_seconds";
+        header(&mut text, name, "histogram", "Request latency by command.");
+        for (command, histogram) in lock(&self.latencies).iter() {
+            let command = escape(command);
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                writeln!(
+                    text,
+                    "{name}_bucket{{command=\"{command}\",le=\"{le}\"}} {cumulative}"
+                )
+                .unwrap_or_default();
+            }
+            let count = histogram.count;
+            writeln!(
+                text,
+                "{name}_bucket{{command=\"{command}\",le=\"+Inf\"}} {count}"
+            )
+            .unwrap_or_default();
+            writeln!(
+                text,
+                "{name}_sum{{command=\"{command}\"}} {}",
+                histogram.sum
+            )
+            .unwrap_or_default();
+            writeln!(text, "{name}_count{{command=\"{command}\"}} {count}").unwrap_or_default();
+        }
+        header(
+            &mut text,
+            "home_server_temperature_celsius",
+            "gauge",
+            "Thermometer readings.",
+        );
+        let mut devices = vec![];
+        for room in home.room_names_list() {
+            for device in home.device_names_list(room).unwrap_or_default() {
+                if let Some(state) = home.get_device_by_path(room, device) {
+                    devices.push((room, device, state));
+                }
+            }
+        }
+        devices.sort_by(|left, right| (left.0, left.1).cmp(&(right.0, right.1)));
+        for (room, device, state) in &devices {
+            if let Device::Thermometer(thermometer) = state {
+                let labels = device_labels(room, device);
+                let temperature = thermometer.get_temperature();
+                writeln!(
+                    text,
+                    "home_server_temperature_celsius{{{labels}}} {temperature}"
+                )
+                .unwrap_or_default();
+            }
+        }
+        header(
+            &mut text,
+            "home_server_socket_power_watts",
+            "gauge",
+            "Power drawn through sockets.",
+        );
+        for (room, device, state) in &devices {
+            if let Device::Socket(socket) = state {
+                let labels = device_labels(room, device);
+                let power = socket.get_current_power();
+                writeln!(text, "home_server_socket_power_watts{{{labels}}} {power}")
+                    .unwrap_or_default();
+            }
+        }
+        text
+    }
+}
+
+impl StpHooks for Metrics {
+    fn connection_opened(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn connection_closed(&self) {
+        self.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn handshake_failed(&self) {
+        self.error("handshake");
+    }
+
+    fn frame_sent(&self, bytes: usize) {
+        self.sent_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn frame_received(&self, bytes: usize) {
+        self.received_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// Answers `GET /metrics` with the metrics and anything else with 404.
+pub async fn serve(listener: TcpListener, metrics: Arc<Metrics>, home: Arc<RwLock<Home>>) {
+    http::serve(listener, move |request: Request, _| {
+        let metrics = Arc::clone(&metrics);
+        let home = Arc::clone(&home);
+        async move {
+            match (request.method.as_str(), request.segments.as_slice()) {
+                ("GET", [path]) if path == METRICS_PATH => {
+                    let body = metrics.render(&*home.read().await);
+                    Response::new(200, "text/plain; version=0.0.4", body)
+                }
+                _ => Response::new(404, "text/plain", String::new()),
+            }
+        }
+    })
+    .await
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // Counters stay usable even if a panic happened while they were updated.
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn device_labels(room: &str, device: &str) -> String {
+    format!("room=\"{}\",device=\"{}\"", escape(room), escape(device))
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.connection_opened();
+        metrics.frame_received(10);
+        metrics.request("room list", Duration::from_micros(300), true);
+        metrics.request("get device", Duration::from_millis(2), false);
+        let text = metrics.render(&Home::restore());
+        assert!(text.contains("home_server_connections 1\n"));
+        assert!(text.contains("home_server_received_bytes_total 10\n"));
+        assert!(text.contains("home_server_requests_total{command=\"room list\"} 1\n"));
+        assert!(text.contains("home_server_errors_total{type=\"response\"} 1\n"));
+        assert!(text.contains(
+            "home_server_request_duration_seconds_bucket{command=\"get device\",le=\"0.001\"} 0\n"
+        ));
+        assert!(text.contains(
+            "home_server_request_duration_seconds_bucket{command=\"get device\",le=\"0.005\"} 1\n"
+        ));
+        assert!(text.contains("home_server_temperature_celsius{room=\"R\",device=\"T\"}"));
+        assert!(text.contains("home_server_socket_power_watts{room=\"R\",device=\"S\"}"));
+        assert_eq!("a\\\"b\\\\", escape("a\"b\\"));
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let home = Arc::new(RwLock::new(Home::restore()));
+        tokio::spawn(serve(listener, Arc::new(Metrics::default()), home));
+        let (status, body) = http::send(addr, "GET", "/metrics", "").await;
+        assert_eq!(200, status);
+        assert!(body.contains("# TYPE home_server_connections gauge\n"));
+        assert_eq!(404, http::send(addr, "GET", "/", "").await.0);
+    }
+}
@@ -0,0 +1,563 @@
+//! MQTT bridge: device states are published to `home/<room>/<device>/state` as retained
+//! JSON (as in the REST gateway) and JSON changes are accepted on `home/<room>/<device>/set`.
+//!
+//! `home/status` is `online` while the server is connected and `offline` (the last will)
+//! otherwise. Home Assistant discovery payloads are published under `homeassistant/`.
+
+use crate::context::Context;
+use crate::events::Event;
+use crate::request_handler::SEPARATOR;
+use crate::rest;
+use rumqttc::{AsyncClient, ConnectionError, LastWill, MqttOptions, Packet, Publish, QoS};
+use serde_json::{json, Map, Value};
+use smart_home::{group::DevicePath, smart_device::Device};
+use std::collections::BTreeSet;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, info, warn};
+
+const DEFAULT_PREFIX: &str = "home";
+/// First level of Home Assistant discovery topics.
+const DISCOVERY_PREFIX: &str = "homeassistant";
+const CLIENT_ID: &str = "home_server";
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Requests queued for the MQTT connection.
+const CAPACITY: usize = 64;
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    host: String,
+    port: u16,
+    prefix: String,
+}
+
+impl Config {
+    pub fn new(host: &str, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            prefix: DEFAULT_PREFIX.into(),
+        }
+    }
+
+    /// First level of state, command and status topics, `home` by default.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn status_topic(&self) -> String {
+        format!("{}/status", self.prefix)
+    }
+
+    fn device_topic(&self, path: &DevicePath, leaf: &str) -> String {
+        format!("{}/{}/{}/{leaf}", self.prefix, path.room, path.device)
+    }
+}
+
+/// Keeps the home in sync with the broker, reconnecting when the connection is lost.
+pub async fn run(config: Config, context: Context) {
+    let mut options = MqttOptions::new(CLIENT_ID, &config.host, config.port);
+    options
+        .set_keep_alive(KEEP_ALIVE)
+        .set_last_will(LastWill::new(
+            config.status_topic(),
+            OFFLINE,
+            QoS::AtLeastOnce,
+            true,
+        ));
+    let (client, mut connection) = AsyncClient::new(options, CAPACITY);
+    // Polled by its own task, publishing must not wait for it.
+    let (incoming_tx, mut incoming) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let event = tokio::select! {
+                // The bridge is gone, dropping the connection lets the broker send the will.
+                _ = incoming_tx.closed() => return,
+                event = connection.poll() => event,
+            };
+            match event {
+                Ok(rumqttc::Event::Incoming(packet)) => {
+                    if incoming_tx.send(packet).is_err() {
+                        return;
+                    }
+                }
+                Ok(rumqttc::Event::Outgoing(_)) => {}
+                Err(ConnectionError::RequestsDone) => return,
+                Err(e) => {
+                    warn!(error = %e, "MQTT connection failed");
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            }
+        }
+    });
+    let mut bridge = Bridge {
+        config,
+        context: context.clone(),
+        client,
+        announced: BTreeSet::new(),
+    };
+    let mut events = context.events.subscribe();
+    loop {
+        tokio::select! {
+            packet = incoming.recv() => match packet {
+                Some(Packet::ConnAck(_)) => {
+                    info!("connected to MQTT broker");
+                    bridge.announce().await;
+                }
+                Some(Packet::Publish(publish)) => bridge.command(&publish).await,
+                Some(_) => {}
+                None => return,
+            },
+            event = events.recv() => match event {
+                Ok(Event::Device { path, device }) => bridge.publish_device(&path, &device).await,
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(_)) => bridge.publish_devices().await,
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+        }
+    }
+}
+
+struct Bridge {
+    config: Config,
+    context: Context,
+    client: AsyncClient,
+    /// Devices with published discovery payloads.
+    announced: BTreeSet<DevicePath>,
+}
+
+impl Bridge {
+    /// Subscribes to commands and publishes the status, discovery payloads and states.
+    async fn announce(&mut self) {
+        let commands = format!("{}/+/+/set", self.config.prefix);
+        if let Err(e) = self.client.subscribe(commands, QoS::AtLeastOnce).await {
+            warn!(error = %e, "can't subscribe to MQTT commands");
+        }
+        self.publish(&self.config.status_topic(), ONLINE.into())
+            .await;
+        // The broker may have lost retained messages.
+        self.announced.clear();
+        self.publish_devices().await;
+    }
+
+    async fn publish_devices(&mut self) {
+        let mut devices = vec![];
+        {
+            let home = self.context.home.read().await;
+            for room in home.room_names_list() {
+                for name in home.device_names_list(room).unwrap_or_default() {
+                    if let Some(device) = home.get_device_by_path(room, name) {
+                        devices.push((DevicePath::new(room, name), device.clone()));
+                    }
+                }
+            }
+        }
+        for (path, device) in devices {
+            self.publish_device(&path, &device).await;
+        }
+    }
+
+    async fn publish_device(&mut self, path: &DevicePath, device: &Device) {
+        if !is_topic_level(&path.room) || !is_topic_level(&path.device) {
+            debug!(device = %path, "device name can't be a topic level");
+            return;
+        }
+        if self.announced.insert(path.clone()) {
+            for (topic, payload) in discovery(&self.config, path, device) {
+                self.publish(&topic, payload.to_string()).await;
+            }
+        }
+        let state = rest::device_json(device).to_string();
+        self.publish(&self.config.device_topic(path, "state"), state)
+            .await;
+    }
+
+    async fn publish(&self, topic: &str, payload: String) {
+        if let Err(e) = self
+            .client
+            .publish(topic, QoS::AtLeastOnce, true, payload)
+            .await
+        {
+            warn!(topic, error = %e, "can't publish to MQTT");
+        }
+    }
+
+    /// Applies a JSON object published to a `set` topic, as `PUT` of the REST gateway does.
+    async fn command(&self, publish: &Publish) {
+        let levels: Vec<_> = publish.topic.split('/').collect();
+        let [_, room, device, "set"] = levels.as_slice() else {
+            return;
+        };
+        if room.contains(SEPARATOR) || device.contains(SEPARATOR) {
+            warn!(topic = publish.topic, "bad MQTT command topic");
+            return;
+        }
+        let body = match serde_json::from_slice(&publish.payload) {
+            Ok(Value::Object(body)) => body,
+            _ => {
+                warn!(topic = publish.topic, "MQTT command is not a JSON object");
+                return;
+            }
+        };
+        let response = rest::update_device(&self.context, "mqtt", room, device, &body).await;
+        if response.status != 200 {
+            warn!(
+                topic = publish.topic,
+                error = response.body,
+                "MQTT command failed"
+            );
+        }
+    }
+}
+
+/// Whether the name can be a level of a topic name.
+fn is_topic_level(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['/', '+', '#'])
+}
+
+/// Home Assistant discovery topics and payloads of the device.
+fn discovery(config: &Config, path: &DevicePath, device: &Device) -> Vec<(String, Value)> {
+    let id: String = format!("{}_{}_{}", config.prefix, path.room, path.device)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let state_topic = config.device_topic(path, "state");
+    let command_topic = config.device_topic(path, "set");
+    let entity = |component: &str, id: &str, name: &str, fields: Value| {
+        let mut payload = Map::new();
+        payload.insert("name".into(), json!(name));
+        payload.insert("unique_id".into(), json!(id));
+        payload.insert("availability_topic".into(), json!(config.status_topic()));
+        payload.insert("state_topic".into(), json!(state_topic));
+        payload.insert(
+            "device".into(),
+            json!({
+                "identifiers": [id],
+                "name": format!("{} {}", path.room, path.device),
+                "suggested_area": path.room,
+            }),
+        );
+        if let Value::Object(fields) = fields {
+            payload.extend(fields);
+        }
+        let topic = format!("{DISCOVERY_PREFIX}/{component}/{id}/config");
+        (topic, Value::Object(payload))
+    };
+    match device {
+        Device::Socket(_) => vec![
+            entity(
+                "switch",
+                &id,
+                &path.device,
+                json!({
+                    "command_topic": command_topic,
+                    "payload_on": r#"{"on": true}"#,
+                    "payload_off": r#"{"on": false}"#,
+                    "state_on": "ON",
+                    "state_off": "OFF",
+                    "value_template": "{{ 'ON' if value_json.on else 'OFF' }}",
+                }),
+            ),
+            entity(
+                "sensor",
+                &format!("{id}_power"),
+                &format!("{} power", path.device),
+                json!({
+                    "device_class": "power",
+                    "unit_of_measurement": "W",
+                    "value_template": "{{ value_json.power }}",
+                }),
+            ),
+        ],
+        Device::Thermometer(_) => vec![entity(
+            "sensor",
+            &id,
+            &path.device,
+            json!({
+                "device_class": "temperature",
+                "unit_of_measurement": "°C",
+                "value_template": "{{ value_json.temperature }}",
+            }),
+        )],
+        Device::Lock(_) => vec![entity(
+            "lock",
+            &id,
+            &path.device,
+            json!({
+                "command_topic": command_topic,
+                "payload_lock": r#"{"locked": true}"#,
+                "payload_unlock": r#"{"locked": false}"#,
+                "value_template": "{{ value_json.state | upper }}",
+            }),
+        )],
+        Device::WindowCovering(_) => vec![entity(
+            "cover",
+            &id,
+            &path.device,
+            json!({
+                "command_topic": command_topic,
+                "payload_open": r#"{"position": 100}"#,
+                "payload_close": r#"{"position": 0}"#,
+                "payload_stop": null,
+                "value_template": "{{ 'opening' if value_json.moving and value_json.target_position > value_json.position else 'closing' if value_json.moving else 'open' if value_json.position > 0 else 'closed' }}",
+                "position_topic": state_topic,
+                "position_template": "{{ value_json.position }}",
+                "set_position_topic": command_topic,
+                "set_position_template": r#"{"position": {{ position }}}"#,
+                "tilt_status_topic": state_topic,
+                "tilt_status_template": "{{ value_json.tilt }}",
+                "tilt_command_topic": command_topic,
+                "tilt_command_template": r#"{"tilt": {{ tilt_position }}}"#,
+            }),
+        )],
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::DeviceWatch;
+    use rumqttc::EventLoop;
+    use smart_home::home::Home;
+
+    /// In-process MQTT 3.1.1 broker: QoS 0 delivery, retained messages and last wills.
+    mod broker {
+        use bytes::BytesMut;
+        use rumqttc::mqttbytes::{self, matches};
+        use rumqttc::{
+            ConnAck, ConnectReturnCode, Packet, PingResp, PubAck, Publish, QoS, SubAck,
+            SubscribeReasonCode,
+        };
+        use std::collections::BTreeMap;
+        use std::sync::{Arc, Mutex};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::{TcpListener, TcpStream};
+        use tokio::sync::mpsc;
+
+        const MAX_PACKET: usize = 64 * 1024;
+
+        #[derive(Default)]
+        struct State {
+            retained: BTreeMap<String, Publish>,
+            subscribers: Vec<(Vec<String>, mpsc::UnboundedSender<BytesMut>)>,
+        }
+
+        impl State {
+            fn route(&mut self, mut publish: Publish) {
+                if publish.retain {
+                    if publish.payload.is_empty() {
+                        self.retained.remove(&publish.topic);
+                    } else {
+                        self.retained.insert(publish.topic.clone(), publish.clone());
+                    }
+                }
+                publish.retain = false;
+                publish.qos = QoS::AtMostOnce;
+                publish.pkid = 0;
+                for (filters, sender) in &self.subscribers {
+                    if filters.iter().any(|filter| matches(&publish.topic, filter)) {
+                        let _ = sender.send(encode(|buffer| publish.write(buffer)));
+                    }
+                }
+            }
+        }
+
+        pub async fn start() -> u16 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let state = Arc::new(Mutex::new(State::default()));
+            tokio::spawn(async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    tokio::spawn(connection(stream, Arc::clone(&state)));
+                }
+            });
+            port
+        }
+
+        fn encode(
+            write: impl FnOnce(&mut BytesMut) -> Result<usize, mqttbytes::Error>,
+        ) -> BytesMut {
+            let mut buffer = BytesMut::new();
+            write(&mut buffer).unwrap();
+            buffer
+        }
+
+        async fn connection(stream: TcpStream, state: Arc<Mutex<State>>) {
+            let (mut reader, mut writer) = stream.into_split();
+            let (sender, mut outgoing) = mpsc::unbounded_channel::<BytesMut>();
+            tokio::spawn(async move {
+                while let Some(bytes) = outgoing.recv().await {
+                    if writer.write_all(&bytes).await.is_err() {
+                        return;
+                    }
+                }
+            });
+            let subscriber = {
+                let mut state = state.lock().unwrap();
+                state.subscribers.push((vec![], sender.clone()));
+                state.subscribers.len() - 1
+            };
+            let mut buffer = BytesMut::new();
+            let mut will = None;
+            let disconnected = loop {
+                let packet = match mqttbytes::v4::read(&mut buffer, MAX_PACKET) {
+                    Ok(packet) => packet,
+                    Err(mqttbytes::Error::InsufficientBytes(_)) => {
+                        match reader.read_buf(&mut buffer).await {
+                            Ok(0) | Err(_) => break false,
+                            Ok(_) => continue,
+                        }
+                    }
+                    Err(_) => break false,
+                };
+                match packet {
+                    Packet::Connect(connect) => {
+                        will = connect.last_will;
+                        let connack = ConnAck::new(ConnectReturnCode::Success, false);
+                        let _ = sender.send(encode(|buffer| connack.write(buffer)));
+                    }
+                    Packet::Subscribe(subscribe) => {
+                        let mut state = state.lock().unwrap();
+                        let codes = subscribe
+                            .filters
+                            .iter()
+                            .map(|_| SubscribeReasonCode::Success(QoS::AtMostOnce))
+                            .collect();
+                        let suback = SubAck::new(subscribe.pkid, codes);
+                        let _ = sender.send(encode(|buffer| suback.write(buffer)));
+                        for filter in subscribe.filters {
+                            for publish in state.retained.values() {
+                                if matches(&publish.topic, &filter.path) {
+                                    let mut publish = publish.clone();
+                                    publish.qos = QoS::AtMostOnce;
+                                    let _ = sender.send(encode(|buffer| publish.write(buffer)));
+                                }
+                            }
+                            state.subscribers[subscriber].0.push(filter.path);
+                        }
+                    }
+                    Packet::Publish(publish) => {
+                        if publish.qos != QoS::AtMostOnce {
+                            let puback = PubAck::new(publish.pkid);
+                            let _ = sender.send(encode(|buffer| puback.write(buffer)));
+                        }
+                        state.lock().unwrap().route(publish);
+                    }
+                    Packet::PingReq => {
+                        let _ = sender.send(encode(|buffer| PingResp.write(buffer)));
+                    }
+                    Packet::Disconnect => break true,
+                    _ => {}
+                }
+            };
+            let mut state = state.lock().unwrap();
+            state.subscribers[subscriber].0.clear();
+            if let (false, Some(will)) = (disconnected, will) {
+                let mut publish = Publish::from_bytes(will.topic, will.qos, will.message);
+                publish.retain = will.retain;
+                state.route(publish);
+            }
+        }
+    }
+
+    /// Waits for a publish to the topic which satisfies the condition, failing after 5 s.
+    async fn receive(events: &mut EventLoop, topic: &str, condition: impl Fn(&str) -> bool) {
+        let publish = async {
+            loop {
+                let event = events.poll().await.unwrap();
+                if let rumqttc::Event::Incoming(Packet::Publish(publish)) = event {
+                    let payload = String::from_utf8_lossy(&publish.payload).to_string();
+                    if publish.topic == topic && condition(&payload) {
+                        return;
+                    }
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), publish)
+            .await
+            .unwrap_or_else(|_| panic!("Nothing is published to '{topic}'."));
+    }
+
+    #[test]
+    fn test_discovery() {
+        let config = Config::new("localhost", 1883);
+        let home = Home::restore();
+        let path = DevicePath::new("R", "T");
+        let device = home.get_device_by_path("R", "T").unwrap();
+        let payloads = discovery(&config, &path, device);
+        assert_eq!(1, payloads.len());
+        let (topic, payload) = &payloads[0];
+        assert_eq!("homeassistant/sensor/home_R_T/config", topic);
+        assert_eq!("home/R/T/state", payload["state_topic"]);
+        assert_eq!("home/status", payload["availability_topic"]);
+        assert_eq!("temperature", payload["device_class"]);
+
+        let socket = home.get_device_by_path("R", "S").unwrap();
+        let payloads = discovery(&config, &DevicePath::new("R", "S"), socket);
+        assert_eq!("homeassistant/switch/home_R_S/config", payloads[0].0);
+        assert_eq!("home/R/S/set", payloads[0].1["command_topic"]);
+        // The rendered state is compared with these, not with the payloads.
+        assert_eq!("ON", payloads[0].1["state_on"]);
+        assert_eq!("OFF", payloads[0].1["state_off"]);
+        assert_eq!("home/R/S/state", payloads[0].1["state_topic"]);
+
+        assert!(is_topic_level("Living room"));
+        assert!(!is_topic_level("a/b"));
+        assert!(!is_topic_level("#"));
+    }
+
+    #[tokio::test]
+    async fn test_bridge() {
+        let port = broker::start().await;
+        let context = Context::for_tests();
+        let bridge = tokio::spawn(run(Config::new("127.0.0.1", port), context.clone()));
+        let watching = context.clone();
+        tokio::spawn(async move {
+            let mut watch = DeviceWatch::default();
+            loop {
+                watch.check(&*watching.home.read().await, &watching.events);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+
+        let (client, mut events) =
+            AsyncClient::new(MqttOptions::new("test", "127.0.0.1", port), CAPACITY);
+        client.subscribe("home/#", QoS::AtMostOnce).await.unwrap();
+        client
+            .subscribe("homeassistant/#", QoS::AtMostOnce)
+            .await
+            .unwrap();
+        receive(&mut events, "home/status", |payload| payload == ONLINE).await;
+        receive(&mut events, "homeassistant/sensor/home_R_T/config", |_| {
+            true
+        })
+        .await;
+        receive(&mut events, "home/R/T/state", |payload| {
+            payload.contains("\"kind\":\"thermometer\"")
+        })
+        .await;
+
+        client
+            .publish("home/R/S/set", QoS::AtMostOnce, false, r#"{"on": true}"#)
+            .await
+            .unwrap();
+        receive(&mut events, "home/R/S/state", |payload| {
+            payload.contains("\"on\":true")
+        })
+        .await;
+        match context.home.read().await.get_device_by_path("R", "S") {
+            Some(Device::Socket(socket)) => assert!(socket.is_on()),
+            _ => panic!("socket is lost"),
+        }
+
+        // The connection is closed without DISCONNECT, the broker publishes the will.
+        bridge.abort();
+        receive(&mut events, "home/status", |payload| payload == OFFLINE).await;
+    }
+}
@@ -1,20 +1,69 @@
 #![allow(unused, dead_code)]
 
+use crate::alerts::Alerts;
+use crate::audit::{self, AuditEntry, AuditLog};
+use crate::clock::Clock;
+use crate::context::Context;
+use crate::events::{Event, EventSender};
+use crate::history::{History, TIME_FORMAT};
+use crate::rules::{Rule, Rules};
+use crate::schedule::{Schedule, Scheduler};
+use chrono::{NaiveDateTime, TimeDelta};
 use smart_home::{
+    group::DevicePath,
     home::Home,
-    smart_device::{Device, DeviceInfo, Socket},
+    metadata::{DeviceMetadata, RoomMetadata},
+    query::{Query, QueryOutput},
+    smart_device::{Device, DeviceInfo, LockState, Socket},
+    units::{Energy, Power, PowerUnit, Temperature, TemperatureUnit, Units},
 };
-use std::{fmt::Write, str::Split, sync::Arc};
-use tokio::sync::RwLock;
+use std::{fmt::Write, str::FromStr, str::Split, sync::Arc};
+use tokio::sync::{Mutex, RwLock};
 
-const OK_RESPONSE: &str = "Ok";
-const ERR_RESPONSE: &str = "Err";
-const SEPARATOR: &str = "///";
+pub const OK_RESPONSE: &str = "Ok";
+pub const ERR_RESPONSE: &str = "Err";
+pub const SEPARATOR: &str = "///";
+/// Switches the connection to receiving events, see [`event_message`].
+pub const SUBSCRIBE: &str = "subscribe";
+const EVENT_MESSAGE: &str = "Event";
+pub const BAD_COMMAND_RESPONSE: &str = "Err///Bad command";
+/// Commands changing only the home, run under one lock with the audit snapshots.
+const HOME_COMMANDS: [&str; 8] = [
+    "update device",
+    "remove room",
+    "add device",
+    "remove device",
+    "set metadata",
+    "set room metadata",
+    "switch group",
+    "apply scene",
+];
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Request<'a>(Split<'a, &'a str>);
 
-pub struct Handler(Arc<RwLock<Home>>);
+pub struct Handler {
+    home: Arc<RwLock<Home>>,
+    rules: Arc<Mutex<Rules>>,
+    scheduler: Arc<Mutex<Scheduler>>,
+    history: Arc<Mutex<History>>,
+    alerts: Arc<Mutex<Alerts>>,
+    events: EventSender,
+    clock: Arc<dyn Clock>,
+    audit: Arc<Mutex<AuditLog>>,
+    /// Address of the client.
+    peer: String,
+    /// Units negotiated with the client of the connection.
+    units: Units,
+}
+
+impl<'a> Iterator for Request<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(str::trim)
+    }
+}
 
 impl<'a> Request<'a> {
     pub fn new(raw: &'a str) -> Self {
@@ -27,24 +76,198 @@ impl<'a> Request<'a> {
 }
 
 impl Handler {
-    pub fn new(h: Arc<RwLock<Home>>) -> Self {
-        Self(h)
+    pub fn new(context: &Context, peer: &str) -> Self {
+        Self {
+            home: Arc::clone(&context.home),
+            rules: Arc::clone(&context.rules),
+            scheduler: Arc::clone(&context.scheduler),
+            history: Arc::clone(&context.history),
+            alerts: Arc::clone(&context.alerts),
+            events: context.events.clone(),
+            clock: Arc::clone(&context.clock),
+            audit: Arc::clone(&context.audit),
+            peer: peer.into(),
+            units: Units::default(),
+        }
+    }
+
+    pub fn get_units(&self) -> Units {
+        self.units
     }
 
+    /// Commands changing the state of the server are written to the audit log.
     pub async fn respond<'a>(&'a mut self, r: &'a mut Request<'_>) -> String {
+        let mut fields = r.clone();
+        let command = fields.proceed();
+        let Some(count) = audit::target_fields(command) else {
+            return self.dispatch(r).await;
+        };
+        let definition = definition(command, fields.clone());
+        let target: Vec<_> = fields.take(count).collect();
+        let (old, response, new) = if HOME_COMMANDS.contains(&command) {
+            // Nothing else changes the target between the snapshots.
+            let mut home = self.home.write().await;
+            let old = snapshot(&home, command, &target);
+            r.proceed();
+            let response = self.change_home(&mut home, command, r);
+            let new = snapshot(&home, command, &target);
+            (old, response, new)
+        } else {
+            let response = self.dispatch(r).await;
+            let new = definition.filter(|_| !response.starts_with(ERR_RESPONSE));
+            (None, response, new)
+        };
+        let outcome = match response.strip_prefix(ERR_RESPONSE) {
+            Some(error) => error.trim_start_matches(SEPARATOR).into(),
+            None => String::from(OK_RESPONSE),
+        };
+        self.record(command, &target, old, new, outcome).await;
+        response
+    }
+
+    /// Changes the device under one lock, so that concurrent changes of other fields
+    /// are kept, and audits the change as `update device`, a failed one too. A change
+    /// failing halfway leaves the device as it was.
+    pub async fn change_device(
+        &self,
+        room: &str,
+        device: &str,
+        change: impl FnOnce(&mut Device) -> Result<(), String>,
+    ) -> Result<Device, String> {
+        let mut home = self.home.write().await;
+        let (old, result) = match home.get_device_by_path_mut(room, device) {
+            Some(current) => {
+                let old = current.device_info().join(" ");
+                let mut changed = current.clone();
+                let result = change(&mut changed).map(|()| {
+                    *current = changed.clone();
+                    changed
+                });
+                (Some(old), result)
+            }
+            None => (
+                None,
+                Err(format!("Device '{device}' not found in room '{room}'.")),
+            ),
+        };
+        drop(home);
+        let (new, outcome) = match &result {
+            Ok(changed) => (
+                Some(changed.device_info().join(" ")),
+                String::from(OK_RESPONSE),
+            ),
+            Err(e) => (old.clone(), e.clone()),
+        };
+        self.record("update device", &[room, device], old, new, outcome)
+            .await;
+        result
+    }
+
+    async fn record(
+        &self,
+        command: &str,
+        target: &[&str],
+        old: Option<String>,
+        new: Option<String>,
+        outcome: String,
+    ) {
+        let entry = AuditEntry {
+            time: self.clock.now().naive_local(),
+            peer: self.peer.clone(),
+            user: None,
+            command: command.into(),
+            target: target.join("/"),
+            old,
+            new,
+            outcome,
+        };
+        let audit = Arc::clone(&self.audit);
+        // Writing the file blocks, so it's done off the async workers.
+        let recorded = tokio::task::spawn_blocking(move || audit.blocking_lock().record(entry))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|recorded| recorded);
+        if let Err(e) = recorded {
+            tracing::error!(error = e, "can't write audit log");
+        }
+    }
+
+    /// Runs one of [`HOME_COMMANDS`] under the lock taken by the caller.
+    fn change_home(&self, home: &mut Home, cmd: &str, r: &mut Request<'_>) -> String {
+        match cmd {
+            "update device" => self.update_device(home, r),
+            "remove room" => self.remove_room(home, r),
+            "add device" => self.add_device(home, r),
+            "remove device" => self.remove_device(home, r),
+            "set metadata" => self.set_metadata(home, r),
+            "set room metadata" => self.set_room_metadata(home, r),
+            "switch group" => self.switch_group(home, r),
+            "apply scene" => self.apply_scene(home, r),
+            _ => String::from(BAD_COMMAND_RESPONSE),
+        }
+    }
+
+    async fn dispatch<'a>(&'a mut self, r: &'a mut Request<'_>) -> String {
         let cmd = r.proceed();
         match cmd {
+            cmd if HOME_COMMANDS.contains(&cmd) => {
+                self.change_home(&mut *self.home.write().await, cmd, r)
+            }
             "room list" => self.room_list(r).await,
             "device list" => self.device_list(r).await,
             "get device" => self.get_device(r).await,
-            "update device" => self.update_device(r).await,
-            _ => format!("{ERR_RESPONSE}{SEPARATOR}Bad command"),
+            "add room" => self.add_room(r).await,
+            "energy" => self.energy(r).await,
+            "units" => self.units(r),
+            "get metadata" => self.get_metadata(r).await,
+            "get room metadata" => self.get_room_metadata(r).await,
+            "find tag" => self.find_tag(r).await,
+            "zone list" => self.zone_list(r).await,
+            "zone rooms" => self.zone_rooms(r).await,
+            "zone devices" => self.zone_devices(r).await,
+            "zone energy" => self.zone_energy(r).await,
+            "add zone" => self.add_zone(r).await,
+            "remove zone" => self.remove_zone(r).await,
+            "place room" => self.place_room(r).await,
+            "room zone" => self.room_zone(r).await,
+            "get device at" => self.get_device_at(r).await,
+            "group list" => self.group_list().await,
+            "add group" => self.add_group(r).await,
+            "remove group" => self.remove_group(r).await,
+            "group members" => self.group_members(r).await,
+            "group add" => self.group_add(r).await,
+            "group remove" => self.group_remove(r).await,
+            "read group" => self.read_group(r).await,
+            "query" => self.query(r).await,
+            "stats" => self.stats(r).await,
+            "zone stats" => self.zone_stats(r).await,
+            "scene list" => self.scene_list().await,
+            "get scene" => self.get_scene(r).await,
+            "capture scene" => self.capture_scene(r).await,
+            "remove scene" => self.remove_scene(r).await,
+            "rule list" => self.rule_list().await,
+            "get rule" => self.get_rule(r).await,
+            "add rule" => self.add_rule(r).await,
+            "remove rule" => self.remove_rule(r).await,
+            "enable rule" => self.enable_rule(r, true).await,
+            "disable rule" => self.enable_rule(r, false).await,
+            "rule log" => self.rule_log().await,
+            "schedule list" => self.schedule_list().await,
+            "add schedule" => self.add_schedule(r).await,
+            "remove schedule" => self.remove_schedule(r).await,
+            "history" => self.history(r).await,
+            "alert list" => self.alert_list().await,
+            "add alert" => self.add_alert(r).await,
+            "remove alert" => self.remove_alert(r).await,
+            "ack alert" => self.acknowledge_alert(r).await,
+            "audit log" => self.audit_log(r).await,
+            _ => String::from(BAD_COMMAND_RESPONSE),
         }
     }
 
     async fn room_list(&self, r: &mut Request<'_>) -> String {
         let mut result = String::from(OK_RESPONSE);
-        let home = self.0.read().await;
+        let home = self.home.read().await;
         for room in home.room_names_list() {
             write!(result, "{SEPARATOR}{room}");
         }
@@ -53,7 +276,7 @@ impl Handler {
 
     async fn device_list(&self, r: &mut Request<'_>) -> String {
         let mut result = String::new();
-        let home = self.0.read().await;
+        let home = self.home.read().await;
         let room_name = r.proceed();
         if let Some(room) = home.get_room_by_name(room_name) {
             write!(result, "{OK_RESPONSE}");
@@ -71,13 +294,13 @@ impl Handler {
 
     async fn get_device(&self, r: &mut Request<'_>) -> String {
         let mut result = String::new();
-        let home = self.0.read().await;
+        let home = self.home.read().await;
         let room = r.proceed();
         let device = r.proceed();
         match home.get_device_by_path(room, device) {
             Some(device) => {
                 write!(result, "{OK_RESPONSE}");
-                for info in device.device_info() {
+                for info in device.device_info_in(&self.units) {
                     write!(result, "{SEPARATOR}{info}");
                 }
             }
@@ -90,16 +313,741 @@ impl Handler {
         result
     }
 
-    async fn update_device(&self, r: &mut Request<'_>) -> String {
+    async fn add_room(&self, r: &mut Request<'_>) -> String {
+        let room = r.proceed();
+        if room.is_empty() {
+            return format!("{ERR_RESPONSE}{SEPARATOR}Room name is empty.");
+        }
+        match self.home.write().await.add_room(room) {
+            Some(_) => String::from(OK_RESPONSE),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Room '{room}' already exists."),
+        }
+    }
+
+    fn remove_room(&self, home: &mut Home, r: &mut Request<'_>) -> String {
+        let room = r.proceed();
+        match home.remove_room(room) {
+            Some(_) => String::from(OK_RESPONSE),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Room '{room}' not found."),
+        }
+    }
+
+    /// `room///device///kind`, optionally followed by the state as in `update device`.
+    fn add_device(&self, home: &mut Home, r: &mut Request<'_>) -> String {
+        let room = r.proceed();
+        let name = r.proceed();
+        if name.is_empty() {
+            return format!("{ERR_RESPONSE}{SEPARATOR}Device name is empty.");
+        }
+        let mut device = match r.clone().next().unwrap_or_default() {
+            "socket" => Device::new_socket(),
+            "thermometer" => Device::new_thermometer(),
+            "lock" => Device::new_lock(),
+            "window covering" => Device::new_window_covering(),
+            kind => return format!("{ERR_RESPONSE}{SEPARATOR}Unknown device kind '{kind}'."),
+        };
+        if r.clone().nth(1).is_some() {
+            if let Err(e) = update_from_stp_request(&mut device, r, &self.units) {
+                return format!("{ERR_RESPONSE}{SEPARATOR}{e}");
+            }
+        }
+        if home.get_room_by_name(room).is_none() {
+            return format!("{ERR_RESPONSE}{SEPARATOR}Room '{room}' not found.");
+        }
+        match home.add_device(room, name, device) {
+            Some(_) => String::from(OK_RESPONSE),
+            None => {
+                format!("{ERR_RESPONSE}{SEPARATOR}Device '{name}' already exists in room '{room}'.")
+            }
+        }
+    }
+
+    fn remove_device(&self, home: &mut Home, r: &mut Request<'_>) -> String {
+        let room = r.proceed();
+        let device = r.proceed();
+        match home.remove_device(room, device) {
+            Some(_) => String::from(OK_RESPONSE),
+            None => {
+                format!("{ERR_RESPONSE}{SEPARATOR}Device '{device}' not found in room '{room}'.")
+            }
+        }
+    }
+
+    async fn get_device_at(&self, r: &mut Request<'_>) -> String {
+        let home = self.home.read().await;
+        let path = r.proceed();
+        match home.get_device_by_full_path(path) {
+            Some(device) => ok_response(device.device_info_in(&self.units)),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Device '{path}' not found."),
+        }
+    }
+
+    async fn zone_list(&self, r: &mut Request<'_>) -> String {
+        let home = self.home.read().await;
+        let path = r.proceed();
+        match home.layout().get_zone(path) {
+            Some(zone) => ok_response(zone.zone_names_list().cloned().collect()),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Zone '{path}' not found."),
+        }
+    }
+
+    /// Rooms placed directly in the zone.
+    async fn zone_rooms(&self, r: &mut Request<'_>) -> String {
+        let home = self.home.read().await;
+        let path = r.proceed();
+        match home.layout().get_zone(path) {
+            Some(zone) => ok_response(zone.room_names_list().cloned().collect()),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Zone '{path}' not found."),
+        }
+    }
+
+    /// Full paths of all devices in the zone and its nested zones.
+    async fn zone_devices(&self, r: &mut Request<'_>) -> String {
+        let home = self.home.read().await;
+        let path = r.proceed();
+        match home.devices_in_zone(path) {
+            Some(devices) => ok_response(
+                devices
+                    .into_iter()
+                    .filter_map(|(room, device)| home.device_full_path(room, device))
+                    .collect(),
+            ),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Zone '{path}' not found."),
+        }
+    }
+
+    async fn zone_energy(&self, r: &mut Request<'_>) -> String {
+        let home = self.home.read().await;
+        let path = r.proceed();
+        match home.zone_energy(path) {
+            Some(energy) => {
+                let energy = Energy::kilowatt_hours(energy).value(self.units.energy);
+                format!("{OK_RESPONSE}{SEPARATOR}{energy}")
+            }
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Zone '{path}' not found."),
+        }
+    }
+
+    async fn add_zone(&self, r: &mut Request<'_>) -> String {
+        let mut home = self.home.write().await;
+        let path = r.proceed();
+        if home.add_zone(path) {
+            String::from(OK_RESPONSE)
+        } else {
+            format!("{ERR_RESPONSE}{SEPARATOR}Zone '{path}' already exists.")
+        }
+    }
+
+    async fn remove_zone(&self, r: &mut Request<'_>) -> String {
+        let mut home = self.home.write().await;
+        let path = r.proceed();
+        if home.remove_zone(path) {
+            String::from(OK_RESPONSE)
+        } else {
+            format!("{ERR_RESPONSE}{SEPARATOR}Zone '{path}' not found or not empty.")
+        }
+    }
+
+    async fn place_room(&self, r: &mut Request<'_>) -> String {
+        let mut home = self.home.write().await;
+        let room = r.proceed();
+        let path = r.proceed();
+        if home.place_room(room, path) {
+            String::from(OK_RESPONSE)
+        } else {
+            format!("{ERR_RESPONSE}{SEPARATOR}Room '{room}' or zone '{path}' not found.")
+        }
+    }
+
+    async fn room_zone(&self, r: &mut Request<'_>) -> String {
+        let home = self.home.read().await;
+        let room = r.proceed();
+        match home.room_zone(room) {
+            Some(path) => format!("{OK_RESPONSE}{SEPARATOR}{path}"),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Room '{room}' not found."),
+        }
+    }
+
+    async fn group_list(&self) -> String {
+        let home = self.home.read().await;
+        ok_response(home.group_names_list().cloned().collect())
+    }
+
+    async fn add_group(&self, r: &mut Request<'_>) -> String {
+        let mut home = self.home.write().await;
+        let group = r.proceed();
+        if home.add_group(group) {
+            String::from(OK_RESPONSE)
+        } else {
+            format!("{ERR_RESPONSE}{SEPARATOR}Group '{group}' already exists.")
+        }
+    }
+
+    async fn remove_group(&self, r: &mut Request<'_>) -> String {
+        let mut home = self.home.write().await;
+        let group = r.proceed();
+        match home.remove_group(group) {
+            Some(_) => String::from(OK_RESPONSE),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Group '{group}' not found."),
+        }
+    }
+
+    /// Responds with `room///device` pairs of the group members.
+    async fn group_members(&self, r: &mut Request<'_>) -> String {
+        let home = self.home.read().await;
+        let group = r.proceed();
+        match home.get_group(group) {
+            Some(group) => ok_response(
+                group
+                    .members()
+                    .flat_map(|path| [path.room.clone(), path.device.clone()])
+                    .collect(),
+            ),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Group '{group}' not found."),
+        }
+    }
+
+    async fn group_add(&self, r: &mut Request<'_>) -> String {
+        let mut home = self.home.write().await;
+        let group = r.proceed();
+        let room = r.proceed();
+        let device = r.proceed();
+        if home.add_to_group(group, room, device) {
+            String::from(OK_RESPONSE)
+        } else {
+            format!(
+                "{ERR_RESPONSE}{SEPARATOR}Can't add device '{device}' in room '{room}' to group '{group}'."
+            )
+        }
+    }
+
+    async fn group_remove(&self, r: &mut Request<'_>) -> String {
+        let mut home = self.home.write().await;
+        let group = r.proceed();
+        let room = r.proceed();
+        let device = r.proceed();
+        if home.remove_from_group(group, room, device) {
+            String::from(OK_RESPONSE)
+        } else {
+            format!(
+                "{ERR_RESPONSE}{SEPARATOR}Device '{device}' in room '{room}' is not a member of group '{group}'."
+            )
+        }
+    }
+
+    /// Responds with `room///device///Ok` or `room///device///Err///message` for every member.
+    fn switch_group(&self, home: &mut Home, r: &mut Request<'_>) -> String {
+        let group = r.proceed();
+        let on = r.proceed() == "on";
+        match home.switch_group(group, on) {
+            Some(results) => {
+                let mut fields = vec![];
+                for (path, outcome) in results {
+                    fields.push(path.room);
+                    fields.push(path.device);
+                    match outcome {
+                        Ok(()) => fields.push(OK_RESPONSE.into()),
+                        Err(e) => {
+                            fields.push(ERR_RESPONSE.into());
+                            fields.push(e.to_string());
+                        }
+                    }
+                }
+                ok_response(fields)
+            }
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Group '{group}' not found."),
+        }
+    }
+
+    /// Responds with `room///device///Ok///<number of fields>///<device info>`
+    /// or `room///device///Err///message` for every member.
+    async fn read_group(&self, r: &mut Request<'_>) -> String {
+        let home = self.home.read().await;
+        let group = r.proceed();
+        match home.get_group(group) {
+            Some(group) => {
+                let mut fields = vec![];
+                for path in group.members() {
+                    fields.push(path.room.clone());
+                    fields.push(path.device.clone());
+                    match home.get_device_by_path(&path.room, &path.device) {
+                        Some(device) => {
+                            let info = device.device_info_in(&self.units);
+                            fields.push(OK_RESPONSE.into());
+                            fields.push(info.len().to_string());
+                            fields.extend(info);
+                        }
+                        None => {
+                            fields.push(ERR_RESPONSE.into());
+                            fields.push(format!("Device '{path}' not found."));
+                        }
+                    }
+                }
+                ok_response(fields)
+            }
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Group '{group}' not found."),
+        }
+    }
+
+    /// Responds with `devices` followed by `room///device///<number of fields>///<device info>`
+    /// for every selected device, with `count///N` or with `value///X` (`none` if no values).
+    async fn query(&self, r: &mut Request<'_>) -> String {
+        let query = match r.proceed().parse::<Query>() {
+            Ok(query) => query,
+            Err(e) => return format!("{ERR_RESPONSE}{SEPARATOR}{e}"),
+        };
+        let home = self.home.read().await;
+        match query.run(&home, &self.units) {
+            QueryOutput::Devices(paths) => {
+                let mut fields = vec![String::from("devices")];
+                for path in paths {
+                    if let Some(device) = home.get_device_by_path(&path.room, &path.device) {
+                        let info = device.device_info_in(&self.units);
+                        fields.push(path.room);
+                        fields.push(path.device);
+                        fields.push(info.len().to_string());
+                        fields.extend(info);
+                    }
+                }
+                ok_response(fields)
+            }
+            QueryOutput::Count(count) => format!("{OK_RESPONSE}{SEPARATOR}count{SEPARATOR}{count}"),
+            QueryOutput::Value(Some(value)) => {
+                format!("{OK_RESPONSE}{SEPARATOR}value{SEPARATOR}{value}")
+            }
+            QueryOutput::Value(None) => format!("{OK_RESPONSE}{SEPARATOR}value{SEPARATOR}none"),
+        }
+    }
+
+    /// Stats of the room, or of the whole home if the room is not given.
+    async fn stats(&self, r: &mut Request<'_>) -> String {
+        let home = self.home.read().await;
+        let room = r.proceed();
+        if room.is_empty() {
+            return ok_response(home.stats().fields_in(&self.units));
+        }
+        match home.room_stats(room) {
+            Some(stats) => ok_response(stats.fields_in(&self.units)),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Room '{room}' not found."),
+        }
+    }
+
+    async fn zone_stats(&self, r: &mut Request<'_>) -> String {
+        let home = self.home.read().await;
+        let path = r.proceed();
+        match home.zone_stats(path) {
+            Some(stats) => ok_response(stats.fields_in(&self.units)),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Zone '{path}' not found."),
+        }
+    }
+
+    async fn scene_list(&self) -> String {
+        let home = self.home.read().await;
+        ok_response(home.scene_names_list().cloned().collect())
+    }
+
+    /// Responds with `room///device///<number of fields>///<device state>` for every device.
+    async fn get_scene(&self, r: &mut Request<'_>) -> String {
+        let home = self.home.read().await;
+        let scene = r.proceed();
+        match home.get_scene(scene) {
+            Some(scene) => {
+                let mut fields = vec![];
+                for (path, state) in scene.states() {
+                    let state = state.fields();
+                    fields.push(path.room.clone());
+                    fields.push(path.device.clone());
+                    fields.push(state.len().to_string());
+                    fields.extend(state);
+                }
+                ok_response(fields)
+            }
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Scene '{scene}' not found."),
+        }
+    }
+
+    /// The scene name is followed by `room///device` pairs of the devices to capture.
+    async fn capture_scene(&self, r: &mut Request<'_>) -> String {
+        let scene = r.proceed();
+        let mut paths = vec![];
+        while let Some(room) = r.next() {
+            paths.push(DevicePath::new(room, r.proceed()));
+        }
+        match self.home.write().await.capture_scene(scene, &paths) {
+            Ok(()) => String::from(OK_RESPONSE),
+            Err(e) => format!("{ERR_RESPONSE}{SEPARATOR}{e}"),
+        }
+    }
+
+    fn apply_scene(&self, home: &mut Home, r: &mut Request<'_>) -> String {
+        let scene = r.proceed();
+        match home.apply_scene(scene) {
+            Some(Ok(())) => String::from(OK_RESPONSE),
+            Some(Err(e)) => format!("{ERR_RESPONSE}{SEPARATOR}{e}"),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Scene '{scene}' not found."),
+        }
+    }
+
+    async fn remove_scene(&self, r: &mut Request<'_>) -> String {
+        let scene = r.proceed();
+        match self.home.write().await.remove_scene(scene) {
+            Some(_) => String::from(OK_RESPONSE),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Scene '{scene}' not found."),
+        }
+    }
+
+    /// Responds with `name///enabled` or `name///disabled` for every rule.
+    async fn rule_list(&self) -> String {
+        let rules = self.rules.lock().await;
+        ok_response(
+            rules
+                .rule_list()
+                .flat_map(|rule| {
+                    let state = if rule.is_enabled() {
+                        "enabled"
+                    } else {
+                        "disabled"
+                    };
+                    [rule.get_name().into(), state.into()]
+                })
+                .collect(),
+        )
+    }
+
+    /// Responds with the definition lines of the rule.
+    async fn get_rule(&self, r: &mut Request<'_>) -> String {
+        let rules = self.rules.lock().await;
+        let name = r.proceed();
+        match rules.get_rule(name) {
+            Some(rule) => ok_response(rule.lines().cloned().collect()),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Rule '{name}' not found."),
+        }
+    }
+
+    /// The rest of the request is the definition of the rule, a line per field.
+    async fn add_rule(&self, r: &mut Request<'_>) -> String {
+        let rule = match Rule::parse(r) {
+            Ok(rule) => rule,
+            Err(e) => return format!("{ERR_RESPONSE}{SEPARATOR}{e}"),
+        };
+        let name = String::from(rule.get_name());
+        if self.rules.lock().await.add(rule) {
+            String::from(OK_RESPONSE)
+        } else {
+            format!("{ERR_RESPONSE}{SEPARATOR}Rule '{name}' already exists.")
+        }
+    }
+
+    async fn remove_rule(&self, r: &mut Request<'_>) -> String {
+        let name = r.proceed();
+        match self.rules.lock().await.remove(name) {
+            Some(_) => String::from(OK_RESPONSE),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Rule '{name}' not found."),
+        }
+    }
+
+    async fn enable_rule(&self, r: &mut Request<'_>, enabled: bool) -> String {
+        let name = r.proceed();
+        if self.rules.lock().await.set_enabled(name, enabled) {
+            String::from(OK_RESPONSE)
+        } else {
+            format!("{ERR_RESPONSE}{SEPARATOR}Rule '{name}' not found.")
+        }
+    }
+
+    /// Responds with `time///rule///message` for every executed action, the oldest first.
+    async fn rule_log(&self) -> String {
+        let rules = self.rules.lock().await;
+        ok_response(
+            rules
+                .log()
+                .flat_map(|entry| {
+                    [
+                        entry.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                        entry.rule.clone(),
+                        entry.message.clone(),
+                    ]
+                })
+                .collect(),
+        )
+    }
+
+    /// Responds with `name///when///action///next` for every schedule,
+    /// `next` is the local time or `none`.
+    async fn schedule_list(&self) -> String {
+        let scheduler = self.scheduler.lock().await;
+        ok_response(
+            scheduler
+                .schedule_list()
+                .flat_map(|schedule| {
+                    let next = match schedule.get_next() {
+                        Some(next) => next.format("%Y-%m-%d %H:%M").to_string(),
+                        None => String::from("none"),
+                    };
+                    [
+                        schedule.get_name().into(),
+                        schedule.get_when().into(),
+                        schedule.get_action().to_string(),
+                        next,
+                    ]
+                })
+                .collect(),
+        )
+    }
+
+    async fn add_schedule(&self, r: &mut Request<'_>) -> String {
+        let schedule = match Schedule::parse(r.proceed(), r.proceed(), r.proceed()) {
+            Ok(schedule) => schedule,
+            Err(e) => return format!("{ERR_RESPONSE}{SEPARATOR}{e}"),
+        };
+        match self.scheduler.lock().await.add(schedule) {
+            Ok(()) => String::from(OK_RESPONSE),
+            Err(e) => format!("{ERR_RESPONSE}{SEPARATOR}{e}"),
+        }
+    }
+
+    async fn remove_schedule(&self, r: &mut Request<'_>) -> String {
+        match self.scheduler.lock().await.remove(r.proceed()) {
+            Ok(()) => String::from(OK_RESPONSE),
+            Err(e) => format!("{ERR_RESPONSE}{SEPARATOR}{e}"),
+        }
+    }
+
+    /// Request fields are room, device, optional `from` and `to` times and optional step in seconds.
+    /// Responds with `time///min///max///avg///count` for every point, values are in client's units.
+    async fn history(&self, r: &mut Request<'_>) -> String {
+        let room = r.proceed();
+        let device = r.proceed();
+        let from = match parse_time(r.proceed()) {
+            Ok(from) => from,
+            Err(e) => return e,
+        };
+        let to = match parse_time(r.proceed()) {
+            Ok(to) => to,
+            Err(e) => return e,
+        };
+        let step = match r.proceed() {
+            "" => None,
+            step => match step.parse::<u32>() {
+                Ok(step) if step > 0 => Some(TimeDelta::seconds(i64::from(step))),
+                _ => return format!("{ERR_RESPONSE}{SEPARATOR}Bad step '{step}'."),
+            },
+        };
+        let history = self.history.lock().await;
+        let Some(series) = history.get_series(room, device) else {
+            return format!(
+                "{ERR_RESPONSE}{SEPARATOR}No history of device '{device}' in room '{room}'."
+            );
+        };
+        let convert = |value: f64| match series.get_kind() {
+            "thermometer" => Temperature::celsius(value).value(self.units.temperature),
+            "socket" => Power::watts(value).value(self.units.power),
+            _ => value,
+        };
+        let mut fields = vec![];
+        for mut point in series.points(from, to, step) {
+            point.min = convert(point.min);
+            point.max = convert(point.max);
+            point.avg = convert(point.avg);
+            fields.extend(point.fields());
+        }
+        ok_response(fields)
+    }
+
+    /// Responds with `name///rule///state///since///value` for every alert, `since` and `value`
+    /// are `none` until known. The value is in client's units.
+    async fn alert_list(&self) -> String {
+        let alerts = self.alerts.lock().await;
+        ok_response(
+            alerts
+                .alert_list()
+                .flat_map(|alert| {
+                    let since = match alert.get_since() {
+                        Some(since) => since.format(TIME_FORMAT).to_string(),
+                        None => String::from("none"),
+                    };
+                    let value = match alert.get_value() {
+                        Some(value) => alert
+                            .get_rule()
+                            .field
+                            .convert(value, &self.units)
+                            .to_string(),
+                        None => String::from("none"),
+                    };
+                    [
+                        alert.get_name().into(),
+                        alert.get_spec().into(),
+                        alert.get_state().as_str().into(),
+                        since,
+                        value,
+                    ]
+                })
+                .collect(),
+        )
+    }
+
+    async fn add_alert(&self, r: &mut Request<'_>) -> String {
+        let name = r.proceed();
+        match self.alerts.lock().await.add(name, r.proceed()) {
+            Ok(()) => String::from(OK_RESPONSE),
+            Err(e) => format!("{ERR_RESPONSE}{SEPARATOR}{e}"),
+        }
+    }
+
+    async fn remove_alert(&self, r: &mut Request<'_>) -> String {
+        let name = r.proceed();
+        match self.alerts.lock().await.remove(name) {
+            Some(_) => String::from(OK_RESPONSE),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Alert '{name}' not found."),
+        }
+    }
+
+    async fn acknowledge_alert(&self, r: &mut Request<'_>) -> String {
+        let now = self.clock.now().naive_local();
+        match self
+            .alerts
+            .lock()
+            .await
+            .acknowledge(r.proceed(), now, &self.events)
+        {
+            Ok(()) => String::from(OK_RESPONSE),
+            Err(e) => format!("{ERR_RESPONSE}{SEPARATOR}{e}"),
+        }
+    }
+
+    /// Request fields are optional `from` and `to` times and optional target, like `R` for
+    /// the room and its devices.
+    /// Responds with `time///peer///user///command///target///old///new///outcome`
+    /// for every entry, the oldest first.
+    async fn audit_log(&self, r: &mut Request<'_>) -> String {
+        let from = match parse_time(r.proceed()) {
+            Ok(from) => from,
+            Err(e) => return e,
+        };
+        let to = match parse_time(r.proceed()) {
+            Ok(to) => to,
+            Err(e) => return e,
+        };
+        let target = Some(r.proceed())
+            .filter(|target| !target.is_empty())
+            .map(String::from);
+        let audit = Arc::clone(&self.audit);
+        let entries = tokio::task::spawn_blocking(move || {
+            audit.blocking_lock().entries(from, to, target.as_deref())
+        })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|entries| entries);
+        match entries {
+            Ok(entries) => ok_response(entries.iter().flat_map(AuditEntry::fields).collect()),
+            Err(e) => format!("{ERR_RESPONSE}{SEPARATOR}{e}"),
+        }
+    }
+
+    async fn energy(&self, r: &mut Request<'_>) -> String {
+        let home = self.home.read().await;
+        let room_name = r.proceed();
+        let unit = self.units.energy;
+        if room_name.is_empty() {
+            let energy = Energy::kilowatt_hours(home.total_energy()).value(unit);
+            return format!("{OK_RESPONSE}{SEPARATOR}{energy}");
+        }
+        match home.room_energy(room_name) {
+            Some(energy) => {
+                let energy = Energy::kilowatt_hours(energy).value(unit);
+                format!("{OK_RESPONSE}{SEPARATOR}{energy}")
+            }
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Room '{room_name}' not found."),
+        }
+    }
+
+    async fn get_metadata(&self, r: &mut Request<'_>) -> String {
+        let home = self.home.read().await;
+        let room = r.proceed();
+        let device = r.proceed();
+        match home.device_metadata(room, device) {
+            Some(metadata) => ok_response(metadata.fields()),
+            None => {
+                format!("{ERR_RESPONSE}{SEPARATOR}Device '{device}' not found in room '{room}'.")
+            }
+        }
+    }
+
+    fn set_metadata(&self, home: &mut Home, r: &mut Request<'_>) -> String {
+        let room = r.proceed();
+        let device = r.proceed();
+        match home.device_metadata_mut(room, device) {
+            Some(metadata) => {
+                *metadata = DeviceMetadata::from_fields(r);
+                String::from(OK_RESPONSE)
+            }
+            None => {
+                format!("{ERR_RESPONSE}{SEPARATOR}Device '{device}' not found in room '{room}'.")
+            }
+        }
+    }
+
+    async fn get_room_metadata(&self, r: &mut Request<'_>) -> String {
+        let home = self.home.read().await;
+        let room = r.proceed();
+        match home.room_metadata(room) {
+            Some(metadata) => ok_response(metadata.fields()),
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Room '{room}' not found."),
+        }
+    }
+
+    fn set_room_metadata(&self, home: &mut Home, r: &mut Request<'_>) -> String {
+        let room = r.proceed();
+        match home.room_metadata_mut(room) {
+            Some(metadata) => match RoomMetadata::from_fields(r) {
+                Ok(fields) => {
+                    *metadata = fields;
+                    String::from(OK_RESPONSE)
+                }
+                Err(e) => format!("{ERR_RESPONSE}{SEPARATOR}{e}"),
+            },
+            None => format!("{ERR_RESPONSE}{SEPARATOR}Room '{room}' not found."),
+        }
+    }
+
+    /// Responds with `room///device` pairs of devices labelled with the tag.
+    async fn find_tag(&self, r: &mut Request<'_>) -> String {
+        let home = self.home.read().await;
+        let tag = r.proceed();
+        let mut result = String::from(OK_RESPONSE);
+        for (room, device) in home.devices_with_tag(tag) {
+            write!(result, "{SEPARATOR}{room}{SEPARATOR}{device}");
+        }
+        result
+    }
+
+    /// Sets preferred units for the rest of the connection. Empty fields keep the current units.
+    fn units(&mut self, r: &mut Request<'_>) -> String {
+        let mut units = self.units;
+        let parsed = parse_unit(r.proceed(), &mut units.temperature)
+            .and_then(|_| parse_unit(r.proceed(), &mut units.power))
+            .and_then(|_| parse_unit(r.proceed(), &mut units.energy));
+        if let Err(e) = parsed {
+            return format!("{ERR_RESPONSE}{SEPARATOR}{e}");
+        }
+        self.units = units;
+        format!(
+            "{OK_RESPONSE}{SEPARATOR}{}{SEPARATOR}{}{SEPARATOR}{}",
+            units.temperature.as_str(),
+            units.power.as_str(),
+            units.energy.as_str()
+        )
+    }
+
+    fn update_device(&self, home: &mut Home, r: &mut Request<'_>) -> String {
         let mut result = String::new();
-        let mut home = self.0.write().await;
         let room_name = r.proceed();
         let device_name = r.proceed();
         match home.get_device_by_path_mut(room_name, device_name) {
-            Some(device) => {
-                write!(result, "{OK_RESPONSE}");
-                update_from_stp_request(device, r);
-            }
+            Some(device) => match update_from_stp_request(device, r, &self.units) {
+                Ok(()) => write!(result, "{OK_RESPONSE}").unwrap_or_default(),
+                Err(e) => write!(result, "{ERR_RESPONSE}{SEPARATOR}{e}").unwrap_or_default(),
+            },
             None => write!(
                 result,
                 "{ERR_RESPONSE}{SEPARATOR}Device '{device_name}' not found in room '{room_name}'."
@@ -110,23 +1058,410 @@ impl Handler {
     }
 }
 
-fn update_from_stp_request(device: &mut Device, req: &mut Request) {
+/// Applies the commanded state of the device given in client's units.
+/// Invalid values are rejected with a message.
+pub fn update_from_stp_request(
+    device: &mut Device,
+    req: &mut Request,
+    units: &Units,
+) -> Result<(), String> {
     match device {
         Device::Socket(socket) if req.proceed() == "socket" => {
             let on = "on" == req.proceed();
-            if let Ok(current) = req.proceed().parse::<f64>() {
-                if let Ok(voltage) = req.proceed().parse::<f64>() {
-                    socket.switch(on);
-                    socket.set_current(current);
-                    socket.set_voltage(voltage);
+            let current = parse_value(req.proceed(), "current")?;
+            let voltage = parse_value(req.proceed(), "voltage")?;
+            // Consumed energy and peak power are measured by the socket itself.
+            req.proceed();
+            req.proceed();
+            let power_limit = match req.proceed() {
+                "none" => None,
+                limit => {
+                    let limit = parse_value(limit, "power limit")?;
+                    Some(Power::new(limit, units.power).value(PowerUnit::Watt))
                 }
-            }
+            };
+            let frequency = match req.proceed() {
+                "" => socket.get_frequency(),
+                frequency => parse_value(frequency, "frequency")?,
+            };
+            let power_factor = match req.proceed() {
+                "" => socket.get_power_factor(),
+                power_factor => parse_value(power_factor, "power factor")?,
+            };
+            // Validated on a copy, so the socket is left untouched on error.
+            let mut updated = socket.clone();
+            updated
+                .update(voltage, current, frequency, power_factor)
+                .map_err(|e| e.to_string())?;
+            updated
+                .set_power_limit(power_limit)
+                .map_err(|e| e.to_string())?;
+            updated.switch(on);
+            *socket = updated;
         }
         Device::Thermometer(thermometer) if req.proceed() == "thermometer" => {
-            if let Ok(temperature) = req.proceed().parse::<f64>() {
-                thermometer.set_temperature(temperature);
-            }
+            let temperature = parse_value(req.proceed(), "temperature")?;
+            let temperature = Temperature::new(temperature, units.temperature);
+            thermometer.set_temperature(temperature.value(TemperatureUnit::Celsius));
+        }
+        Device::Lock(lock) if req.proceed() == "lock" => match req.proceed() {
+            "locked" => lock.lock(),
+            "unlocked" => lock.unlock(),
+            // Jamming is reported by the lock, it can't be commanded.
+            target => return Err(format!("Bad lock target '{target}'.")),
+        },
+        Device::WindowCovering(covering) if req.proceed() == "window covering" => {
+            let position = match req.proceed() {
+                "" => covering.get_target_position(),
+                position => parse_value(position, "position")?,
+            };
+            req.proceed();
+            let tilt = match req.proceed() {
+                "" => covering.get_target_tilt(),
+                tilt => parse_value(tilt, "tilt")?,
+            };
+            covering.set_target_position(position);
+            covering.set_target_tilt(tilt);
+        }
+        _ => return Err(String::from("Device kind mismatch.")),
+    }
+    Ok(())
+}
+
+/// Message pushed to subscribed clients, values are in client's units:
+/// `Event///alert///name///room///device///state///value`, `Event///notification///source///message`
+/// or `Event///device///room///device` followed by the fields of `get device`.
+pub fn event_message(event: &Event, units: &Units) -> String {
+    let fields = match event {
+        Event::Notification { source, message } => {
+            vec!["notification".into(), source.clone(), message.clone()]
+        }
+        Event::Alert {
+            name,
+            path,
+            field,
+            state,
+            value,
+        } => vec![
+            "alert".into(),
+            name.clone(),
+            path.room.clone(),
+            path.device.clone(),
+            state.as_str().into(),
+            match value {
+                Some(value) => field.convert(*value, units).to_string(),
+                None => String::from("none"),
+            },
+        ],
+        Event::Device { path, device } => {
+            let mut fields = vec!["device".into(), path.room.clone(), path.device.clone()];
+            fields.extend(device.device_info_in(units));
+            fields
+        }
+    };
+    let mut result = String::from(EVENT_MESSAGE);
+    for field in fields {
+        write!(result, "{SEPARATOR}{field}");
+    }
+    result
+}
+
+/// Device states as `room/device state` separated by `; `.
+fn devices_state<'a>(home: &Home, paths: impl Iterator<Item = &'a DevicePath>) -> String {
+    paths
+        .map(|path| {
+            let state = match home.get_device_by_path(&path.room, &path.device) {
+                Some(device) => device
+                    .state()
+                    .map(String::from)
+                    .unwrap_or_else(|| device.device_info().join(" ")),
+                None => String::from("none"),
+            };
+            format!("{path} {state}")
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Empty time is `None`, a bad one is an error response.
+fn parse_time(time: &str) -> Result<Option<NaiveDateTime>, String> {
+    match time {
+        "" => Ok(None),
+        time => NaiveDateTime::parse_from_str(time, TIME_FORMAT)
+            .map(Some)
+            .map_err(|_| format!("{ERR_RESPONSE}{SEPARATOR}Bad time '{time}'.")),
+    }
+}
+
+/// State of the command target for the audit log.
+fn snapshot(home: &Home, command: &str, target: &[&str]) -> Option<String> {
+    match (command, target) {
+        ("remove room", [room]) => {
+            let mut devices: Vec<_> = home.get_room_by_name(room)?.device_names_list().collect();
+            devices.sort();
+            let paths: Vec<_> = devices
+                .into_iter()
+                .map(|device| DevicePath::new(room, device))
+                .collect();
+            Some(devices_state(home, paths.iter()))
+        }
+        ("update device" | "add device" | "remove device", [room, device]) => home
+            .get_device_by_path(room, device)
+            .map(|device| device.device_info().join(" ")),
+        ("set metadata", [room, device]) => home
+            .device_metadata(room, device)
+            .map(|metadata| metadata.fields().join(" ")),
+        ("set room metadata", [room]) => home
+            .room_metadata(room)
+            .map(|metadata| metadata.fields().join(" ")),
+        ("switch group", [group]) => Some(devices_state(home, home.get_group(group)?.members())),
+        ("apply scene", [scene]) => Some(devices_state(
+            home,
+            home.get_scene(scene)?.states().map(|(path, _)| path),
+        )),
+        _ => None,
+    }
+}
+
+/// Definition given to the command which adds a rule, a schedule or an alert, for the
+/// audit log. `fields` follow the command.
+fn definition(command: &str, fields: Request) -> Option<String> {
+    match command {
+        "add rule" | "add schedule" | "add alert" => Some(fields.collect::<Vec<_>>().join("; ")),
+        _ => None,
+    }
+}
+
+fn ok_response(fields: Vec<String>) -> String {
+    let mut result = String::from(OK_RESPONSE);
+    for field in fields {
+        write!(result, "{SEPARATOR}{field}");
+    }
+    result
+}
+
+fn parse_unit<U: FromStr>(value: &str, unit: &mut U) -> Result<(), String> {
+    if !value.is_empty() {
+        *unit = value
+            .parse()
+            .map_err(|_| format!("Unknown unit '{value}'."))?;
+    }
+    Ok(())
+}
+
+fn parse_value(value: &str, name: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .map_err(|_| format!("Bad {name} value '{value}'."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn send(handler: &mut Handler, request: &str) -> String {
+        handler.respond(&mut Request::new(request)).await
+    }
+
+    #[tokio::test]
+    async fn test_add_and_remove_room() {
+        let context = Context::for_tests();
+        let mut handler = Handler::new(&context, "test");
+        assert_eq!("Ok", send(&mut handler, "add room///Hall").await);
+        // Rooms are listed in no particular order.
+        let rooms = send(&mut handler, "room list").await;
+        let mut rooms: Vec<_> = rooms.split(SEPARATOR).skip(1).collect();
+        rooms.sort();
+        assert_eq!(vec!["Hall", "R"], rooms);
+        assert!(send(&mut handler, "add room///Hall")
+            .await
+            .starts_with("Err"));
+        assert!(send(&mut handler, "add room///").await.starts_with("Err"));
+
+        for request in [
+            "add group///all",
+            "group add///all///R///S",
+            "capture scene///Away///R///L",
+        ] {
+            assert_eq!("Ok", send(&mut handler, request).await);
+        }
+        assert_eq!("Ok", send(&mut handler, "remove room///R").await);
+        assert_eq!("Ok///Hall", send(&mut handler, "room list").await);
+        assert!(send(&mut handler, "remove room///R")
+            .await
+            .starts_with("Err"));
+        // Devices of the room leave groups and scenes.
+        let home = context.home.read().await;
+        assert!(home.get_group("all").unwrap().is_empty());
+        assert!(home.get_scene("Away").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_add_and_remove_device() {
+        let context = Context::for_tests();
+        let mut handler = Handler::new(&context, "test");
+        assert_eq!(
+            "Ok",
+            send(&mut handler, "add device///R///Door///lock///locked").await
+        );
+        assert!(send(&mut handler, "get device///R///Door")
+            .await
+            .starts_with("Ok///lock///locked"));
+        assert!(send(&mut handler, "add device///R///Door///lock")
+            .await
+            .starts_with("Err"));
+        assert!(send(&mut handler, "add device///R///Fan///fan")
+            .await
+            .starts_with("Err"));
+        assert!(send(&mut handler, "add device///Hall///Fan///socket")
+            .await
+            .starts_with("Err"));
+        assert!(send(&mut handler, "add device///R///Door2///lock///jammed")
+            .await
+            .starts_with("Err"));
+
+        for request in [
+            "add group///doors",
+            "group add///doors///R///Door",
+            "capture scene///Night///R///Door///R///L",
+        ] {
+            assert_eq!("Ok", send(&mut handler, request).await);
+        }
+        assert_eq!("Ok", send(&mut handler, "remove device///R///Door").await);
+        assert!(send(&mut handler, "remove device///R///Door")
+            .await
+            .starts_with("Err"));
+        assert_eq!("Ok", send(&mut handler, "group members///doors").await);
+        let home = context.home.read().await;
+        let scene = home.get_scene("Night").unwrap();
+        assert_eq!(
+            vec![&DevicePath::new("R", "L")],
+            scene.states().map(|(path, _)| path).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_audit_snapshots() {
+        let context = Context::for_tests();
+        let mut handler = Handler::new(&context, "test");
+        send(&mut handler, "update device///R///L///lock///locked").await;
+        let entries = context
+            .audit
+            .lock()
+            .await
+            .entries(None, None, None)
+            .unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!(Some("lock unlocked unlocked 0"), entries[0].old.as_deref());
+        assert_eq!(Some("lock locked unlocked 2000"), entries[0].new.as_deref());
+        assert_eq!(OK_RESPONSE, entries[0].outcome);
+    }
+
+    #[tokio::test]
+    async fn test_audit_more_snapshots() {
+        let context = Context::for_tests();
+        let mut handler = Handler::new(&context, "test");
+        for request in [
+            "add device///R///Door///lock///locked",
+            "add schedule///wake///cron 0 7 * * *///switch R/S on",
+            "add alert///frost///R/T temperature < -30",
+            "add alert///bad///R/T kind > 1",
+            "remove room///R",
+        ] {
+            send(&mut handler, request).await;
+        }
+        let audit = context.audit.lock().await;
+        let entries = audit.entries(None, None, None).unwrap();
+        let states: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.old.as_deref(), entry.new.as_deref()))
+            .collect();
+        assert_eq!((None, Some("lock locked unlocked 2000")), states[0]);
+        assert_eq!(
+            (None, Some("wake; cron 0 7 * * *; switch R/S on")),
+            states[1]
+        );
+        assert_eq!((None, Some("frost; R/T temperature < -30")), states[2]);
+        // A failed addition has no new state.
+        assert_eq!((None, None), states[3]);
+        let (Some(old), None) = states[4] else {
+            panic!("Unexpected states {:?}", states[4]);
+        };
+        // The door is still unlocked, locking takes time.
+        assert!(old.starts_with("R/Door unlocked; R/L unlocked; R/S off; R/T "));
+        // Only whole names match the target.
+        assert_eq!(2, audit.entries(None, None, Some("R")).unwrap().len());
+        assert_eq!(1, audit.entries(None, None, Some("R/Door")).unwrap().len());
+    }
+
+    fn update(device: &mut Device, fields: &str) -> Result<(), String> {
+        update_from_stp_request(device, &mut Request::new(fields), &Units::default())
+    }
+
+    #[test]
+    fn test_update_lock() {
+        let mut device = Device::new_lock();
+        update(&mut device, "lock///locked///unlocked///0").unwrap();
+        let Device::Lock(lock) = &device else {
+            unreachable!()
+        };
+        assert_eq!(LockState::Locked, lock.get_target());
+        assert!(update(&mut device, "lock///garbage").is_err());
+        assert!(update(&mut device, "lock///jammed").is_err());
+        assert!(update(&mut device, "lock").is_err());
+    }
+
+    #[test]
+    fn test_update_window_covering() {
+        let mut device = Device::new_window_covering();
+        update(&mut device, "window covering///40///0///20///0").unwrap();
+        // Empty fields keep the target.
+        update(&mut device, "window covering///60//////").unwrap();
+        let Device::WindowCovering(covering) = &device else {
+            unreachable!()
+        };
+        assert_eq!(60_f64, covering.get_target_position());
+        assert_eq!(20_f64, covering.get_target_tilt());
+        assert!(update(&mut device, "window covering///garbage///0///0///0").is_err());
+        assert!(update(&mut device, "window covering///0///0///garbage///0").is_err());
+        assert!(update(&mut device, "lock///locked").is_err());
+    }
+
+    #[test]
+    fn test_update_socket() {
+        let mut device = Device::new_socket();
+        update(&mut device, "socket///on///2///220///0///0///1000///50///1").unwrap();
+        let Device::Socket(socket) = &device else {
+            unreachable!()
+        };
+        assert!(socket.is_on());
+        assert_eq!(Some(1000_f64), socket.get_power_limit());
+        for limit in ["garbage", "", "-1", "0", "NaN", "inf"] {
+            let fields = format!("socket///on///2///220///0///0///{limit}///50///1");
+            assert!(update(&mut device, &fields).is_err());
         }
-        _ => todo!(),
+        // A rejected update leaves the socket as it was.
+        let Device::Socket(socket) = &device else {
+            unreachable!()
+        };
+        assert!(socket.is_on());
+        assert_eq!(Some(1000_f64), socket.get_power_limit());
+        update(&mut device, "socket///on///2///220///0///0///none///50///1").unwrap();
+        let Device::Socket(socket) = &device else {
+            unreachable!()
+        };
+        assert_eq!(None, socket.get_power_limit());
+    }
+
+    #[test]
+    fn test_update_thermometer() {
+        let mut device = Device::new_thermometer();
+        update(&mut device, "thermometer///21.5").unwrap();
+        assert!(update(&mut device, "thermometer///garbage").is_err());
+        assert!(update(&mut device, "thermometer").is_err());
+        let Device::Thermometer(thermometer) = &device else {
+            unreachable!()
+        };
+        assert_eq!(21.5, thermometer.get_temperature());
     }
 }
@@ -0,0 +1,509 @@
+//! REST gateway: rooms, devices and management operations as JSON resources under `/api`,
+//! described by `/api/openapi.json`. Values are in °C, W and kWh.
+//!
+//! Changes are made by STP commands of the request handler, so they are validated
+//! and audited the same way as requests of STP clients.
+
+use crate::context::Context;
+use crate::history::TIME_FORMAT;
+use crate::http::{self, Response};
+use crate::request_handler::{self, Handler, ERR_RESPONSE, OK_RESPONSE, SEPARATOR};
+use serde_json::{json, Map, Value};
+use smart_home::smart_device::Device;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+
+const API: &str = "api";
+const OPENAPI: &str = include_str!("openapi.json");
+
+pub async fn serve(listener: TcpListener, context: Context) {
+    http::serve(listener, move |request: http::Request, peer: SocketAddr| {
+        let context = context.clone();
+        async move { route(&context, request, &format!("http {peer}")).await }
+    })
+    .await
+}
+
+async fn route(context: &Context, request: http::Request, peer: &str) -> Response {
+    let segments: Vec<_> = request.segments.iter().map(String::as_str).collect();
+    let ([API, path @ ..], method) = (segments.as_slice(), request.method.as_str()) else {
+        return Response::error(404, "Not found.");
+    };
+    // Names become fields of STP requests.
+    if path.iter().any(|segment| segment.contains(SEPARATOR)) {
+        return Response::error(400, "Bad name.");
+    }
+    let body = match request.body.trim() {
+        "" => Map::new(),
+        body => match serde_json::from_str(body) {
+            Ok(Value::Object(body)) => body,
+            _ => return Response::error(400, "Body is not a JSON object."),
+        },
+    };
+    match (method, path) {
+        ("GET", ["openapi.json"]) => Response::new(200, "application/json", OPENAPI.into()),
+        ("GET", ["rooms"]) => {
+            let home = context.home.read().await;
+            Response::json(200, &json!(home.room_names_list().collect::<Vec<_>>()))
+        }
+        ("POST", ["rooms", room]) => done(command(context, peer, &["add room", room]).await),
+        ("DELETE", ["rooms", room]) => done(command(context, peer, &["remove room", room]).await),
+        ("GET", ["rooms", room, "devices"]) => {
+            match context.home.read().await.device_names_list(room) {
+                Some(mut devices) => {
+                    devices.sort();
+                    Response::json(200, &json!(devices))
+                }
+                None => Response::error(404, &format!("Room '{room}' not found.")),
+            }
+        }
+        ("GET", ["rooms", room, "energy"]) => match context.home.read().await.room_energy(room) {
+            Some(energy) => Response::json(200, &json!({ "energy": energy })),
+            None => Response::error(404, &format!("Room '{room}' not found.")),
+        },
+        ("GET", ["energy"]) => {
+            let energy = context.home.read().await.total_energy();
+            Response::json(200, &json!({ "energy": energy }))
+        }
+        ("GET", ["rooms", room, "devices", device]) => {
+            match context.home.read().await.get_device_by_path(room, device) {
+                Some(device) => Response::json(200, &device_json(device)),
+                None => device_not_found(room, device),
+            }
+        }
+        ("PUT", ["rooms", room, "devices", device]) => {
+            update_device(context, peer, room, device, &body).await
+        }
+        ("POST", ["rooms", room, "devices", device]) => {
+            let Some(kind) = body.get("kind").and_then(Value::as_str) else {
+                return Response::error(400, "Field 'kind' must be a string.");
+            };
+            if kind.contains(SEPARATOR) {
+                return Response::error(400, "Bad device kind.");
+            }
+            done(command(context, peer, &["add device", room, device, kind]).await)
+        }
+        ("DELETE", ["rooms", room, "devices", device]) => {
+            done(command(context, peer, &["remove device", room, device]).await)
+        }
+        ("GET", ["groups"]) => {
+            let home = context.home.read().await;
+            Response::json(200, &json!(home.group_names_list().collect::<Vec<_>>()))
+        }
+        ("POST", ["groups", group, "switch"]) => {
+            let Some(on) = body.get("on").and_then(Value::as_bool) else {
+                return Response::error(400, "Field 'on' must be a boolean.");
+            };
+            let on = if on { "on" } else { "off" };
+            match command(context, peer, &["switch group", group, on]).await {
+                Ok(fields) => Response::json(200, &switch_results(&fields)),
+                Err(response) => response,
+            }
+        }
+        ("GET", ["scenes"]) => {
+            let home = context.home.read().await;
+            Response::json(200, &json!(home.scene_names_list().collect::<Vec<_>>()))
+        }
+        ("POST", ["scenes", scene, "apply"]) => {
+            done(command(context, peer, &["apply scene", scene]).await)
+        }
+        ("DELETE", ["scenes", scene]) => {
+            done(command(context, peer, &["remove scene", scene]).await)
+        }
+        ("GET", ["rules"]) => {
+            let rules = context.rules.lock().await;
+            let rules: Vec<_> = rules
+                .rule_list()
+                .map(|rule| json!({ "name": rule.get_name(), "enabled": rule.is_enabled() }))
+                .collect();
+            Response::json(200, &json!(rules))
+        }
+        ("POST", ["rules", rule, action @ ("enable" | "disable")]) => {
+            done(command(context, peer, &[&format!("{action} rule"), rule]).await)
+        }
+        ("GET", ["alerts"]) => {
+            let alerts = context.alerts.lock().await;
+            let alerts: Vec<_> = alerts
+                .alert_list()
+                .map(|alert| {
+                    json!({
+                        "name": alert.get_name(),
+                        "rule": alert.get_spec(),
+                        "state": alert.get_state().as_str(),
+                        "since": alert.get_since().map(|since| since.format(TIME_FORMAT).to_string()),
+                        "value": alert.get_value(),
+                    })
+                })
+                .collect();
+            Response::json(200, &json!(alerts))
+        }
+        ("POST", ["alerts", alert, "acknowledge"]) => {
+            done(command(context, peer, &["ack alert", alert]).await)
+        }
+        _ => Response::error(404, "Not found."),
+    }
+}
+
+/// Runs the STP command, fields of an `Ok` response or an error response.
+async fn command(context: &Context, peer: &str, fields: &[&str]) -> Result<Vec<String>, Response> {
+    let raw = fields.join(SEPARATOR);
+    let mut handler = Handler::new(context, peer);
+    let response = handler
+        .respond(&mut request_handler::Request::new(&raw))
+        .await;
+    let mut fields = response.split(SEPARATOR).map(String::from);
+    match fields.next().as_deref() {
+        Some(OK_RESPONSE) => Ok(fields.collect()),
+        Some(ERR_RESPONSE) => Err(error(&fields.collect::<Vec<_>>().join(" "))),
+        _ => Err(Response::error(500, "Bad response of the request handler.")),
+    }
+}
+
+/// Error response of a failed command.
+fn error(message: &str) -> Response {
+    let status = if message.contains("not found") {
+        404
+    } else {
+        400
+    };
+    Response::error(status, message)
+}
+
+fn done(result: Result<Vec<String>, Response>) -> Response {
+    match result {
+        Ok(_) => Response::json(200, &json!({})),
+        Err(response) => response,
+    }
+}
+
+fn device_not_found(room: &str, device: &str) -> Response {
+    Response::error(
+        404,
+        &format!("Device '{device}' not found in room '{room}'."),
+    )
+}
+
+/// Applies the fields of the body to the device, all of them or none.
+pub async fn update_device(
+    context: &Context,
+    peer: &str,
+    room: &str,
+    device: &str,
+    body: &Map<String, Value>,
+) -> Response {
+    let handler = Handler::new(context, peer);
+    match handler
+        .change_device(room, device, |device| apply(device, body))
+        .await
+    {
+        Ok(device) => Response::json(200, &device_json(&device)),
+        Err(e) => error(&e),
+    }
+}
+
+/// Changes the commanded state of the device by the JSON fields.
+fn apply(device: &mut Device, body: &Map<String, Value>) -> Result<(), String> {
+    for (field, value) in body {
+        let number = || {
+            value
+                .as_f64()
+                .ok_or_else(|| format!("Field '{field}' must be a number."))
+        };
+        let boolean = || {
+            value
+                .as_bool()
+                .ok_or_else(|| format!("Field '{field}' must be a boolean."))
+        };
+        match (&mut *device, field.as_str()) {
+            (Device::Socket(socket), "on") => socket.switch(boolean()?),
+            (Device::Socket(socket), "load_current") => {
+                socket.set_current(number()?).map_err(|e| e.to_string())?
+            }
+            (Device::Socket(socket), "power_limit") => {
+                let limit = if value.is_null() {
+                    None
+                } else {
+                    Some(number()?)
+                };
+                socket.set_power_limit(limit).map_err(|e| e.to_string())?
+            }
+            (Device::Thermometer(thermometer), "temperature") => {
+                thermometer.set_temperature(number()?)
+            }
+            (Device::Lock(lock), "locked") if boolean()? => lock.lock(),
+            (Device::Lock(lock), "locked") => lock.unlock(),
+            (Device::WindowCovering(covering), "position") => {
+                covering.set_target_position(number()?)
+            }
+            (Device::WindowCovering(covering), "tilt") => covering.set_target_tilt(number()?),
+            _ => {
+                return Err(format!(
+                    "Field '{field}' can't be changed for a {}.",
+                    device.kind()
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// State of the device in °C, W and kWh.
+pub fn device_json(device: &Device) -> Value {
+    match device {
+        Device::Socket(socket) => json!({
+            "kind": device.kind(),
+            "on": socket.is_on(),
+            "current": socket.get_current(),
+            "load_current": socket.get_load_current(),
+            "voltage": socket.get_voltage(),
+            "frequency": socket.get_frequency(),
+            "power_factor": socket.get_power_factor(),
+            "power": socket.get_current_power(),
+            "energy": socket.get_energy(),
+            "peak_power": socket.get_peak_power(),
+            "power_limit": socket.get_power_limit(),
+        }),
+        Device::Thermometer(thermometer) => json!({
+            "kind": device.kind(),
+            "temperature": thermometer.get_temperature(),
+        }),
+        Device::Lock(lock) => json!({
+            "kind": device.kind(),
+            "locked": lock.is_locked(),
+            "target": lock.get_target().as_str(),
+            "state": lock.get_state().as_str(),
+        }),
+        Device::WindowCovering(covering) => json!({
+            "kind": device.kind(),
+            "position": covering.get_position(),
+            "target_position": covering.get_target_position(),
+            "tilt": covering.get_tilt(),
+            "target_tilt": covering.get_target_tilt(),
+            "moving": covering.is_moving(),
+        }),
+        _ => json!({ "kind": device.kind() }),
+    }
+}
+
+/// `room///device///Ok` or `room///device///Err///message` per member.
+fn switch_results(fields: &[String]) -> Value {
+    let mut results = vec![];
+    let mut fields = fields.iter();
+    while let (Some(room), Some(device), Some(outcome)) =
+        (fields.next(), fields.next(), fields.next())
+    {
+        let error = match outcome.as_str() {
+            OK_RESPONSE => None,
+            _ => fields.next(),
+        };
+        results.push(json!({ "room": room, "device": device, "error": error }));
+    }
+    json!(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn start() -> (SocketAddr, Context) {
+        let context = Context::for_tests();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, context.clone()));
+        (addr, context)
+    }
+
+    fn parse(body: &str) -> Value {
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rooms_and_devices() {
+        let (addr, _) = start().await;
+        let (status, body) = http::send(addr, "GET", "/api/rooms", "").await;
+        assert_eq!((200, json!(["R"])), (status, parse(&body)));
+        let (_, body) = http::send(addr, "GET", "/api/rooms/R/devices", "").await;
+        assert_eq!(json!(["L", "S", "T", "W"]), parse(&body));
+        let (_, body) = http::send(addr, "GET", "/api/rooms/R/devices/T", "").await;
+        assert_eq!("thermometer", parse(&body)["kind"]);
+        // The socket is off, so no current flows whatever the load.
+        let (_, body) = http::send(
+            addr,
+            "PUT",
+            "/api/rooms/R/devices/S",
+            r#"{"load_current": 2}"#,
+        )
+        .await;
+        assert_eq!(json!(0.0), parse(&body)["current"]);
+        assert_eq!(json!(2.0), parse(&body)["load_current"]);
+        assert_eq!(
+            404,
+            http::send(addr, "GET", "/api/rooms/R/devices/X", "")
+                .await
+                .0
+        );
+        assert_eq!(404, http::send(addr, "GET", "/api/nothing", "").await.0);
+        let (_, body) = http::send(addr, "GET", "/api/openapi.json", "").await;
+        assert!(parse(&body)["paths"]["/api/rooms/{room}/devices/{device}"].is_object());
+    }
+
+    #[tokio::test]
+    async fn test_update_device() {
+        let (addr, context) = start().await;
+        let (status, body) = http::send(
+            addr,
+            "PUT",
+            "/api/rooms/R/devices/S",
+            r#"{"on": true, "power_limit": 2000}"#,
+        )
+        .await;
+        assert_eq!(200, status);
+        assert_eq!(json!(true), parse(&body)["on"]);
+        assert_eq!(json!(2000.0), parse(&body)["power_limit"]);
+        let (status, body) = http::send(
+            addr,
+            "PUT",
+            "/api/rooms/R/devices/S",
+            r#"{"temperature": 1}"#,
+        )
+        .await;
+        assert_eq!(400, status);
+        assert!(parse(&body)["error"].is_string());
+        // Fields are applied all or none.
+        assert_eq!(
+            400,
+            http::send(
+                addr,
+                "PUT",
+                "/api/rooms/R/devices/S",
+                r#"{"on": false, "temperature": 1}"#
+            )
+            .await
+            .0
+        );
+        assert!(context
+            .home
+            .read()
+            .await
+            .get_device_by_path("R", "S")
+            .is_some_and(|device| matches!(device, Device::Socket(socket) if socket.is_on())));
+        assert_eq!(
+            400,
+            http::send(addr, "PUT", "/api/rooms/R/devices/S", r#"{"on": 1}"#)
+                .await
+                .0
+        );
+        assert_eq!(
+            400,
+            http::send(addr, "PUT", "/api/rooms/R/devices/S", "[]")
+                .await
+                .0
+        );
+        assert_eq!(
+            404,
+            http::send(addr, "PUT", "/api/rooms/R/devices/X", r#"{"on": true}"#)
+                .await
+                .0
+        );
+        // Changes are audited like STP requests, failed ones with the error.
+        let audit = context.audit.lock().await;
+        let entries = audit.entries(None, None, Some("R/S")).unwrap();
+        assert_eq!(4, entries.len());
+        assert!(entries[0].peer.starts_with("http "));
+        assert_eq!(OK_RESPONSE, entries[0].outcome);
+        assert_ne!(OK_RESPONSE, entries[1].outcome);
+        assert_eq!(entries[1].old, entries[1].new);
+        let entries = audit.entries(None, None, Some("R/X")).unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!(None, entries[0].old);
+    }
+
+    #[tokio::test]
+    async fn test_add_and_remove() {
+        let (addr, context) = start().await;
+        assert_eq!(200, http::send(addr, "POST", "/api/rooms/Hall", "").await.0);
+        assert_eq!(400, http::send(addr, "POST", "/api/rooms/Hall", "").await.0);
+        let (status, _) = http::send(
+            addr,
+            "POST",
+            "/api/rooms/Hall/devices/Lamp",
+            r#"{"kind": "socket"}"#,
+        )
+        .await;
+        assert_eq!(200, status);
+        let (_, body) = http::send(addr, "GET", "/api/rooms/Hall/devices/Lamp", "").await;
+        assert_eq!("socket", parse(&body)["kind"]);
+        for (body, status) in [
+            (r#"{"kind": "fan"}"#, 400),
+            (r#"{"kind": 1}"#, 400),
+            (r#"{}"#, 400),
+        ] {
+            let path = "/api/rooms/Hall/devices/Fan";
+            assert_eq!(status, http::send(addr, "POST", path, body).await.0);
+        }
+        let path = "/api/rooms/Attic/devices/Fan";
+        let body = r#"{"kind": "socket"}"#;
+        assert_eq!(404, http::send(addr, "POST", path, body).await.0);
+
+        let path = "/api/rooms/Hall/devices/Lamp";
+        assert_eq!(200, http::send(addr, "DELETE", path, "").await.0);
+        assert_eq!(404, http::send(addr, "DELETE", path, "").await.0);
+        assert_eq!(
+            200,
+            http::send(addr, "DELETE", "/api/rooms/Hall", "").await.0
+        );
+        assert_eq!(
+            404,
+            http::send(addr, "DELETE", "/api/rooms/Hall", "").await.0
+        );
+        let (_, body) = http::send(addr, "GET", "/api/rooms", "").await;
+        assert_eq!(json!(["R"]), parse(&body));
+        // Audited as STP commands.
+        let audit = context.audit.lock().await;
+        let entries = audit.entries(None, None, Some("Hall")).unwrap();
+        let commands: Vec<_> = entries.iter().map(|entry| entry.command.as_str()).collect();
+        assert!(commands.contains(&"add room"));
+        assert!(commands.contains(&"remove device"));
+    }
+
+    #[tokio::test]
+    async fn test_management() {
+        let (addr, context) = start().await;
+        context.home.write().await.add_group("lights");
+        let (status, body) =
+            http::send(addr, "POST", "/api/groups/lights/switch", r#"{"on": true}"#).await;
+        assert_eq!((200, json!([])), (status, parse(&body)));
+        assert_eq!(
+            404,
+            http::send(addr, "POST", "/api/groups/none/switch", r#"{"on": true}"#)
+                .await
+                .0
+        );
+        assert_eq!(
+            404,
+            http::send(addr, "POST", "/api/scenes/none/apply", "")
+                .await
+                .0
+        );
+        assert_eq!(
+            404,
+            http::send(addr, "POST", "/api/rules/none/enable", "")
+                .await
+                .0
+        );
+        context
+            .alerts
+            .lock()
+            .await
+            .add("hot", "R/T temperature > 100")
+            .unwrap();
+        let (_, body) = http::send(addr, "GET", "/api/alerts", "").await;
+        assert_eq!("cleared", parse(&body)[0]["state"]);
+        assert_eq!(
+            400,
+            http::send(addr, "POST", "/api/alerts/hot/acknowledge", "")
+                .await
+                .0
+        );
+    }
+}
@@ -0,0 +1,635 @@
+//! Automation rules evaluated by the server after the devices are simulated.
+//!
+//! A rule is a block of lines:
+//!
+//! ```text
+//! rule heating
+//! when R/T temperature < 18
+//! if between 06:00 and 23:00
+//! if R/S state = off
+//! then switch R/S on
+//! then notify Heating is on
+//! ```
+//!
+//! Triggers: `when changed <path>`, `when <path> <condition>` (fires as the condition
+//! becomes true), `when at HH:MM`, `when every <N>s|m|h`.
+//! Conditions: `if <path> <condition>`, `if between HH:MM and HH:MM`.
+//! Actions: `then switch <path> on|off`, `then switch group <name> on|off`,
+//! `then update <path> <comma separated device info>`, `then scene <name>`,
+//! `then notify <message>`.
+//!
+//! Paths are `room/device`, device conditions use the query syntax with values in °C, W and kWh.
+
+use crate::events::{Event, EventSender};
+use crate::request_handler::{update_from_stp_request, Request, SEPARATOR};
+use chrono::{NaiveDateTime, NaiveTime};
+use smart_home::{
+    error::DeviceError,
+    group::DevicePath,
+    home::Home,
+    query::Condition,
+    smart_device::{Device, DeviceInfo},
+    units::Units,
+};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+const LOG_CAPACITY: usize = 100;
+const TIME_FORMAT: &str = "%H:%M";
+const FIELD_SEPARATOR: char = ',';
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Trigger {
+    /// State of the device changed, readings for devices without a state.
+    Changed(DevicePath),
+    /// The condition became true.
+    Crossing(DevicePath, Condition),
+    At(NaiveTime),
+    Every(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Guard {
+    Device(DevicePath, Condition),
+    /// Time window, it may span midnight.
+    Between(NaiveTime, NaiveTime),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Switch(DevicePath, bool),
+    SwitchGroup(String, bool),
+    /// Device info fields as in the `update device` request.
+    Update(DevicePath, Vec<String>),
+    ApplyScene(String),
+    Notify(String),
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    name: String,
+    enabled: bool,
+    trigger: Trigger,
+    guards: Vec<Guard>,
+    actions: Vec<Action>,
+    /// Definition the rule was parsed from.
+    lines: Vec<String>,
+    state: TriggerState,
+}
+
+#[derive(Debug, Default)]
+struct TriggerState {
+    last_seen: Option<Vec<String>>,
+    was_true: bool,
+    elapsed: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    pub time: NaiveDateTime,
+    pub rule: String,
+    /// Executed action followed by the error if it failed.
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Rules {
+    rules: BTreeMap<String, Rule>,
+    log: VecDeque<LogEntry>,
+    last_time: Option<NaiveTime>,
+}
+
+impl Rule {
+    /// Parses the definition of a single rule, the first line names the rule.
+    pub fn parse<'a>(lines: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let mut name = None;
+        let mut trigger = None;
+        let mut guards = vec![];
+        let mut actions = vec![];
+        let mut source = vec![];
+        for line in lines.into_iter().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, rest) = line
+                .split_once(char::is_whitespace)
+                .map(|(keyword, rest)| (keyword, rest.trim()))
+                .unwrap_or((line, ""));
+            match keyword {
+                "rule" if name.is_none() && !rest.is_empty() => name = Some(String::from(rest)),
+                "when" if name.is_some() && trigger.is_none() => trigger = Some(rest.parse()?),
+                "if" if name.is_some() => guards.push(rest.parse()?),
+                "then" if name.is_some() => actions.push(rest.parse()?),
+                _ => return Err(format!("Unexpected line '{line}'.")),
+            }
+            source.push(String::from(line));
+        }
+        let name = name.ok_or("Rule name is missing.")?;
+        let trigger = trigger.ok_or_else(|| format!("Rule '{name}' has no trigger."))?;
+        if actions.is_empty() {
+            return Err(format!("Rule '{name}' has no actions."));
+        }
+        Ok(Self {
+            name,
+            enabled: true,
+            trigger,
+            guards,
+            actions,
+            lines: source,
+            state: TriggerState::default(),
+        })
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &String> {
+        self.lines.iter()
+    }
+
+    fn triggered(
+        &mut self,
+        home: &Home,
+        last_time: Option<NaiveTime>,
+        time: NaiveTime,
+        elapsed: Duration,
+    ) -> bool {
+        match &self.trigger {
+            Trigger::Changed(path) => {
+                let seen = home
+                    .get_device_by_path(&path.room, &path.device)
+                    .map(|device| match device.state() {
+                        Some(state) => vec![String::from(state)],
+                        None => device.device_info(),
+                    });
+                let last_seen = std::mem::replace(&mut self.state.last_seen, seen);
+                last_seen.is_some() && last_seen != self.state.last_seen
+            }
+            Trigger::Crossing(path, condition) => {
+                let is_true = condition.matches_at(home, path, &Units::default());
+                let was_true = std::mem::replace(&mut self.state.was_true, is_true);
+                is_true && !was_true
+            }
+            Trigger::At(at) => last_time.is_some_and(|last| passed(last, time, *at)),
+            Trigger::Every(period) => {
+                self.state.elapsed += elapsed;
+                if self.state.elapsed >= *period {
+                    self.state.elapsed -= *period;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
+
+impl Guard {
+    fn holds(&self, home: &Home, time: NaiveTime) -> bool {
+        match self {
+            Guard::Device(path, condition) => condition.matches_at(home, path, &Units::default()),
+            Guard::Between(from, to) if from <= to => *from <= time && time < *to,
+            Guard::Between(from, to) => *from <= time || time < *to,
+        }
+    }
+}
+
+impl Action {
+    pub(crate) fn execute(&self, home: &mut Home) -> Result<(), String> {
+        match self {
+            Action::Switch(path, on) => {
+                match home.get_device_by_path_mut(&path.room, &path.device) {
+                    Some(Device::Socket(socket)) => {
+                        socket.switch(*on);
+                        if socket.is_on() != *on {
+                            return Err(DeviceError::PowerLimit(path.to_string()).to_string());
+                        }
+                    }
+                    Some(_) => return Err(DeviceError::NotSupported(path.to_string()).to_string()),
+                    None => return Err(DeviceError::NotFound(path.to_string()).to_string()),
+                }
+            }
+            Action::SwitchGroup(group, on) => {
+                let results = home
+                    .switch_group(group, *on)
+                    .ok_or_else(|| format!("Group '{group}' not found."))?;
+                let errors: Vec<String> = results
+                    .into_iter()
+                    .filter_map(|(_, result)| result.err().map(|e| e.to_string()))
+                    .collect();
+                if !errors.is_empty() {
+                    return Err(errors.join(" "));
+                }
+            }
+            Action::Update(path, fields) => {
+                let device = home
+                    .get_device_by_path_mut(&path.room, &path.device)
+                    .ok_or_else(|| DeviceError::NotFound(path.to_string()).to_string())?;
+                let fields = fields.join(SEPARATOR);
+                update_from_stp_request(device, &mut Request::new(&fields), &Units::default())?;
+            }
+            Action::ApplyScene(scene) => home
+                .apply_scene(scene)
+                .ok_or_else(|| format!("Scene '{scene}' not found."))?
+                .map_err(|e| e.to_string())?,
+            Action::Notify(_) => {}
+        }
+        Ok(())
+    }
+}
+
+impl Rules {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Parses rules from the text of a config file, every rule starts with the `rule` line.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut blocks: Vec<Vec<&str>> = vec![];
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match blocks.last_mut() {
+                Some(block) if !line.starts_with("rule ") => block.push(line),
+                _ => blocks.push(vec![line]),
+            }
+        }
+        let mut rules = Self::new();
+        for block in blocks {
+            let rule = Rule::parse(block)?;
+            let name = rule.name.clone();
+            if !rules.add(rule) {
+                return Err(format!("Rule '{name}' is defined twice."));
+            }
+        }
+        Ok(rules)
+    }
+
+    pub fn rule_list(&self) -> impl Iterator<Item = &Rule> {
+        self.rules.values()
+    }
+
+    pub fn get_rule(&self, name: &str) -> Option<&Rule> {
+        self.rules.get(name)
+    }
+
+    /// Returns `false` if there is a rule with the same name already.
+    pub fn add(&mut self, rule: Rule) -> bool {
+        if self.rules.contains_key(&rule.name) {
+            return false;
+        }
+        self.rules.insert(rule.name.clone(), rule);
+        true
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Rule> {
+        self.rules.remove(name)
+    }
+
+    /// A rule being enabled starts watching its trigger afresh.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.rules.get_mut(name) {
+            Some(rule) => {
+                if enabled && !rule.enabled {
+                    rule.state = TriggerState::default();
+                }
+                rule.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Executed actions, the oldest first.
+    pub fn log(&self) -> impl Iterator<Item = &LogEntry> {
+        self.log.iter()
+    }
+
+    /// Runs actions of the enabled rules which have been triggered since the previous evaluation
+    /// and whose conditions hold.
+    pub fn evaluate(
+        &mut self,
+        home: &mut Home,
+        now: NaiveDateTime,
+        elapsed: Duration,
+        events: &EventSender,
+    ) {
+        let time = now.time();
+        let last_time = self.last_time.replace(time);
+        for rule in self.rules.values_mut().filter(|rule| rule.enabled) {
+            if !rule.triggered(home, last_time, time, elapsed)
+                || !rule.guards.iter().all(|guard| guard.holds(home, time))
+            {
+                continue;
+            }
+            for action in &rule.actions {
+                let message = match action.execute(home) {
+                    Ok(()) => action.to_string(),
+                    Err(e) => format!("{action}: {e}"),
+                };
+                if let Action::Notify(message) = action {
+                    // Nobody may be listening, that is fine.
+                    let _ = events.send(Event::Notification {
+                        source: rule.name.clone(),
+                        message: message.clone(),
+                    });
+                }
+                if self.log.len() == LOG_CAPACITY {
+                    self.log.pop_front();
+                }
+                self.log.push_back(LogEntry {
+                    time: now,
+                    rule: rule.name.clone(),
+                    message,
+                });
+            }
+        }
+    }
+}
+
+impl FromStr for Trigger {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(char::is_whitespace) {
+            Some(("changed", path)) => Ok(Trigger::Changed(parse_path(path)?)),
+            Some(("at", time)) => Ok(Trigger::At(parse_time(time)?)),
+            Some(("every", period)) => Ok(Trigger::Every(parse_period(period)?)),
+            _ => {
+                let (path, condition) = parse_device_condition(s)?;
+                Ok(Trigger::Crossing(path, condition))
+            }
+        }
+    }
+}
+
+impl FromStr for Guard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s
+            .strip_prefix("between ")
+            .and_then(|s| s.split_once(" and "))
+        {
+            Some((from, to)) => Ok(Guard::Between(parse_time(from)?, parse_time(to)?)),
+            None => {
+                let (path, condition) = parse_device_condition(s)?;
+                Ok(Guard::Device(path, condition))
+            }
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (keyword, rest) = s
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("Bad action '{s}'."))?;
+        let rest = rest.trim();
+        match keyword {
+            "switch" => {
+                let (target, on_off) = rest
+                    .rsplit_once(char::is_whitespace)
+                    .ok_or_else(|| format!("Bad action '{s}'."))?;
+                let on = match on_off {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(format!("Expected 'on' or 'off' in '{s}'.")),
+                };
+                match target.strip_prefix("group ") {
+                    Some(group) => Ok(Action::SwitchGroup(group.trim().into(), on)),
+                    None => Ok(Action::Switch(parse_path(target)?, on)),
+                }
+            }
+            "update" => {
+                let (path, fields) = rest
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| format!("Bad action '{s}'."))?;
+                let fields = fields
+                    .split(FIELD_SEPARATOR)
+                    .map(|field| String::from(field.trim()))
+                    .collect();
+                Ok(Action::Update(parse_path(path)?, fields))
+            }
+            "scene" => Ok(Action::ApplyScene(rest.into())),
+            "notify" => Ok(Action::Notify(rest.into())),
+            _ => Err(format!("Unknown action '{keyword}'.")),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let on_off = |on: &bool| if *on { "on" } else { "off" };
+        match self {
+            Action::Switch(path, on) => write!(f, "switch {path} {}", on_off(on)),
+            Action::SwitchGroup(group, on) => write!(f, "switch group {group} {}", on_off(on)),
+            Action::Update(path, fields) => {
+                write!(f, "update {path} {}", fields.join(", "))
+            }
+            Action::ApplyScene(scene) => write!(f, "scene {scene}"),
+            Action::Notify(message) => write!(f, "notify {message}"),
+        }
+    }
+}
+
+/// `room/device`, the zone path in front of the room is allowed and ignored.
+pub(crate) fn parse_path(s: &str) -> Result<DevicePath, String> {
+    match s.trim().rsplit_once('/') {
+        Some((rooms, device)) if !device.is_empty() => match rooms.rsplit('/').next() {
+            Some(room) if !room.is_empty() => Ok(DevicePath::new(room, device)),
+            _ => Err(format!("Bad device path '{s}'.")),
+        },
+        _ => Err(format!("Bad device path '{s}'.")),
+    }
+}
+
+fn parse_device_condition(s: &str) -> Result<(DevicePath, Condition), String> {
+    let (path, condition) = s
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(|| format!("Expected device path and condition in '{s}'."))?;
+    let condition = condition.parse().map_err(|e| format!("{e}"))?;
+    Ok((parse_path(path)?, condition))
+}
+
+fn parse_time(s: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(s.trim(), TIME_FORMAT).map_err(|_| format!("Bad time '{s}'."))
+}
+
+pub(crate) fn parse_period(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let seconds = match s.chars().last() {
+        Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 3600,
+        _ => return Err(format!("Bad period '{s}'.")),
+    };
+    s[..s.len() - 1]
+        .parse::<u64>()
+        .ok()
+        .filter(|number| *number > 0)
+        .and_then(|number| number.checked_mul(seconds))
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("Bad period '{s}'."))
+}
+
+/// Whether the clock passed `at` moving from `last` to `now`, maybe across midnight.
+fn passed(last: NaiveTime, now: NaiveTime, at: NaiveTime) -> bool {
+    if last <= now {
+        last < at && at <= now
+    } else {
+        last < at || at <= now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events;
+    use chrono::NaiveDate;
+
+    const HEATING: &str = "
+        # Keeps the room warm.
+        rule heating
+        when R/T temperature < 18
+        if R/S state = off
+        then switch R/S on
+        then notify Heating is on
+
+        rule curtains
+        when at 07:30
+        then update R/W window covering, 100, , 0
+    ";
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2022, 1, 31)
+            .and_then(|date| date.and_hms_opt(hour, minute, 0))
+            .unwrap()
+    }
+
+    fn socket_is_on(home: &Home) -> bool {
+        matches!(home.get_device_by_path("R", "S"), Some(Device::Socket(s)) if s.is_on())
+    }
+
+    #[test]
+    fn test_parse() {
+        let rules = Rules::parse(HEATING).unwrap();
+        let names: Vec<&str> = rules.rule_list().map(Rule::get_name).collect();
+        assert_eq!(vec!["curtains", "heating"], names);
+        let heating = rules.get_rule("heating").unwrap();
+        assert_eq!(5, heating.lines().count());
+        assert_eq!(
+            vec![
+                Action::Switch(DevicePath::new("R", "S"), true),
+                Action::Notify("Heating is on".into())
+            ],
+            heating.actions
+        );
+        assert!(Rule::parse(["rule empty", "when every 10s"]).is_err());
+        assert!(Rule::parse(["when every 10s", "then notify no name"]).is_err());
+        assert!(Rule::parse(["rule x", "when every 10", "then notify x"]).is_err());
+        assert!(
+            Rule::parse(["rule x", "when every 9223372036854775807h", "then notify x"]).is_err()
+        );
+        assert!(Rule::parse(["rule x", "when R/T colour = red", "then notify x"]).is_err());
+        assert!(Rules::parse(
+            "rule x\nwhen at 10:00\nthen notify x\nrule x\nwhen at 11:00\nthen notify x"
+        )
+        .is_err());
+        assert_eq!(
+            Ok(Action::SwitchGroup("night lights".into(), false)),
+            "switch group night lights off".parse()
+        );
+        assert_eq!(
+            Ok(Guard::Device(
+                DevicePath::new("R", "T"),
+                "temperature > 20".parse().unwrap()
+            )),
+            "floor1/R/T temperature > 20".parse()
+        );
+    }
+
+    #[test]
+    fn test_threshold_crossing() {
+        let mut home = Home::restore();
+        let events = events::channel();
+        let mut notifications = events.subscribe();
+        let mut rules = Rules::parse(HEATING).unwrap();
+        if let Some(Device::Thermometer(t)) = home.get_device_by_path_mut("R", "T") {
+            t.set_temperature(15_f64);
+        }
+        rules.evaluate(&mut home, at(10, 0), Duration::ZERO, &events);
+        assert!(socket_is_on(&home));
+        assert_eq!(2, rules.log().count());
+        assert_eq!(
+            Ok(Event::Notification {
+                source: "heating".into(),
+                message: "Heating is on".into()
+            }),
+            notifications.try_recv()
+        );
+        // Still cold, but the threshold has not been crossed again.
+        if let Some(Device::Socket(s)) = home.get_device_by_path_mut("R", "S") {
+            s.switch(false);
+        }
+        rules.evaluate(&mut home, at(10, 1), Duration::ZERO, &events);
+        assert!(!socket_is_on(&home));
+        assert!(rules.set_enabled("heating", false));
+        assert!(rules.set_enabled("heating", true));
+        rules.evaluate(&mut home, at(10, 2), Duration::ZERO, &events);
+        assert!(socket_is_on(&home));
+    }
+
+    #[test]
+    fn test_time_triggers() {
+        let mut home = Home::restore();
+        let events = events::channel();
+        let mut rules = Rules::parse(HEATING).unwrap();
+        rules.set_enabled("heating", false);
+        rules.evaluate(&mut home, at(7, 29), Duration::ZERO, &events);
+        assert_eq!(0, rules.log().count());
+        rules.evaluate(&mut home, at(7, 30), Duration::ZERO, &events);
+        assert_eq!("update R/W window covering, 100, , 0", rules.log[0].message);
+        rules.evaluate(&mut home, at(7, 31), Duration::ZERO, &events);
+        assert_eq!(1, rules.log().count());
+
+        let mut rules =
+            Rules::parse("rule tick\nwhen every 1s\nif between 23:00 and 01:00\nthen notify tick")
+                .unwrap();
+        rules.evaluate(&mut home, at(23, 59), Duration::from_millis(600), &events);
+        rules.evaluate(&mut home, at(0, 0), Duration::from_millis(600), &events);
+        assert_eq!(1, rules.log().count());
+        rules.evaluate(&mut home, at(12, 0), Duration::from_secs(1), &events);
+        assert_eq!(1, rules.log().count());
+    }
+
+    #[test]
+    fn test_changed_and_failures() {
+        let mut home = Home::restore();
+        let events = events::channel();
+        let mut rules =
+            Rules::parse("rule follow\nwhen changed R/L\nthen switch R/S on\nthen switch R/T on")
+                .unwrap();
+        rules.evaluate(&mut home, at(8, 0), Duration::ZERO, &events);
+        assert_eq!(0, rules.log().count());
+        if let Some(Device::Lock(lock)) = home.get_device_by_path_mut("R", "L") {
+            lock.jam();
+        }
+        rules.evaluate(&mut home, at(8, 0), Duration::ZERO, &events);
+        assert!(socket_is_on(&home));
+        let messages: Vec<&str> = rules.log().map(|entry| entry.message.as_str()).collect();
+        assert_eq!("switch R/S on", messages[0]);
+        assert!(messages[1].starts_with("switch R/T on: "));
+    }
+}
@@ -0,0 +1,564 @@
+//! Actions run at given local times. The action syntax is the one of the rules' `then` lines.
+//!
+//! ```text
+//! at 2022-02-01 06:30    once; missed while the server was down, it runs on start
+//! cron 30 6 * * 1-5      minute hour day month weekday, Sunday is 0 or 7
+//! sunrise +15m           every day, the offset is optional
+//! sunset -1h
+//! ```
+//!
+//! A persistent scheduler keeps a schedule per line of its file as `name///when///action`.
+
+use crate::clock::Clock;
+use crate::events::{Event, EventSender};
+use crate::request_handler::SEPARATOR;
+use crate::rules::Action;
+use crate::sun::{Location, SunEvent};
+use chrono::{Datelike, Days, NaiveDate, NaiveDateTime, TimeDelta};
+use smart_home::home::Home;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+const DATE_TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+/// How far ahead recurring schedules are looked up.
+const LOOKAHEAD_DAYS: u64 = 5 * 366;
+/// Largest offset from a sun event in minutes, either way.
+const MAX_SUN_OFFSET: i64 = 24 * 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum When {
+    Once(NaiveDateTime),
+    Cron(Cron),
+    /// Offset from the event in minutes.
+    Sun(SunEvent, i64),
+}
+
+/// Sorted values of every field of a cron expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days: Vec<u32>,
+    months: Vec<u32>,
+    /// Days from Sunday.
+    weekdays: Vec<u32>,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+#[derive(Debug)]
+pub struct Schedule {
+    name: String,
+    when: When,
+    /// Text `when` was parsed from.
+    when_text: String,
+    action: Action,
+    next: Option<NaiveDateTime>,
+}
+
+pub struct Scheduler {
+    schedules: BTreeMap<String, Schedule>,
+    clock: Arc<dyn Clock>,
+    location: Option<Location>,
+    file: Option<PathBuf>,
+}
+
+impl Cron {
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if !self.months.contains(&date.month()) {
+            return false;
+        }
+        let day = self.days.contains(&date.day());
+        let weekday = self
+            .weekdays
+            .contains(&date.weekday().num_days_from_sunday());
+        // Like in cron, restricted day and weekday fields match either of them.
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+
+    fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut date = after.date();
+        for _ in 0..LOOKAHEAD_DAYS {
+            if self.matches_date(date) {
+                for hour in &self.hours {
+                    for minute in &self.minutes {
+                        let time = date.and_hms_opt(*hour, *minute, 0)?;
+                        if time > after {
+                            return Some(time);
+                        }
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+}
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("Expected 5 fields in cron expression '{s}'."));
+        }
+        let weekdays: BTreeSet<u32> = parse_cron_field(fields[4], 0, 7)?
+            .into_iter()
+            .map(|weekday| weekday % 7)
+            .collect();
+        Ok(Self {
+            minutes: parse_cron_field(fields[0], 0, 59)?,
+            hours: parse_cron_field(fields[1], 0, 23)?,
+            days: parse_cron_field(fields[2], 1, 31)?,
+            months: parse_cron_field(fields[3], 1, 12)?,
+            weekdays: weekdays.into_iter().collect(),
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+        })
+    }
+}
+
+/// `*`, `5`, `1-5`, `*/15`, `0-30/10` and comma separated lists of them.
+fn parse_cron_field(s: &str, min: u32, max: u32) -> Result<Vec<u32>, String> {
+    let bad_field = || format!("Bad cron field '{s}'.");
+    let mut values = BTreeSet::new();
+    for part in s.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<usize>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(bad_field()),
+            },
+            None => (part, 1),
+        };
+        let number = |s: &str| s.parse::<u32>().map_err(|_| bad_field());
+        let (from, to) = if range == "*" {
+            (min, max)
+        } else {
+            match range.split_once('-') {
+                Some((from, to)) => (number(from)?, number(to)?),
+                None if step > 1 => (number(range)?, max),
+                None => (number(range)?, number(range)?),
+            }
+        };
+        if from < min || to > max || from > to {
+            return Err(bad_field());
+        }
+        values.extend((from..=to).step_by(step));
+    }
+    Ok(values.into_iter().collect())
+}
+
+impl When {
+    /// First time after `after`, in local time `utc_offset` seconds ahead of UTC.
+    fn next_after(
+        &self,
+        after: NaiveDateTime,
+        utc_offset: i64,
+        location: Option<&Location>,
+    ) -> Option<NaiveDateTime> {
+        match self {
+            When::Once(time) => Some(*time).filter(|time| *time > after),
+            When::Cron(cron) => cron.next_after(after),
+            When::Sun(event, offset) => {
+                let location = location?;
+                let offset = offset
+                    .checked_mul(60)
+                    .and_then(|offset| offset.checked_add(utc_offset))
+                    .and_then(TimeDelta::try_seconds)?;
+                let mut date = after.date().checked_sub_days(Days::new(1))?;
+                for _ in 0..LOOKAHEAD_DAYS {
+                    if let Some(time) = location.sun_event(date, *event) {
+                        let time = time + offset;
+                        if time > after {
+                            return Some(time);
+                        }
+                    }
+                    date = date.succ_opt()?;
+                }
+                None
+            }
+        }
+    }
+}
+
+impl FromStr for When {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (keyword, rest) = s
+            .split_once(char::is_whitespace)
+            .map(|(keyword, rest)| (keyword, rest.trim()))
+            .unwrap_or((s, ""));
+        let event = match keyword {
+            "at" => {
+                return NaiveDateTime::parse_from_str(rest, DATE_TIME_FORMAT)
+                    .map(When::Once)
+                    .map_err(|_| format!("Bad date and time '{rest}'."))
+            }
+            "cron" => return rest.parse().map(When::Cron),
+            "sunrise" => SunEvent::Sunrise,
+            "sunset" => SunEvent::Sunset,
+            _ => return Err(format!("Bad schedule time '{s}'.")),
+        };
+        if rest.is_empty() {
+            return Ok(When::Sun(event, 0));
+        }
+        let minutes = match rest.chars().last() {
+            Some('m') => 1,
+            Some('h') => 60,
+            _ => return Err(format!("Bad offset '{rest}'.")),
+        };
+        let offset = rest[..rest.len() - 1]
+            .trim_start_matches('+')
+            .parse::<i64>()
+            .ok()
+            .and_then(|offset| offset.checked_mul(minutes))
+            .filter(|offset| offset.abs() <= MAX_SUN_OFFSET)
+            .ok_or_else(|| format!("Bad offset '{rest}'."))?;
+        Ok(When::Sun(event, offset))
+    }
+}
+
+impl Schedule {
+    pub fn parse(name: &str, when: &str, action: &str) -> Result<Self, String> {
+        if name.is_empty() {
+            return Err(String::from("Schedule name is missing."));
+        }
+        // Every schedule is saved on a line of its own.
+        if [name, when, action]
+            .iter()
+            .any(|text| text.contains(char::is_control))
+        {
+            return Err(String::from("Schedule can't contain control characters."));
+        }
+        Ok(Self {
+            name: name.into(),
+            when: when.parse()?,
+            when_text: when.trim().into(),
+            action: action.parse()?,
+            next: None,
+        })
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_when(&self) -> &str {
+        &self.when_text
+    }
+
+    pub fn get_action(&self) -> &Action {
+        &self.action
+    }
+
+    /// Local time the schedule runs next, `None` if it never does.
+    pub fn get_next(&self) -> Option<NaiveDateTime> {
+        self.next
+    }
+}
+
+impl Scheduler {
+    pub fn new(clock: Arc<dyn Clock>, location: Option<Location>) -> Self {
+        Self {
+            schedules: BTreeMap::new(),
+            clock,
+            location,
+            file: None,
+        }
+    }
+
+    /// Keeps schedules in the file and loads the ones saved there before.
+    pub fn persistent(mut self, file: PathBuf) -> Result<Self, String> {
+        let text = match fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Can't read '{}': {e}", file.display())),
+        };
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let mut fields = line.split(SEPARATOR);
+            let schedule = Schedule::parse(
+                fields.next().unwrap_or_default(),
+                fields.next().unwrap_or_default(),
+                fields.next().unwrap_or_default(),
+            )?;
+            self.insert(schedule)?;
+        }
+        self.file = Some(file);
+        Ok(self)
+    }
+
+    pub fn schedule_list(&self) -> impl Iterator<Item = &Schedule> {
+        self.schedules.values()
+    }
+
+    /// Adds the schedule unless it can't be saved.
+    pub fn add(&mut self, schedule: Schedule) -> Result<(), String> {
+        let name = schedule.name.clone();
+        self.insert(schedule)?;
+        self.save().inspect_err(|_| {
+            self.schedules.remove(&name);
+        })
+    }
+
+    /// Removes the schedule unless the change can't be saved.
+    pub fn remove(&mut self, name: &str) -> Result<(), String> {
+        let schedule = self
+            .schedules
+            .remove(name)
+            .ok_or_else(|| format!("Schedule '{name}' not found."))?;
+        self.save().inspect_err(|_| {
+            self.schedules.insert(schedule.name.clone(), schedule);
+        })
+    }
+
+    /// Runs actions of the schedules which are due and returns what was done.
+    /// One-shot schedules are removed once they have run.
+    pub fn run_due(&mut self, home: &mut Home, events: &EventSender) -> Vec<(String, String)> {
+        let now = self.clock.now();
+        let utc_offset = i64::from(now.offset().local_minus_utc());
+        let now = now.naive_local();
+        let mut result = vec![];
+        for schedule in self.schedules.values_mut() {
+            if schedule.next.is_none_or(|next| next > now) {
+                continue;
+            }
+            let action = &schedule.action;
+            let message = match action.execute(home) {
+                Ok(()) => action.to_string(),
+                Err(e) => format!("{action}: {e}"),
+            };
+            if let Action::Notify(message) = action {
+                // Nobody may be listening, that is fine.
+                let _ = events.send(Event::Notification {
+                    source: schedule.name.clone(),
+                    message: message.clone(),
+                });
+            }
+            result.push((schedule.name.clone(), message));
+            schedule.next = schedule
+                .when
+                .next_after(now, utc_offset, self.location.as_ref());
+        }
+        let count = self.schedules.len();
+        self.schedules.retain(|_, schedule| {
+            !matches!(schedule.when, When::Once(_)) || schedule.next.is_some()
+        });
+        if count != self.schedules.len() {
+            if let Err(e) = self.save() {
+                result.push((String::new(), e));
+            }
+        }
+        result
+    }
+
+    fn insert(&mut self, mut schedule: Schedule) -> Result<(), String> {
+        if self.schedules.contains_key(&schedule.name) {
+            return Err(format!("Schedule '{}' already exists.", schedule.name));
+        }
+        if matches!(schedule.when, When::Sun(..)) && self.location.is_none() {
+            return Err(String::from("Location of the home is not configured."));
+        }
+        let now = self.clock.now();
+        let utc_offset = i64::from(now.offset().local_minus_utc());
+        schedule.next = match schedule.when {
+            When::Once(time) => Some(time),
+            _ => schedule
+                .when
+                .next_after(now.naive_local(), utc_offset, self.location.as_ref()),
+        };
+        self.schedules.insert(schedule.name.clone(), schedule);
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let mut text = String::new();
+        for schedule in self.schedules.values() {
+            let fields = [
+                schedule.name.clone(),
+                schedule.when_text.clone(),
+                schedule.action.to_string(),
+            ];
+            text.push_str(&fields.join(SEPARATOR));
+            text.push('\n');
+        }
+        fs::write(file, text).map_err(|e| format!("Can't write '{}': {e}", file.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::events;
+    use chrono::DateTime;
+    use smart_home::smart_device::Device;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, DATE_TIME_FORMAT).unwrap()
+    }
+
+    /// Monday, 31 January 2022, Moscow time.
+    fn clock(time: &str) -> Arc<ManualClock> {
+        let now = DateTime::parse_from_str(&format!("{time} +0300"), "%Y-%m-%d %H:%M %z");
+        Arc::new(ManualClock::new(now.unwrap()))
+    }
+
+    fn socket_is_on(home: &Home) -> bool {
+        matches!(home.get_device_by_path("R", "S"), Some(Device::Socket(s)) if s.is_on())
+    }
+
+    #[test]
+    fn test_cron() {
+        let weekdays: Cron = "30 6 * * 1-5".parse().unwrap();
+        // Friday evening to Monday morning.
+        assert_eq!(
+            Some(at("2022-02-07 06:30")),
+            weekdays.next_after(at("2022-02-04 07:00"))
+        );
+        let quarters: Cron = "*/15 * * * *".parse().unwrap();
+        assert_eq!(
+            Some(at("2022-02-04 07:15")),
+            quarters.next_after(at("2022-02-04 07:00"))
+        );
+        // Either the 1st day of a month or a Sunday.
+        let either: Cron = "0 12 1 * 0".parse().unwrap();
+        assert_eq!(
+            Some(at("2022-02-01 12:00")),
+            either.next_after(at("2022-01-30 13:00"))
+        );
+        let leap_day: Cron = "0 0 29 2 *".parse().unwrap();
+        assert_eq!(
+            Some(at("2024-02-29 00:00")),
+            leap_day.next_after(at("2022-01-01 00:00"))
+        );
+        assert!("60 * * * *".parse::<Cron>().is_err());
+        assert!("* * * *".parse::<Cron>().is_err());
+        assert!("*/0 * * * *".parse::<Cron>().is_err());
+    }
+
+    #[test]
+    fn test_parse_when() {
+        assert_eq!(
+            Ok(When::Once(at("2022-02-01 06:30"))),
+            "at 2022-02-01 06:30".parse()
+        );
+        assert_eq!(Ok(When::Sun(SunEvent::Sunset, -60)), "sunset -1h".parse());
+        assert_eq!(Ok(When::Sun(SunEvent::Sunrise, 15)), "sunrise +15m".parse());
+        assert_eq!(Ok(When::Sun(SunEvent::Sunrise, 0)), "sunrise".parse());
+        assert!("sunrise soon".parse::<When>().is_err());
+        assert_eq!(
+            Ok(When::Sun(SunEvent::Sunrise, -1440)),
+            "sunrise -24h".parse()
+        );
+        assert!("sunrise +25h".parse::<When>().is_err());
+        assert!("sunset 9223372036854775807h".parse::<When>().is_err());
+        assert!("at noon".parse::<When>().is_err());
+    }
+
+    #[test]
+    fn test_run_due() {
+        let clock = clock("2022-01-31 06:00");
+        let mut scheduler = Scheduler::new(clock.clone(), None);
+        let events = events::channel();
+        let mut home = Home::restore();
+        scheduler
+            .add(Schedule::parse("wake", "cron 30 6 * * 1-5", "switch R/S on").unwrap())
+            .unwrap();
+        scheduler
+            .add(Schedule::parse("once", "at 2022-01-31 07:00", "switch R/S off").unwrap())
+            .unwrap();
+        assert!(scheduler
+            .add(Schedule::parse("dusk", "sunset", "switch R/S on").unwrap())
+            .is_err());
+        assert!(scheduler.run_due(&mut home, &events).is_empty());
+        clock.advance(chrono::Duration::minutes(30));
+        assert_eq!(
+            vec![(String::from("wake"), String::from("switch R/S on"))],
+            scheduler.run_due(&mut home, &events)
+        );
+        assert!(socket_is_on(&home));
+        assert!(scheduler.run_due(&mut home, &events).is_empty());
+        clock.advance(chrono::Duration::hours(1));
+        assert_eq!(1, scheduler.run_due(&mut home, &events).len());
+        assert!(!socket_is_on(&home));
+        let names: Vec<&str> = scheduler.schedule_list().map(Schedule::get_name).collect();
+        assert_eq!(vec!["wake"], names);
+        assert_eq!(
+            Some(at("2022-02-01 06:30")),
+            scheduler.schedule_list().next().unwrap().get_next()
+        );
+    }
+
+    #[test]
+    fn test_sun_and_persistence() {
+        let file = std::env::temp_dir().join(format!("schedules-{}.txt", std::process::id()));
+        let moscow = Location {
+            latitude: 55.75,
+            longitude: 37.62,
+        };
+        let clock = clock("2022-06-21 12:00");
+        let mut scheduler = Scheduler::new(clock.clone(), Some(moscow))
+            .persistent(file.clone())
+            .unwrap();
+        scheduler
+            .add(Schedule::parse("dusk", "sunset -30m", "scene Evening").unwrap())
+            .unwrap();
+        // Sunset is at 21:18.
+        let next = scheduler
+            .schedule_list()
+            .next()
+            .unwrap()
+            .get_next()
+            .unwrap();
+        assert!((next - at("2022-06-21 20:48")).num_minutes().abs() < 5);
+
+        let restored = Scheduler::new(clock, Some(moscow))
+            .persistent(file.clone())
+            .unwrap();
+        let schedule = restored.schedule_list().next().unwrap();
+        assert_eq!("sunset -30m", schedule.get_when());
+        assert_eq!(&Action::ApplyScene("Evening".into()), schedule.get_action());
+        assert_eq!(Some(next), schedule.get_next());
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_control_characters() {
+        assert!(Schedule::parse("two\nlines", "sunrise", "switch R/S on").is_err());
+        assert!(Schedule::parse("wake", "sunrise", "switch R/S\nR/L on").is_err());
+        assert!(Schedule::parse("wake", "cron 0 7 * * *\r", "switch R/S on").is_err());
+    }
+
+    #[test]
+    fn test_failed_save() {
+        let dir = std::env::temp_dir().join(format!("schedules-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut scheduler = Scheduler::new(clock("2022-06-21 12:00"), None)
+            .persistent(dir.join("schedules.txt"))
+            .unwrap();
+        scheduler
+            .add(Schedule::parse("wake", "cron 0 7 * * *", "switch R/S on").unwrap())
+            .unwrap();
+        // The file can't be written once the directory is gone.
+        fs::remove_dir_all(&dir).unwrap();
+        let schedule = Schedule::parse("noon", "cron 0 12 * * *", "switch R/S off").unwrap();
+        assert!(scheduler.add(schedule).is_err());
+        assert!(scheduler.remove("wake").is_err());
+        let names: Vec<_> = scheduler.schedule_list().map(|s| s.name.as_str()).collect();
+        assert_eq!(vec!["wake"], names);
+    }
+}
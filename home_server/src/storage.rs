@@ -0,0 +1,50 @@
+//! Fields of the line-based files kept by the server. Names come from clients and may
+//! contain line breaks, so fields are escaped to keep every record on its own line.
+
+/// Replaces backslashes and line breaks with `\\`, `\n` and `\r`.
+pub fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Inverse of [`escape`].
+pub fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        for field in ["Hall", "two\nlines", "back\\slash\\n", "\r\n", ""] {
+            let escaped = escape(field);
+            assert!(!escaped.contains(['\n', '\r']));
+            assert_eq!(field, unescape(&escaped));
+        }
+        assert_eq!("a\\nb", escape("a\nb"));
+    }
+}
@@ -0,0 +1,96 @@
+//! Sunrise and sunset times by the sunrise equation, precise to a couple of minutes.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+const J2000: f64 = 2_451_545.0;
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2_440_587.5;
+const SECONDS_PER_DAY: f64 = 86_400.0;
+/// Sun altitude at sunrise, accounts for refraction and the solar disc size.
+const HORIZON: f64 = -0.833;
+const AXIAL_TILT: f64 = 23.4397;
+
+/// Place on Earth, degrees. Longitude is positive to the east.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SunEvent {
+    Sunrise,
+    Sunset,
+}
+
+impl Location {
+    /// UTC time of the event on the date, `None` during polar day or night.
+    pub fn sun_event(&self, date: NaiveDate, event: SunEvent) -> Option<NaiveDateTime> {
+        let days = (date - NaiveDate::from_ymd_opt(2000, 1, 1)?).num_days() as f64;
+        let mean_solar_time = days - self.longitude / 360.0;
+        let anomaly = (357.5291 + 0.98560028 * mean_solar_time).rem_euclid(360.0);
+        let m = anomaly.to_radians();
+        let center = 1.9148 * m.sin() + 0.02 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+        let ecliptic_longitude = (anomaly + center + 180.0 + 102.9372)
+            .rem_euclid(360.0)
+            .to_radians();
+        let transit =
+            J2000 + mean_solar_time + 0.0053 * m.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+        let declination = (ecliptic_longitude.sin() * AXIAL_TILT.to_radians().sin()).asin();
+        let latitude = self.latitude.to_radians();
+        let cos_hour_angle = (HORIZON.to_radians().sin() - latitude.sin() * declination.sin())
+            / (latitude.cos() * declination.cos());
+        if !(-1.0..=1.0).contains(&cos_hour_angle) {
+            return None;
+        }
+        let hour_angle = cos_hour_angle.acos().to_degrees() / 360.0;
+        let julian_day = match event {
+            SunEvent::Sunrise => transit - hour_angle,
+            SunEvent::Sunset => transit + hour_angle,
+        };
+        let seconds = (julian_day - UNIX_EPOCH_JULIAN_DAY) * SECONDS_PER_DAY;
+        DateTime::from_timestamp(seconds.round() as i64, 0).map(|time| time.naive_utc())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveTime;
+
+    fn minutes_between(left: NaiveDateTime, right: NaiveTime) -> i64 {
+        (left.time() - right).num_minutes().abs()
+    }
+
+    #[test]
+    fn test_equator_at_equinox() {
+        let equator = Location {
+            latitude: 0.0,
+            longitude: 0.0,
+        };
+        let date = NaiveDate::from_ymd_opt(2022, 3, 20).unwrap();
+        let sunrise = equator.sun_event(date, SunEvent::Sunrise).unwrap();
+        let sunset = equator.sun_event(date, SunEvent::Sunset).unwrap();
+        assert_eq!(date, sunrise.date());
+        assert!(minutes_between(sunrise, NaiveTime::from_hms_opt(6, 4, 0).unwrap()) < 5);
+        assert!(minutes_between(sunset, NaiveTime::from_hms_opt(18, 11, 0).unwrap()) < 5);
+    }
+
+    #[test]
+    fn test_moscow_and_polar_day() {
+        let moscow = Location {
+            latitude: 55.75,
+            longitude: 37.62,
+        };
+        let date = NaiveDate::from_ymd_opt(2022, 6, 21).unwrap();
+        // 03:44 and 21:18 local time, UTC+3.
+        let sunrise = moscow.sun_event(date, SunEvent::Sunrise).unwrap();
+        assert!(minutes_between(sunrise, NaiveTime::from_hms_opt(0, 44, 0).unwrap()) < 5);
+        let sunset = moscow.sun_event(date, SunEvent::Sunset).unwrap();
+        assert!(minutes_between(sunset, NaiveTime::from_hms_opt(18, 18, 0).unwrap()) < 5);
+        let svalbard = Location {
+            latitude: 78.2,
+            longitude: 15.6,
+        };
+        assert!(svalbard.sun_event(date, SunEvent::Sunset).is_none());
+    }
+}
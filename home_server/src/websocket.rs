@@ -0,0 +1,208 @@
+//! WebSocket bridge for browsers: STP commands as JSON and pushed events.
+//!
+//! A request is `{"id": 1, "command": ["get device", "R", "T"]}`, the answer is
+//! `{"id": 1, "ok": true, "fields": [...]}` or `{"id": 1, "ok": false, "error": "..."}`.
+//! The `id` is optional and only echoed. After `["subscribe"]` events are pushed as
+//! `{"event": "device", "room": ..., "device": ..., "state": {...}}`, `alert` or `notification`,
+//! values in °C, W and kWh. Commands are still accepted while subscribed.
+
+use crate::context::Context;
+use crate::events::Event;
+use crate::request_handler::{Handler, Request, OK_RESPONSE, SEPARATOR, SUBSCRIBE};
+use crate::rest;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::{Error, Message};
+use tracing::{debug, info, info_span, warn, Instrument};
+
+pub async fn serve(listener: TcpListener, context: Context) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!(error = %e, "WebSocket connection failed");
+                continue;
+            }
+        };
+        let context = context.clone();
+        let span = info_span!("websocket", %peer);
+        tokio::spawn(
+            async move {
+                info!("connected");
+                match handle_connection(stream, peer, context).await {
+                    Ok(()) | Err(Error::ConnectionClosed) => info!("disconnected"),
+                    Err(e) => debug!(error = %e, "connection failed"),
+                }
+            }
+            .instrument(span),
+        );
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    context: Context,
+) -> Result<(), Error> {
+    let (mut sink, mut messages) = tokio_tungstenite::accept_async(stream).await?.split();
+    let mut handler = Handler::new(&context, &format!("ws {peer}"));
+    let mut events = None;
+    loop {
+        tokio::select! {
+            message = messages.next() => match message.transpose()? {
+                Some(Message::Text(text)) => {
+                    let answer = respond(&mut handler, &context, &text, &mut events).await;
+                    sink.send(Message::text(answer.to_string())).await?;
+                }
+                Some(Message::Close(_)) | None => return Ok(()),
+                // Pings are answered by the library.
+                Some(_) => {}
+            },
+            Some(event) = next_event(&mut events) => {
+                sink.send(Message::text(event_json(&event).to_string())).await?;
+            }
+        }
+    }
+}
+
+async fn respond(
+    handler: &mut Handler,
+    context: &Context,
+    text: &str,
+    events: &mut Option<broadcast::Receiver<Event>>,
+) -> Value {
+    let request: Value = serde_json::from_str(text).unwrap_or_default();
+    let id = request.get("id").cloned().unwrap_or_default();
+    let command: Option<Vec<&str>> = request
+        .get("command")
+        .and_then(Value::as_array)
+        .and_then(|fields| fields.iter().map(Value::as_str).collect());
+    let command = match command {
+        Some(command) if !command.is_empty() => command,
+        _ => {
+            let error = "Request must have a command, a list of strings.";
+            return json!({ "id": id, "ok": false, "error": error });
+        }
+    };
+    // Fields must not run into each other.
+    if command.iter().any(|field| field.contains(SEPARATOR)) {
+        return json!({ "id": id, "ok": false, "error": "Bad field." });
+    }
+    if command == [SUBSCRIBE] {
+        *events = Some(context.events.subscribe());
+        return json!({ "id": id, "ok": true, "fields": [] });
+    }
+    let raw = command.join(SEPARATOR);
+    let response = handler.respond(&mut Request::new(&raw)).await;
+    let mut fields = response.split(SEPARATOR);
+    match fields.next() {
+        Some(OK_RESPONSE) => json!({ "id": id, "ok": true, "fields": fields.collect::<Vec<_>>() }),
+        _ => {
+            let error = fields.collect::<Vec<_>>().join(" ");
+            json!({ "id": id, "ok": false, "error": error })
+        }
+    }
+}
+
+/// Next event of a subscribed connection, `None` for a connection that isn't.
+async fn next_event(events: &mut Option<broadcast::Receiver<Event>>) -> Option<Event> {
+    let events = events.as_mut()?;
+    loop {
+        match events.recv().await {
+            Ok(event) => return Some(event),
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
+
+fn event_json(event: &Event) -> Value {
+    match event {
+        Event::Notification { source, message } => {
+            json!({ "event": "notification", "source": source, "message": message })
+        }
+        Event::Alert {
+            name,
+            path,
+            state,
+            value,
+            ..
+        } => json!({
+            "event": "alert",
+            "name": name,
+            "room": path.room,
+            "device": path.device,
+            "state": state.as_str(),
+            "value": value,
+        }),
+        Event::Device { path, device } => json!({
+            "event": "device",
+            "room": path.room,
+            "device": path.device,
+            "state": rest::device_json(device),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::DeviceWatch;
+    use smart_home::smart_device::{Device, Thermometer};
+
+    async fn start() -> (SocketAddr, Context) {
+        let context = Context::for_tests();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, context.clone()));
+        (addr, context)
+    }
+
+    #[tokio::test]
+    async fn test_commands_and_events() {
+        let (addr, context) = start().await;
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}"))
+            .await
+            .unwrap();
+        let mut exchange = async |request: &str| {
+            socket.send(Message::text(request)).await.unwrap();
+            let answer = socket.next().await.unwrap().unwrap();
+            serde_json::from_str::<Value>(answer.to_text().unwrap()).unwrap()
+        };
+        assert_eq!(
+            json!({ "id": 1, "ok": true, "fields": ["R"] }),
+            exchange(r#"{"id": 1, "command": ["room list"]}"#).await
+        );
+        let answer = exchange(r#"{"command": ["get device", "R", "X"]}"#).await;
+        assert_eq!(json!(false), answer["ok"]);
+        assert_eq!(json!(false), exchange("not json").await["ok"]);
+        assert_eq!(
+            json!(true),
+            exchange(r#"{"command": ["subscribe"]}"#).await["ok"]
+        );
+
+        let mut watch = DeviceWatch::default();
+        watch.check(&*context.home.read().await, &context.events);
+        *context
+            .home
+            .write()
+            .await
+            .get_device_by_path_mut("R", "T")
+            .unwrap() = Device::Thermometer(Thermometer::new(42.5));
+        watch.check(&*context.home.read().await, &context.events);
+        let event = socket.next().await.unwrap().unwrap();
+        let event: Value = serde_json::from_str(event.to_text().unwrap()).unwrap();
+        assert_eq!(
+            json!({
+                "event": "device",
+                "room": "R",
+                "device": "T",
+                "state": { "kind": "thermometer", "temperature": 42.5 },
+            }),
+            event
+        );
+    }
+}
@@ -18,20 +18,11 @@ fn average_temperature_in_room() {
         "Thermo2",
         smart_device::Thermometer::new(26_f64).into(),
     );
-    if let Some(room) = home.get_room_by_name("Main") {
-        let mut temperature = 0_f64;
-        let mut count = 0_usize;
-        for device in room.device_list() {
-            if let smart_device::Device::Thermometer(thermometer) = device {
-                count += 1;
-                temperature += thermometer.get_temperature();
-            }
-        }
-        let average_temperature = temperature / count as f64;
-        println!(
-            "Average temperature in Main room is {}",
-            average_temperature
-        );
+    if let Some(temperature) = home
+        .get_room_by_name("Main")
+        .and_then(|room| room.average_temperature())
+    {
+        println!("Average temperature in Main room is {}", temperature);
     }
 }
 
@@ -51,12 +42,12 @@ fn report_example() {
     home.add_device(
         "Main",
         "S1",
-        smart_device::Socket::new(220., 5., true).into(),
+        smart_device::Socket::new(220., 5., true).unwrap().into(),
     );
     home.add_device(
         "Main",
         "S2",
-        smart_device::Socket::new(220., 0., false).into(),
+        smart_device::Socket::new(220., 0., false).unwrap().into(),
     );
 
     home.add_room("Kitchen");
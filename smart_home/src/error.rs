@@ -0,0 +1,58 @@
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SocketError {
+    #[error("Voltage {0} V is out of range.")]
+    Voltage(f64),
+    #[error("Current {0} A is out of range.")]
+    Current(f64),
+    #[error("Frequency {0} Hz is out of range.")]
+    Frequency(f64),
+    #[error("Power factor {0} is out of range.")]
+    PowerFactor(f64),
+    #[error("Energy {0} kWh is out of range.")]
+    Energy(f64),
+    #[error("Peak power {0} W is out of range.")]
+    PeakPower(f64),
+    #[error("Power limit {0} W must be greater than zero.")]
+    PowerLimit(f64),
+}
+
+pub type SocketResult<T> = Result<T, SocketError>;
+
+/// Why an operation on a single device failed.
+#[derive(Debug, Error, PartialEq)]
+pub enum DeviceError {
+    #[error("Device '{0}' not found.")]
+    NotFound(String),
+    #[error("Operation is not supported by device '{0}'.")]
+    NotSupported(String),
+    #[error("Power limit of device '{0}' is exceeded.")]
+    PowerLimit(String),
+    #[error("Device '{0}' is of another kind.")]
+    KindMismatch(String),
+    #[error(transparent)]
+    Socket(#[from] SocketError),
+}
+
+pub type DeviceResult<T> = Result<T, DeviceError>;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum QueryError {
+    #[error("Unknown field '{0}'.")]
+    UnknownField(String),
+    #[error("Bad condition '{0}'.")]
+    BadCondition(String),
+    #[error("Field '{0}' is not numeric.")]
+    NotNumeric(String),
+    #[error("Bad number '{0}'.")]
+    BadNumber(String),
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum MetadataError {
+    #[error("Bad area '{0}'.")]
+    Area(String),
+}
+
+pub type MetadataResult<T> = Result<T, MetadataError>;
@@ -0,0 +1,85 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Address of a device in the home.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DevicePath {
+    pub room: String,
+    pub device: String,
+}
+
+/// Named set of devices which may span rooms. Members are kept by path
+/// and dropped when the device is removed from the home.
+#[derive(Debug, Default, PartialEq)]
+pub struct Group {
+    members: BTreeSet<DevicePath>,
+}
+
+impl DevicePath {
+    pub fn new(room: &str, device: &str) -> Self {
+        Self {
+            room: room.into(),
+            device: device.into(),
+        }
+    }
+}
+
+impl fmt::Display for DevicePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.room, self.device)
+    }
+}
+
+impl Group {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn members(&self) -> impl Iterator<Item = &DevicePath> {
+        self.members.iter()
+    }
+
+    pub fn contains(&self, path: &DevicePath) -> bool {
+        self.members.contains(path)
+    }
+
+    pub fn add(&mut self, path: DevicePath) -> bool {
+        self.members.insert(path)
+    }
+
+    pub fn remove(&mut self, path: &DevicePath) -> bool {
+        self.members.remove(path)
+    }
+
+    /// Keeps only the members for which `keep` returns true.
+    pub fn retain(&mut self, keep: impl FnMut(&DevicePath) -> bool) {
+        self.members.retain(keep);
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group() {
+        let mut group = Group::new();
+        assert!(group.is_empty());
+        assert!(group.add(DevicePath::new("R1", "S")));
+        assert!(group.add(DevicePath::new("R2", "S")));
+        assert!(!group.add(DevicePath::new("R1", "S")));
+        assert_eq!(2, group.len());
+        assert!(group.contains(&DevicePath::new("R2", "S")));
+        assert!(group.remove(&DevicePath::new("R2", "S")));
+        assert!(!group.remove(&DevicePath::new("R2", "S")));
+        assert_eq!("R1/S", group.members().next().unwrap().to_string());
+    }
+}
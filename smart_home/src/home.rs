@@ -1,11 +1,22 @@
+use crate::error::{DeviceError, DeviceResult};
+use crate::group::{DevicePath, Group};
+use crate::layout::{join_path, split_path, Zone};
+use crate::metadata::{DeviceMetadata, RoomMetadata};
+use crate::scene::{DeviceState, Scene};
 use crate::smart_device::{Device, DeviceInfo};
 use crate::smart_room::Room;
-use std::collections::{hash_map::Entry, HashMap};
+use crate::stats::Stats;
+use crate::units::Units;
+use std::collections::{btree_map, hash_map::Entry, BTreeMap, HashMap};
+use std::time::Duration;
 
 #[allow(dead_code, unused)]
 pub struct Home {
     name: String,
     rooms: HashMap<String, Room>,
+    layout: Zone,
+    groups: BTreeMap<String, Group>,
+    scenes: BTreeMap<String, Scene>,
 }
 
 #[allow(dead_code, unused)]
@@ -14,6 +25,9 @@ impl Home {
         Home {
             name: String::from(name),
             rooms: HashMap::new(),
+            layout: Zone::new(),
+            groups: BTreeMap::new(),
+            scenes: BTreeMap::new(),
         }
     }
 
@@ -28,12 +42,111 @@ impl Home {
     pub fn add_room(&mut self, unique_name: &str) -> Option<&Room> {
         match self.rooms.entry(unique_name.into()) {
             Entry::Occupied(_) => None,
-            Entry::Vacant(entry) => Some(entry.insert(Default::default())),
+            Entry::Vacant(entry) => {
+                self.layout.insert_room(unique_name);
+                Some(entry.insert(Default::default()))
+            }
         }
     }
 
+    /// Removes the room, its devices are dropped from groups and scenes.
     pub fn remove_room(&mut self, room_name: &str) -> Option<Room> {
-        self.rooms.remove(room_name)
+        self.layout.remove_room(room_name);
+        let room = self.rooms.remove(room_name)?;
+        self.forget_devices(|path| path.room == room_name);
+        Some(room)
+    }
+
+    /// Root of the floors and zones tree.
+    pub fn layout(&self) -> &Zone {
+        &self.layout
+    }
+
+    /// Creates a zone like `floor2/east` together with missing parents.
+    pub fn add_zone(&mut self, path: &str) -> bool {
+        self.layout.add_zone(path)
+    }
+
+    /// Removes the zone if there are no rooms in it.
+    pub fn remove_zone(&mut self, path: &str) -> bool {
+        self.layout.remove_zone(path)
+    }
+
+    /// Moves the room to the zone, an empty path means the root zone.
+    pub fn place_room(&mut self, room_name: &str, zone_path: &str) -> bool {
+        if !self.rooms.contains_key(room_name) || self.layout.get_zone(zone_path).is_none() {
+            return false;
+        }
+        self.layout.remove_room(room_name);
+        if let Some(zone) = self.layout.get_zone_mut(zone_path) {
+            zone.insert_room(room_name);
+        }
+        true
+    }
+
+    /// Path of the zone containing the room.
+    pub fn room_zone(&self, room_name: &str) -> Option<String> {
+        self.layout.find_room(room_name)
+    }
+
+    /// Rooms in the zone and all its nested zones.
+    pub fn rooms_in_zone(&self, zone_path: &str) -> Option<Vec<&String>> {
+        self.layout
+            .get_zone(zone_path)
+            .map(|zone| zone.all_room_names())
+    }
+
+    /// Paths `(room, device)` of every device in the zone and all its nested zones.
+    pub fn devices_in_zone(&self, zone_path: &str) -> Option<Vec<(&String, &String)>> {
+        let rooms = self.rooms_in_zone(zone_path)?;
+        let mut result = vec![];
+        for room_name in rooms {
+            if let Some(room) = self.rooms.get(room_name) {
+                result.extend(room.device_names_list().map(|device| (room_name, device)));
+            }
+        }
+        Some(result)
+    }
+
+    /// Energy consumed by sockets in the zone and all its nested zones, kWh.
+    pub fn zone_energy(&self, zone_path: &str) -> Option<f64> {
+        let rooms = self.rooms_in_zone(zone_path)?;
+        Some(
+            rooms
+                .into_iter()
+                .filter_map(|room| self.room_energy(room))
+                .sum(),
+        )
+    }
+
+    /// Full path of the device like `floor2/kitchen/socket1`.
+    pub fn device_full_path(&self, room_name: &str, device_name: &str) -> Option<String> {
+        self.get_device_by_path(room_name, device_name)?;
+        let zone = self.room_zone(room_name)?;
+        Some(join_path(&[zone.as_str(), room_name, device_name]))
+    }
+
+    /// Finds the device by its full path like `floor2/kitchen/socket1`.
+    pub fn get_device_by_full_path(&self, path: &str) -> Option<&Device> {
+        let (room_name, device_name) = self.split_full_path(path)?;
+        self.get_device_by_path(room_name, device_name)
+    }
+
+    pub fn get_device_by_full_path_mut(&mut self, path: &str) -> Option<&mut Device> {
+        let (room_name, device_name) = self.split_full_path(path)?;
+        self.get_device_by_path_mut(room_name, device_name)
+    }
+
+    /// Splits the full path into room and device names if the room lies in the given zone.
+    pub fn split_full_path<'a>(&self, path: &'a str) -> Option<(&'a str, &'a str)> {
+        let names: Vec<&str> = split_path(path).collect();
+        let (device_name, rest) = names.split_last()?;
+        let (room_name, zones) = rest.split_last()?;
+        if self.room_zone(room_name)? == join_path(zones) {
+            Some((room_name, device_name))
+        } else {
+            None
+        }
     }
 
     pub fn get_room_by_name(&self, room_name: &str) -> Option<&Room> {
@@ -57,10 +170,24 @@ impl Home {
             .and_then(|room| room.add_device(unique_name, device))
     }
 
+    /// Removes the device, it is dropped from groups and scenes.
     pub fn remove_device(&mut self, room_name: &str, device_name: &str) -> Option<Device> {
-        self.rooms
+        let device = self
+            .rooms
             .get_mut(room_name)
-            .and_then(|room| room.remove_device(device_name))
+            .and_then(|room| room.remove_device(device_name))?;
+        self.forget_devices(|path| path.room == room_name && path.device == device_name);
+        Some(device)
+    }
+
+    /// Drops the devices for which `removed` returns true from groups and scenes.
+    fn forget_devices(&mut self, removed: impl Fn(&DevicePath) -> bool) {
+        for group in self.groups.values_mut() {
+            group.retain(|path| !removed(path));
+        }
+        for scene in self.scenes.values_mut() {
+            scene.retain(|path| !removed(path));
+        }
     }
 
     pub fn get_device_by_path(&self, room_name: &str, device_name: &str) -> Option<&Device> {
@@ -79,13 +206,230 @@ impl Home {
             .and_then(|room| room.get_device_by_name_mut(device_name))
     }
 
+    pub fn group_names_list(&self) -> impl Iterator<Item = &String> {
+        self.groups.keys()
+    }
+
+    pub fn get_group(&self, group_name: &str) -> Option<&Group> {
+        self.groups.get(group_name)
+    }
+
+    pub fn add_group(&mut self, group_name: &str) -> bool {
+        match self.groups.entry(group_name.into()) {
+            btree_map::Entry::Occupied(_) => false,
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(Group::new());
+                true
+            }
+        }
+    }
+
+    pub fn remove_group(&mut self, group_name: &str) -> Option<Group> {
+        self.groups.remove(group_name)
+    }
+
+    /// Adds an existing device to an existing group.
+    pub fn add_to_group(&mut self, group_name: &str, room_name: &str, device_name: &str) -> bool {
+        if self.get_device_by_path(room_name, device_name).is_none() {
+            return false;
+        }
+        self.groups
+            .get_mut(group_name)
+            .is_some_and(|group| group.add(DevicePath::new(room_name, device_name)))
+    }
+
+    pub fn remove_from_group(
+        &mut self,
+        group_name: &str,
+        room_name: &str,
+        device_name: &str,
+    ) -> bool {
+        self.groups
+            .get_mut(group_name)
+            .is_some_and(|group| group.remove(&DevicePath::new(room_name, device_name)))
+    }
+
+    /// Applies the operation to every member of the group and reports the outcome per device.
+    pub fn for_each_in_group<F>(
+        &mut self,
+        group_name: &str,
+        mut operation: F,
+    ) -> Option<Vec<(DevicePath, DeviceResult<()>)>>
+    where
+        F: FnMut(&DevicePath, &mut Device) -> DeviceResult<()>,
+    {
+        let members: Vec<DevicePath> = self.groups.get(group_name)?.members().cloned().collect();
+        let mut result = vec![];
+        for path in members {
+            let outcome = match self.get_device_by_path_mut(&path.room, &path.device) {
+                Some(device) => operation(&path, device),
+                None => Err(DeviceError::NotFound(path.to_string())),
+            };
+            result.push((path, outcome));
+        }
+        Some(result)
+    }
+
+    /// Switches every socket of the group. Other devices are reported as not supported.
+    pub fn switch_group(
+        &mut self,
+        group_name: &str,
+        on: bool,
+    ) -> Option<Vec<(DevicePath, DeviceResult<()>)>> {
+        self.for_each_in_group(group_name, |path, device| match device {
+            Device::Socket(socket) => {
+                socket.switch(on);
+                if socket.is_on() == on {
+                    Ok(())
+                } else {
+                    Err(DeviceError::PowerLimit(path.to_string()))
+                }
+            }
+            _ => Err(DeviceError::NotSupported(path.to_string())),
+        })
+    }
+
+    pub fn scene_names_list(&self) -> impl Iterator<Item = &String> {
+        self.scenes.keys()
+    }
+
+    pub fn get_scene(&self, scene_name: &str) -> Option<&Scene> {
+        self.scenes.get(scene_name)
+    }
+
+    /// Captures the commanded states of the devices into the scene,
+    /// a scene with the same name is replaced.
+    pub fn capture_scene(&mut self, scene_name: &str, paths: &[DevicePath]) -> DeviceResult<()> {
+        let mut scene = Scene::new();
+        for path in paths {
+            let device = self
+                .get_device_by_path(&path.room, &path.device)
+                .ok_or_else(|| DeviceError::NotFound(path.to_string()))?;
+            let state = DeviceState::capture(device)
+                .ok_or_else(|| DeviceError::NotSupported(path.to_string()))?;
+            scene.set(path.clone(), state);
+        }
+        self.scenes.insert(scene_name.into(), scene);
+        Ok(())
+    }
+
+    pub fn remove_scene(&mut self, scene_name: &str) -> Option<Scene> {
+        self.scenes.remove(scene_name)
+    }
+
+    /// Either every device of the scene takes its state or, on the first failure, none does.
+    pub fn apply_scene(&mut self, scene_name: &str) -> Option<DeviceResult<()>> {
+        let scene = self.scenes.get(scene_name)?;
+        let mut updated = vec![];
+        for (path, state) in scene.states() {
+            let mut device = match self.get_device_by_path(&path.room, &path.device) {
+                Some(device) => device.clone(),
+                None => return Some(Err(DeviceError::NotFound(path.to_string()))),
+            };
+            if let Err(e) = state.apply_to(path, &mut device) {
+                return Some(Err(e));
+            }
+            updated.push((path.clone(), device));
+        }
+        for (path, device) in updated {
+            if let Some(current) = self.get_device_by_path_mut(&path.room, &path.device) {
+                *current = device;
+            }
+        }
+        Some(Ok(()))
+    }
+
+    pub fn room_metadata(&self, room_name: &str) -> Option<&RoomMetadata> {
+        self.rooms.get(room_name).map(|room| room.metadata())
+    }
+
+    pub fn room_metadata_mut(&mut self, room_name: &str) -> Option<&mut RoomMetadata> {
+        self.rooms
+            .get_mut(room_name)
+            .map(|room| room.metadata_mut())
+    }
+
+    pub fn device_metadata(&self, room_name: &str, device_name: &str) -> Option<&DeviceMetadata> {
+        self.rooms
+            .get(room_name)
+            .and_then(|room| room.device_metadata(device_name))
+    }
+
+    pub fn device_metadata_mut(
+        &mut self,
+        room_name: &str,
+        device_name: &str,
+    ) -> Option<&mut DeviceMetadata> {
+        self.rooms
+            .get_mut(room_name)
+            .and_then(|room| room.device_metadata_mut(device_name))
+    }
+
+    /// Paths `(room, device)` of devices labelled with the tag.
+    pub fn devices_with_tag(&self, tag: &str) -> Vec<(&String, &String)> {
+        let mut result = vec![];
+        for (room_name, room) in self.rooms.iter() {
+            for device_name in room.device_names_list() {
+                if room
+                    .device_metadata(device_name)
+                    .is_some_and(|metadata| metadata.has_tag(tag))
+                {
+                    result.push((room_name, device_name));
+                }
+            }
+        }
+        result
+    }
+
+    /// Aggregated readings of every device in the home.
+    pub fn stats(&self) -> Stats {
+        Stats::collect(self.rooms.values().flat_map(|room| room.device_list()))
+    }
+
+    pub fn room_stats(&self, room_name: &str) -> Option<Stats> {
+        self.rooms.get(room_name).map(|room| room.stats())
+    }
+
+    /// Aggregated readings of the devices in the zone and all its nested zones.
+    pub fn zone_stats(&self, zone_path: &str) -> Option<Stats> {
+        let rooms = self.rooms_in_zone(zone_path)?;
+        Some(Stats::collect(
+            rooms
+                .into_iter()
+                .filter_map(|room| self.rooms.get(room))
+                .flat_map(|room| room.device_list()),
+        ))
+    }
+
+    /// Energy consumed by sockets in the room, kWh.
+    pub fn room_energy(&self, room_name: &str) -> Option<f64> {
+        self.rooms.get(room_name).map(|room| room.total_energy())
+    }
+
+    /// Energy consumed by all sockets in the home, kWh.
+    pub fn total_energy(&self) -> f64 {
+        self.rooms.values().map(|room| room.total_energy()).sum()
+    }
+
+    /// Lets the time go by for every device in the home.
+    pub fn advance(&mut self, elapsed: Duration) {
+        for room in self.rooms.values_mut() {
+            room.advance(elapsed);
+        }
+    }
+
     pub fn report(&self) -> String {
+        self.report_in(&Units::default())
+    }
+
+    pub fn report_in(&self, units: &Units) -> String {
         let mut lines = vec![format!("General report about {}:", self.name)];
         for room_name in self.room_names_list() {
             lines.push(format!("\tIn room '{}'", room_name));
             let room = self.get_room_by_name(room_name).unwrap();
-            for device in room.device_list() {
-                lines.push(format!("\t\t{:?}", device.device_info()));
+            for device_name in room.device_names_list() {
+                let device = room.get_device_by_name(device_name).unwrap();
+                lines.push(format!("\t\t'{}': {}", device_name, device.describe(units)));
             }
         }
         lines.join("\n")
@@ -96,6 +440,8 @@ impl Home {
         h.add_room("R");
         h.add_device("R", "S", Device::new_socket());
         h.add_device("R", "T", Device::new_thermometer());
+        h.add_device("R", "L", Device::new_lock());
+        h.add_device("R", "W", Device::new_window_covering());
         h
     }
 }
@@ -103,6 +449,7 @@ impl Home {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::smart_device::{LockState, Socket, Thermometer};
     #[test]
     fn test_home() {
         let home = Home::new("Home");
@@ -214,4 +561,238 @@ mod tests {
         assert!(home.remove_device("R2", "T1").is_some());
         assert!(home.device_names_list("R2").unwrap().is_empty());
     }
+
+    #[test]
+    fn test_advance() {
+        let mut home = Home::new("Home with actuators");
+        home.add_room("R");
+        home.add_device("R", "L", Device::new_lock());
+        if let Some(Device::Lock(lock)) = home.get_device_by_path_mut("R", "L") {
+            lock.lock();
+        }
+        home.advance(Duration::from_secs(5));
+        if let Some(Device::Lock(lock)) = home.get_device_by_path("R", "L") {
+            assert!(lock.is_locked());
+        } else {
+            panic!("Lock is lost.");
+        }
+    }
+
+    #[test]
+    fn test_energy() {
+        let mut home = Home::new("Home with sockets");
+        home.add_room("R1");
+        home.add_room("R2");
+        home.add_device("R1", "S", Socket::new(200_f64, 5_f64, true).unwrap().into());
+        home.add_device(
+            "R2",
+            "S",
+            Socket::new(200_f64, 10_f64, true).unwrap().into(),
+        );
+        home.add_device("R2", "T", Device::new_thermometer());
+        home.advance(Duration::from_secs(3600));
+        assert!((home.room_energy("R1").unwrap() - 1_f64).abs() < 1e-9);
+        assert!((home.room_energy("R2").unwrap() - 2_f64).abs() < 1e-9);
+        assert!((home.total_energy() - 3_f64).abs() < 1e-9);
+        assert!(home.room_energy("R3").is_none());
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut home = Home::new("Home with labels");
+        home.add_room("R1");
+        home.add_room("R2");
+        home.add_device("R1", "S", Device::new_socket());
+        home.add_device("R2", "S", Device::new_socket());
+        home.add_device("R2", "T", Device::new_thermometer());
+        home.room_metadata_mut("R2").unwrap().area = Some(12_f64);
+        assert_eq!(Some(12_f64), home.room_metadata("R2").unwrap().area);
+        assert!(home.room_metadata("R3").is_none());
+        home.device_metadata_mut("R1", "S")
+            .unwrap()
+            .add_tag("night");
+        home.device_metadata_mut("R2", "T")
+            .unwrap()
+            .add_tag("night");
+        let mut tagged = home.devices_with_tag("night");
+        tagged.sort();
+        assert_eq!(
+            vec![
+                (&String::from("R1"), &String::from("S")),
+                (&String::from("R2"), &String::from("T"))
+            ],
+            tagged
+        );
+        assert!(home.device_metadata("R1", "T").is_none());
+    }
+
+    #[test]
+    fn test_layout() {
+        let mut home = Home::new("Home with floors");
+        home.add_room("hall");
+        home.add_room("kitchen");
+        home.add_device("hall", "L", Device::new_lock());
+        home.add_device(
+            "kitchen",
+            "S",
+            Socket::new(200_f64, 5_f64, true).unwrap().into(),
+        );
+        assert!(home.add_zone("floor2/east"));
+        assert!(home.place_room("kitchen", "floor2/east"));
+        assert!(!home.place_room("kitchen", "floor3"));
+        assert!(!home.place_room("attic", "floor2"));
+        assert_eq!(Some(String::from("floor2/east")), home.room_zone("kitchen"));
+        assert_eq!(Some(String::new()), home.room_zone("hall"));
+        assert!(home
+            .get_device_by_full_path("floor2/east/kitchen/S")
+            .is_some());
+        assert!(home.get_device_by_full_path("kitchen/S").is_none());
+        assert!(home.get_device_by_full_path("hall/L").is_some());
+        assert!(home
+            .get_device_by_full_path_mut("floor2/east/kitchen/S")
+            .is_some());
+        assert_eq!(
+            Some(String::from("floor2/east/kitchen/S")),
+            home.device_full_path("kitchen", "S")
+        );
+        assert_eq!(2, home.rooms_in_zone("").unwrap().len());
+        assert_eq!(
+            vec![(&String::from("kitchen"), &String::from("S"))],
+            home.devices_in_zone("floor2").unwrap()
+        );
+        home.advance(Duration::from_secs(3600));
+        assert!((home.zone_energy("floor2").unwrap() - 1_f64).abs() < 1e-9);
+        assert!(home.zone_energy("floor3").is_none());
+        assert!(!home.remove_zone("floor2"));
+        home.remove_room("kitchen");
+        assert!(home.remove_zone("floor2"));
+        // The old addressing keeps working.
+        assert!(home.get_device_by_path("hall", "L").is_some());
+    }
+
+    #[test]
+    fn test_groups() {
+        let mut home = Home::new("Home with groups");
+        home.add_room("R1");
+        home.add_room("R2");
+        home.add_device("R1", "S", Device::new_socket());
+        home.add_device(
+            "R2",
+            "S",
+            Socket::new(220_f64, 10_f64, false)
+                .unwrap()
+                .power_limit(Some(1000_f64))
+                .unwrap()
+                .into(),
+        );
+        home.add_device("R2", "T", Device::new_thermometer());
+        assert!(home.add_group("night"));
+        assert!(!home.add_group("night"));
+        assert!(home.add_to_group("night", "R1", "S"));
+        assert!(home.add_to_group("night", "R2", "S"));
+        assert!(home.add_to_group("night", "R2", "T"));
+        assert!(!home.add_to_group("night", "R2", "No device"));
+        assert!(!home.add_to_group("day", "R1", "S"));
+        assert_eq!(3, home.get_group("night").unwrap().len());
+        // A removed device leaves the group.
+        home.remove_device("R1", "S");
+        assert_eq!(2, home.get_group("night").unwrap().len());
+        let results = home.switch_group("night", true).unwrap();
+        assert_eq!(
+            vec![
+                (
+                    DevicePath::new("R2", "S"),
+                    Err(DeviceError::PowerLimit("R2/S".into()))
+                ),
+                (
+                    DevicePath::new("R2", "T"),
+                    Err(DeviceError::NotSupported("R2/T".into()))
+                ),
+            ],
+            results
+        );
+        assert!(home.remove_from_group("night", "R2", "T"));
+        assert!(!home.remove_from_group("night", "R2", "T"));
+        assert!(home.switch_group("day", true).is_none());
+        assert!(home.remove_group("night").is_some());
+        assert_eq!(0, home.group_names_list().count());
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut home = Home::new("Home with stats");
+        home.add_room("R1");
+        home.add_room("R2");
+        home.add_zone("floor2");
+        home.place_room("R2", "floor2");
+        home.add_device("R1", "T", Thermometer::new(18_f64).into());
+        home.add_device("R2", "T", Thermometer::new(22_f64).into());
+        home.add_device("R2", "S", Socket::new(200_f64, 5_f64, true).unwrap().into());
+        let stats = home.stats();
+        assert_eq!(Some(20_f64), stats.average_temperature);
+        assert_eq!(1000_f64, stats.total_power);
+        assert_eq!(3, stats.kinds.values().sum::<usize>());
+        assert_eq!(Some(18_f64), home.room_stats("R1").unwrap().max_temperature);
+        assert!(home.room_stats("R3").is_none());
+        let stats = home.zone_stats("floor2").unwrap();
+        assert_eq!(Some(22_f64), stats.min_temperature);
+        assert_eq!(1, stats.count_in_state("socket", "on"));
+    }
+
+    #[test]
+    fn test_scenes() {
+        let mut home = Home::restore();
+        let paths = [DevicePath::new("R", "S"), DevicePath::new("R", "L")];
+        if let Some(Device::Socket(socket)) = home.get_device_by_path_mut("R", "S") {
+            socket.set_current(10_f64).unwrap();
+            socket.switch(true);
+        }
+        if let Some(Device::Lock(lock)) = home.get_device_by_path_mut("R", "L") {
+            lock.lock();
+        }
+        home.capture_scene("Away", &paths).unwrap();
+        assert_eq!(
+            Err(DeviceError::NotSupported("R/T".into())),
+            home.capture_scene("Bad", &[DevicePath::new("R", "T")])
+        );
+        assert_eq!(vec!["Away"], home.scene_names_list().collect::<Vec<_>>());
+        assert_eq!(2, home.get_scene("Away").unwrap().len());
+
+        // The socket can't be switched on within the limit, so the lock keeps its state too.
+        if let Some(Device::Socket(socket)) = home.get_device_by_path_mut("R", "S") {
+            socket.set_power_limit(Some(1000_f64)).unwrap();
+        }
+        if let Some(Device::Lock(lock)) = home.get_device_by_path_mut("R", "L") {
+            lock.unlock();
+        }
+        assert_eq!(
+            Some(Err(DeviceError::PowerLimit("R/S".into()))),
+            home.apply_scene("Away")
+        );
+        assert!(matches!(
+            home.get_device_by_path("R", "L"),
+            Some(Device::Lock(lock)) if lock.get_target() == LockState::Unlocked
+        ));
+
+        if let Some(Device::Socket(socket)) = home.get_device_by_path_mut("R", "S") {
+            socket.set_power_limit(None).unwrap();
+        }
+        assert_eq!(Some(Ok(())), home.apply_scene("Away"));
+        assert!(matches!(
+            home.get_device_by_path("R", "L"),
+            Some(Device::Lock(lock)) if lock.get_target() == LockState::Locked
+        ));
+        assert!(home.apply_scene("Movie night").is_none());
+        home.capture_scene("Movie night", &paths).unwrap();
+        home.add_group("all");
+        home.add_to_group("all", "R", "S");
+        home.remove_device("R", "S");
+        assert_eq!(1, home.get_scene("Away").unwrap().len());
+        assert_eq!(Some(Ok(())), home.apply_scene("Away"));
+        home.remove_room("R");
+        assert!(home.get_scene("Movie night").unwrap().is_empty());
+        assert!(home.get_group("all").unwrap().is_empty());
+        assert!(home.remove_scene("Away").is_some());
+        assert!(home.apply_scene("Away").is_none());
+    }
 }
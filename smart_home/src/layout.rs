@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const PATH_SEPARATOR: char = '/';
+
+/// Node of the home layout: a floor, a wing, an outdoor zone and so on.
+/// Every room of the home belongs to exactly one zone, the root zone by default.
+#[derive(Debug, Default, PartialEq)]
+pub struct Zone {
+    zones: BTreeMap<String, Zone>,
+    rooms: BTreeSet<String>,
+}
+
+impl Zone {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn zone_names_list(&self) -> impl Iterator<Item = &String> {
+        self.zones.keys()
+    }
+
+    /// Rooms placed directly in the zone.
+    pub fn room_names_list(&self) -> impl Iterator<Item = &String> {
+        self.rooms.iter()
+    }
+
+    /// Rooms placed in the zone or in any nested zone.
+    pub fn all_room_names(&self) -> Vec<&String> {
+        let mut result: Vec<&String> = self.rooms.iter().collect();
+        for zone in self.zones.values() {
+            result.extend(zone.all_room_names());
+        }
+        result
+    }
+
+    pub fn get_zone(&self, path: &str) -> Option<&Zone> {
+        split_path(path).try_fold(self, |zone, name| zone.zones.get(name))
+    }
+
+    pub fn get_zone_mut(&mut self, path: &str) -> Option<&mut Zone> {
+        split_path(path).try_fold(self, |zone, name| zone.zones.get_mut(name))
+    }
+
+    /// Creates the zone together with missing parents. Returns `false` if it already exists.
+    pub fn add_zone(&mut self, path: &str) -> bool {
+        if self.get_zone(path).is_some() {
+            return false;
+        }
+        let mut zone = self;
+        for name in split_path(path) {
+            zone = zone.zones.entry(name.into()).or_default();
+        }
+        true
+    }
+
+    /// Removes the zone only if there are no rooms in it.
+    pub fn remove_zone(&mut self, path: &str) -> bool {
+        let (parent, name) = match path
+            .trim_matches(PATH_SEPARATOR)
+            .rsplit_once(PATH_SEPARATOR)
+        {
+            Some((parent, name)) => (parent, name),
+            None => ("", path.trim_matches(PATH_SEPARATOR)),
+        };
+        match self.get_zone_mut(parent) {
+            Some(parent) if parent.zones.get(name).is_some_and(Zone::is_empty) => {
+                parent.zones.remove(name).is_some()
+            }
+            _ => false,
+        }
+    }
+
+    /// Path of the zone containing the room, empty for the root zone.
+    pub fn find_room(&self, room_name: &str) -> Option<String> {
+        if self.rooms.contains(room_name) {
+            return Some(String::new());
+        }
+        self.zones.iter().find_map(|(name, zone)| {
+            zone.find_room(room_name)
+                .map(|path| join_path(&[name.as_str(), path.as_str()]))
+        })
+    }
+
+    pub(crate) fn insert_room(&mut self, room_name: &str) {
+        self.rooms.insert(room_name.into());
+    }
+
+    pub(crate) fn remove_room(&mut self, room_name: &str) -> bool {
+        self.rooms.remove(room_name)
+            || self
+                .zones
+                .values_mut()
+                .any(|zone| zone.remove_room(room_name))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.rooms.is_empty() && self.zones.values().all(Zone::is_empty)
+    }
+}
+
+/// Non-empty segments of the path.
+pub fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split(PATH_SEPARATOR)
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+}
+
+pub fn join_path(names: &[&str]) -> String {
+    let names: Vec<&str> = names.iter().copied().filter(|n| !n.is_empty()).collect();
+    names.join(&PATH_SEPARATOR.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zones() {
+        let mut root = Zone::new();
+        assert!(root.add_zone("floor2/east"));
+        assert!(root.add_zone("garden"));
+        assert!(!root.add_zone("floor2"));
+        assert!(root.get_zone("floor2/east").is_some());
+        assert!(root.get_zone("/floor2/").is_some());
+        assert!(root.get_zone("floor3").is_none());
+        let zones: Vec<&String> = root.zone_names_list().collect();
+        assert_eq!(vec!["floor2", "garden"], zones);
+        assert!(root.remove_zone("garden"));
+        assert!(!root.remove_zone("garden"));
+    }
+
+    #[test]
+    fn test_rooms() {
+        let mut root = Zone::new();
+        root.add_zone("floor2/east");
+        root.insert_room("hall");
+        root.get_zone_mut("floor2/east")
+            .unwrap()
+            .insert_room("kitchen");
+        assert_eq!(Some(String::new()), root.find_room("hall"));
+        assert_eq!(Some(String::from("floor2/east")), root.find_room("kitchen"));
+        assert!(root.find_room("attic").is_none());
+        assert_eq!(2, root.all_room_names().len());
+        assert_eq!(1, root.get_zone("floor2").unwrap().all_room_names().len());
+        assert!(!root.remove_zone("floor2"));
+        assert!(root.remove_room("kitchen"));
+        assert!(root.remove_zone("floor2"));
+    }
+}
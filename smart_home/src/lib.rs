@@ -1,8 +1,24 @@
+pub mod error;
+
+pub mod group;
+
 pub mod home;
 
+pub mod layout;
+
+pub mod metadata;
+
+pub mod query;
+
+pub mod scene;
+
 pub mod smart_room;
 
+pub mod stats;
+
 pub mod smart_device;
 
+pub mod units;
+
 #[cfg(test)]
 mod tests {}
@@ -0,0 +1,152 @@
+use crate::error::{MetadataError, MetadataResult};
+use std::collections::BTreeSet;
+
+/// What physical product the device is and how it is labelled.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceMetadata {
+    /// Name shown to people, the unique key is used when absent.
+    pub display_name: Option<String>,
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    pub firmware: Option<String>,
+    /// Install date as `YYYY-MM-DD`.
+    pub install_date: Option<String>,
+    pub tags: BTreeSet<String>,
+}
+
+/// The floor of the room is the zone it is placed in, see [`crate::home::Home::room_zone`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RoomMetadata {
+    pub display_name: Option<String>,
+    /// Area in square meters.
+    pub area: Option<f64>,
+}
+
+const TAG_SEPARATOR: char = ',';
+
+impl DeviceMetadata {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        self.tags.insert(tag.into())
+    }
+
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        self.tags.remove(tag)
+    }
+
+    /// Text fields in a fixed order, absent values are empty strings.
+    pub fn fields(&self) -> Vec<String> {
+        let mut result = vec![];
+        for field in [
+            &self.display_name,
+            &self.manufacturer,
+            &self.model,
+            &self.serial,
+            &self.firmware,
+            &self.install_date,
+        ] {
+            result.push(field.clone().unwrap_or_default());
+        }
+        let tags: Vec<&str> = self.tags.iter().map(String::as_str).collect();
+        result.push(tags.join(&TAG_SEPARATOR.to_string()));
+        result
+    }
+
+    /// Inverse of [`DeviceMetadata::fields`].
+    pub fn from_fields<'a>(fields: &mut impl Iterator<Item = &'a str>) -> Self {
+        Self {
+            display_name: optional(fields.next()),
+            manufacturer: optional(fields.next()),
+            model: optional(fields.next()),
+            serial: optional(fields.next()),
+            firmware: optional(fields.next()),
+            install_date: optional(fields.next()),
+            tags: fields
+                .next()
+                .unwrap_or_default()
+                .split(TAG_SEPARATOR)
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+impl RoomMetadata {
+    /// Text fields in a fixed order, absent values are empty strings.
+    pub fn fields(&self) -> Vec<String> {
+        vec![
+            self.display_name.clone().unwrap_or_default(),
+            self.area.map(|area| area.to_string()).unwrap_or_default(),
+        ]
+    }
+
+    /// Inverse of [`RoomMetadata::fields`]. The area must be a non-negative number.
+    pub fn from_fields<'a>(fields: &mut impl Iterator<Item = &'a str>) -> MetadataResult<Self> {
+        let display_name = optional(fields.next());
+        let area = match optional(fields.next()) {
+            Some(area) => match area.parse::<f64>() {
+                Ok(value) if value.is_finite() && value >= 0_f64 => Some(value),
+                _ => return Err(MetadataError::Area(area)),
+            },
+            None => None,
+        };
+        Ok(Self { display_name, area })
+    }
+}
+
+fn optional(field: Option<&str>) -> Option<String> {
+    match field.map(str::trim) {
+        Some("") | None => None,
+        Some(field) => Some(field.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_metadata_fields() {
+        let mut metadata = DeviceMetadata {
+            display_name: Some("Kettle".into()),
+            manufacturer: Some("ACME".into()),
+            install_date: Some("2022-01-31".into()),
+            ..Default::default()
+        };
+        assert!(metadata.add_tag("kitchen"));
+        assert!(metadata.add_tag("heater"));
+        assert!(!metadata.add_tag("heater"));
+        let fields = metadata.fields();
+        assert_eq!(
+            vec!["Kettle", "ACME", "", "", "", "2022-01-31", "heater,kitchen"],
+            fields
+        );
+        let restored = DeviceMetadata::from_fields(&mut fields.iter().map(String::as_str));
+        assert_eq!(metadata, restored);
+        assert!(restored.has_tag("kitchen"));
+    }
+
+    #[test]
+    fn test_room_metadata_fields() {
+        let metadata = RoomMetadata {
+            display_name: None,
+            area: Some(12.5),
+        };
+        let fields = metadata.fields();
+        assert_eq!(vec!["", "12.5"], fields);
+        let restored = RoomMetadata::from_fields(&mut fields.iter().map(String::as_str));
+        assert_eq!(Ok(metadata), restored);
+        for area in ["big", "-1", "NaN", "inf"] {
+            assert_eq!(
+                Err(MetadataError::Area(area.into())),
+                RoomMetadata::from_fields(&mut ["Hall", area].into_iter())
+            );
+        }
+    }
+}
@@ -0,0 +1,494 @@
+//! Selecting devices by kind, room, zone, tag and state predicates.
+//!
+//! A query is a list of conditions joined with `and`, optionally preceded by an aggregate:
+//!
+//! ```text
+//! kind = socket and state = on and power > 1000
+//! avg temperature where kind = thermometer and zone = floor1
+//! count where tag = night
+//! ```
+//!
+//! Numbers are compared in the units given to [`Query::run`].
+
+use crate::error::QueryError;
+use crate::group::DevicePath;
+use crate::home::Home;
+use crate::layout::split_path;
+use crate::smart_device::Device;
+use crate::units::{Energy, Power, Temperature, Units};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Kind,
+    Room,
+    /// Matches rooms placed in the zone or in any zone nested in it.
+    Zone,
+    Tag,
+    State,
+    Power,
+    Current,
+    Voltage,
+    Energy,
+    Temperature,
+    Position,
+    Tilt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Number(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub field: Field,
+    pub op: Op,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Count,
+    Min(Field),
+    Max(Field),
+    Avg(Field),
+    Sum(Field),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    conditions: Vec<Condition>,
+    aggregate: Option<Aggregate>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum QueryOutput {
+    Devices(Vec<DevicePath>),
+    Count(usize),
+    /// `None` when no selected device has the field.
+    Value(Option<f64>),
+}
+
+const OPERATORS: [(&str, Op); 6] = [
+    ("!=", Op::Ne),
+    ("<=", Op::Le),
+    (">=", Op::Ge),
+    ("=", Op::Eq),
+    ("<", Op::Lt),
+    (">", Op::Gt),
+];
+
+impl Field {
+    pub fn is_numeric(&self) -> bool {
+        !matches!(
+            self,
+            Field::Kind | Field::Room | Field::Zone | Field::Tag | Field::State
+        )
+    }
+
+    /// Numeric value of the field for the device in the given units.
+    pub fn number(&self, device: &Device, units: &Units) -> Option<f64> {
+        match (self, device) {
+            (Field::Power, Device::Socket(socket)) => {
+                Some(Power::watts(socket.get_current_power()).value(units.power))
+            }
+            (Field::Current, Device::Socket(socket)) => Some(socket.get_current()),
+            (Field::Voltage, Device::Socket(socket)) => Some(socket.get_voltage()),
+            (Field::Energy, Device::Socket(socket)) => {
+                Some(Energy::kilowatt_hours(socket.get_energy()).value(units.energy))
+            }
+            (Field::Temperature, Device::Thermometer(thermometer)) => {
+                Some(thermometer.reading().value(units.temperature))
+            }
+            (Field::Position, Device::WindowCovering(covering)) => Some(covering.get_position()),
+            (Field::Tilt, Device::WindowCovering(covering)) => Some(covering.get_tilt()),
+            _ => None,
+        }
+    }
+
+    /// Converts the value of the field given in °C, W and kWh to the given units.
+    pub fn convert(&self, value: f64, units: &Units) -> f64 {
+        match self {
+            Field::Power => Power::watts(value).value(units.power),
+            Field::Energy => Energy::kilowatt_hours(value).value(units.energy),
+            Field::Temperature => Temperature::celsius(value).value(units.temperature),
+            _ => value,
+        }
+    }
+}
+
+impl FromStr for Field {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "kind" => Ok(Field::Kind),
+            "room" => Ok(Field::Room),
+            "zone" => Ok(Field::Zone),
+            "tag" => Ok(Field::Tag),
+            "state" => Ok(Field::State),
+            "power" => Ok(Field::Power),
+            "current" => Ok(Field::Current),
+            "voltage" => Ok(Field::Voltage),
+            "energy" => Ok(Field::Energy),
+            "temperature" => Ok(Field::Temperature),
+            "position" => Ok(Field::Position),
+            "tilt" => Ok(Field::Tilt),
+            _ => Err(QueryError::UnknownField(s.into())),
+        }
+    }
+}
+
+impl Op {
+    fn compare<T: PartialOrd>(&self, left: T, right: T) -> bool {
+        match self {
+            Op::Eq => left == right,
+            Op::Ne => left != right,
+            Op::Lt => left < right,
+            Op::Le => left <= right,
+            Op::Gt => left > right,
+            Op::Ge => left >= right,
+        }
+    }
+}
+
+impl Condition {
+    pub fn new(field: Field, op: Op, value: Value) -> Self {
+        Self { field, op, value }
+    }
+
+    /// Checks the condition against the device at the path, `false` if there is no such device.
+    pub fn matches_at(&self, home: &Home, path: &DevicePath, units: &Units) -> bool {
+        home.get_device_by_path(&path.room, &path.device)
+            .is_some_and(|device| self.matches(home, &path.room, &path.device, device, units))
+    }
+
+    fn matches(
+        &self,
+        home: &Home,
+        room: &str,
+        device_name: &str,
+        device: &Device,
+        units: &Units,
+    ) -> bool {
+        match &self.value {
+            Value::Number(number) => self
+                .field
+                .number(device, units)
+                .is_some_and(|value| self.op.compare(value, *number)),
+            Value::Text(text) => {
+                let equal = match self.field {
+                    Field::Kind => device.kind() == text,
+                    Field::Room => room == text,
+                    Field::Zone => home.room_zone(room).is_some_and(|zone| {
+                        let mut zone = split_path(&zone);
+                        split_path(text).all(|name| zone.next() == Some(name))
+                    }),
+                    Field::Tag => home
+                        .device_metadata(room, device_name)
+                        .is_some_and(|metadata| metadata.has_tag(text)),
+                    Field::State => device.state() == Some(text.as_str()),
+                    _ => false,
+                };
+                match self.op {
+                    Op::Ne => !equal,
+                    _ => equal,
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (position, symbol, op) = OPERATORS
+            .iter()
+            .filter_map(|(symbol, op)| s.find(symbol).map(|position| (position, symbol, op)))
+            .min_by_key(|(position, symbol, _)| (*position, usize::MAX - symbol.len()))
+            .ok_or_else(|| QueryError::BadCondition(s.into()))?;
+        let field: Field = s[..position].trim().parse()?;
+        let value = s[position + symbol.len()..].trim();
+        if value.is_empty() {
+            return Err(QueryError::BadCondition(s.into()));
+        }
+        let value = if field.is_numeric() {
+            Value::Number(
+                value
+                    .parse()
+                    .map_err(|_| QueryError::BadNumber(value.into()))?,
+            )
+        } else if matches!(op, Op::Eq | Op::Ne) {
+            Value::Text(value.into())
+        } else {
+            return Err(QueryError::NotNumeric(s[..position].trim().into()));
+        };
+        Ok(Condition::new(field, *op, value))
+    }
+}
+
+impl FromStr for Aggregate {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let function = words.next().unwrap_or_default();
+        let field = match words.next() {
+            Some(field) => Some(field.parse::<Field>()?),
+            None => None,
+        };
+        if words.next().is_some() {
+            return Err(QueryError::BadCondition(s.into()));
+        }
+        let numeric = |field: Option<Field>| match field {
+            Some(field) if field.is_numeric() => Ok(field),
+            Some(field) => Err(QueryError::NotNumeric(
+                format!("{:?}", field).to_lowercase(),
+            )),
+            None => Err(QueryError::BadCondition(s.into())),
+        };
+        match function {
+            "count" if field.is_none() => Ok(Aggregate::Count),
+            "min" => Ok(Aggregate::Min(numeric(field)?)),
+            "max" => Ok(Aggregate::Max(numeric(field)?)),
+            "avg" => Ok(Aggregate::Avg(numeric(field)?)),
+            "sum" => Ok(Aggregate::Sum(numeric(field)?)),
+            _ => Err(QueryError::BadCondition(s.into())),
+        }
+    }
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn condition(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn aggregate(mut self, aggregate: Aggregate) -> Self {
+        self.aggregate = Some(aggregate);
+        self
+    }
+
+    /// Paths of the devices matching all the conditions, sorted.
+    pub fn select<'a>(&self, home: &'a Home, units: &Units) -> Vec<(DevicePath, &'a Device)> {
+        let mut result = vec![];
+        for room_name in home.room_names_list() {
+            let room = match home.get_room_by_name(room_name) {
+                Some(room) => room,
+                None => continue,
+            };
+            for device_name in room.device_names_list() {
+                let device = match room.get_device_by_name(device_name) {
+                    Some(device) => device,
+                    None => continue,
+                };
+                if self
+                    .conditions
+                    .iter()
+                    .all(|condition| condition.matches(home, room_name, device_name, device, units))
+                {
+                    result.push((DevicePath::new(room_name, device_name), device));
+                }
+            }
+        }
+        result.sort_by(|(left, _), (right, _)| left.cmp(right));
+        result
+    }
+
+    pub fn run(&self, home: &Home, units: &Units) -> QueryOutput {
+        let selected = self.select(home, units);
+        let values = |field: Field| -> Vec<f64> {
+            selected
+                .iter()
+                .filter_map(|(_, device)| field.number(device, units))
+                .collect()
+        };
+        match self.aggregate {
+            None => QueryOutput::Devices(selected.into_iter().map(|(path, _)| path).collect()),
+            Some(Aggregate::Count) => QueryOutput::Count(selected.len()),
+            Some(Aggregate::Min(field)) => {
+                QueryOutput::Value(values(field).into_iter().reduce(f64::min))
+            }
+            Some(Aggregate::Max(field)) => {
+                QueryOutput::Value(values(field).into_iter().reduce(f64::max))
+            }
+            Some(Aggregate::Sum(field)) => QueryOutput::Value(Some(values(field).iter().sum())),
+            Some(Aggregate::Avg(field)) => {
+                let values = values(field);
+                if values.is_empty() {
+                    QueryOutput::Value(None)
+                } else {
+                    QueryOutput::Value(Some(values.iter().sum::<f64>() / values.len() as f64))
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (aggregate, conditions) = match s.split_once(" where ") {
+            Some((aggregate, conditions)) => (Some(aggregate.parse()?), conditions),
+            None if s.starts_with("where ") => (None, &s["where ".len()..]),
+            None if s.parse::<Aggregate>().is_ok() => (Some(s.parse()?), ""),
+            None => (None, s),
+        };
+        let mut query = Query {
+            conditions: vec![],
+            aggregate,
+        };
+        for condition in conditions.split(" and ").map(str::trim) {
+            if !condition.is_empty() {
+                query.conditions.push(condition.parse()?);
+            }
+        }
+        Ok(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smart_device::{Socket, Thermometer};
+    use crate::units::TemperatureUnit;
+
+    fn home() -> Home {
+        let mut home = Home::new("Home for queries");
+        home.add_room("hall");
+        home.add_room("kitchen");
+        home.add_zone("floor1");
+        home.place_room("kitchen", "floor1");
+        home.add_device("hall", "T", Thermometer::new(16_f64).into());
+        home.add_device("kitchen", "T", Thermometer::new(22_f64).into());
+        home.add_device(
+            "kitchen",
+            "S1",
+            Socket::new(220_f64, 5_f64, true).unwrap().into(),
+        );
+        home.add_device(
+            "kitchen",
+            "S2",
+            Socket::new(220_f64, 2_f64, true).unwrap().into(),
+        );
+        home.add_device(
+            "hall",
+            "S",
+            Socket::new(220_f64, 10_f64, false).unwrap().into(),
+        );
+        home.device_metadata_mut("hall", "S")
+            .unwrap()
+            .add_tag("night");
+        home
+    }
+
+    #[test]
+    fn test_parse() {
+        let query: Query = "kind = socket and power >= 1000".parse().unwrap();
+        assert_eq!(
+            Query::new()
+                .condition(Condition::new(
+                    Field::Kind,
+                    Op::Eq,
+                    Value::Text("socket".into())
+                ))
+                .condition(Condition::new(
+                    Field::Power,
+                    Op::Ge,
+                    Value::Number(1000_f64)
+                )),
+            query
+        );
+        let query: Query = "avg temperature where zone = floor1".parse().unwrap();
+        assert_eq!(Some(Aggregate::Avg(Field::Temperature)), query.aggregate);
+        assert_eq!(
+            Ok(Query::new().aggregate(Aggregate::Count)),
+            "count".parse()
+        );
+        assert_eq!(Ok(Query::new()), "".parse());
+        assert_eq!(
+            Err(QueryError::UnknownField("colour".into())),
+            "colour = red".parse::<Query>()
+        );
+        assert_eq!(
+            Err(QueryError::BadNumber("hot".into())),
+            "temperature > hot".parse::<Query>()
+        );
+        assert_eq!(
+            Err(QueryError::NotNumeric("kind".into())),
+            "kind < socket".parse::<Query>()
+        );
+        assert!("min kind".parse::<Query>().is_err());
+        assert!("power".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let home = home();
+        let units = Units::default();
+        let query: Query = "kind = socket and state = on and power > 1000"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            QueryOutput::Devices(vec![DevicePath::new("kitchen", "S1")]),
+            query.run(&home, &units)
+        );
+        let query: Query = "kind = thermometer and temperature < 18".parse().unwrap();
+        assert_eq!(
+            QueryOutput::Devices(vec![DevicePath::new("hall", "T")]),
+            query.run(&home, &units)
+        );
+        let query: Query = "zone = floor1 and kind != thermometer".parse().unwrap();
+        assert_eq!(2, query.select(&home, &units).len());
+        let query: Query = "tag = night".parse().unwrap();
+        assert_eq!(
+            QueryOutput::Devices(vec![DevicePath::new("hall", "S")]),
+            query.run(&home, &units)
+        );
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let home = home();
+        let units = Units::default();
+        let run = |text: &str| text.parse::<Query>().unwrap().run(&home, &units);
+        assert_eq!(QueryOutput::Count(5), run("count"));
+        assert_eq!(QueryOutput::Count(3), run("count where kind = socket"));
+        assert_eq!(QueryOutput::Value(Some(19_f64)), run("avg temperature"));
+        assert_eq!(QueryOutput::Value(Some(16_f64)), run("min temperature"));
+        assert_eq!(QueryOutput::Value(Some(1100_f64)), run("max power"));
+        assert_eq!(QueryOutput::Value(Some(1540_f64)), run("sum power"));
+        assert_eq!(
+            QueryOutput::Value(None),
+            run("avg temperature where room = attic")
+        );
+        let fahrenheit = Units {
+            temperature: TemperatureUnit::Fahrenheit,
+            ..Default::default()
+        };
+        let query: Query = "kind = thermometer and temperature > 70".parse().unwrap();
+        assert_eq!(
+            QueryOutput::Devices(vec![DevicePath::new("kitchen", "T")]),
+            query.run(&home, &fahrenheit)
+        );
+    }
+}
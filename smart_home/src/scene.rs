@@ -0,0 +1,177 @@
+use crate::error::{DeviceError, DeviceResult};
+use crate::group::DevicePath;
+use crate::smart_device::{Device, LockState};
+use std::collections::BTreeMap;
+
+/// Commanded part of the device state. Measured values are not a part of a scene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeviceState {
+    Socket { on: bool },
+    Lock { locked: bool },
+    WindowCovering { position: f64, tilt: f64 },
+}
+
+/// Named set of device states applied together, like "Away" or "Movie night".
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scene {
+    states: BTreeMap<DevicePath, DeviceState>,
+}
+
+impl DeviceState {
+    /// `None` for devices without a commanded state, such as thermometers.
+    pub fn capture(device: &Device) -> Option<Self> {
+        match device {
+            Device::Socket(socket) => Some(DeviceState::Socket { on: socket.is_on() }),
+            Device::Lock(lock) => Some(DeviceState::Lock {
+                locked: lock.get_target() == LockState::Locked,
+            }),
+            Device::WindowCovering(covering) => Some(DeviceState::WindowCovering {
+                position: covering.get_target_position(),
+                tilt: covering.get_target_tilt(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Commands the device at the path to take the state.
+    pub fn apply_to(&self, path: &DevicePath, device: &mut Device) -> DeviceResult<()> {
+        match (self, device) {
+            (DeviceState::Socket { on }, Device::Socket(socket)) => {
+                socket.switch(*on);
+                if socket.is_on() != *on {
+                    return Err(DeviceError::PowerLimit(path.to_string()));
+                }
+            }
+            (DeviceState::Lock { locked: true }, Device::Lock(lock)) => lock.lock(),
+            (DeviceState::Lock { locked: false }, Device::Lock(lock)) => lock.unlock(),
+            (DeviceState::WindowCovering { position, tilt }, Device::WindowCovering(covering)) => {
+                covering.set_target_position(*position);
+                covering.set_target_tilt(*tilt);
+            }
+            _ => return Err(DeviceError::KindMismatch(path.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Device kind followed by the state values.
+    pub fn fields(&self) -> Vec<String> {
+        match self {
+            DeviceState::Socket { on } => {
+                vec!["socket".into(), if *on { "on" } else { "off" }.into()]
+            }
+            DeviceState::Lock { locked } => {
+                let target = if *locked {
+                    LockState::Locked
+                } else {
+                    LockState::Unlocked
+                };
+                vec!["lock".into(), target.as_str().into()]
+            }
+            DeviceState::WindowCovering { position, tilt } => vec![
+                "window covering".into(),
+                position.to_string(),
+                tilt.to_string(),
+            ],
+        }
+    }
+
+    /// Inverse of [`DeviceState::fields`].
+    pub fn from_fields<'a>(fields: &mut impl Iterator<Item = &'a str>) -> Option<Self> {
+        match fields.next()? {
+            "socket" => match fields.next()? {
+                "on" => Some(DeviceState::Socket { on: true }),
+                "off" => Some(DeviceState::Socket { on: false }),
+                _ => None,
+            },
+            "lock" => match fields.next()?.parse().ok()? {
+                LockState::Locked => Some(DeviceState::Lock { locked: true }),
+                LockState::Unlocked => Some(DeviceState::Lock { locked: false }),
+                LockState::Jammed => None,
+            },
+            "window covering" => Some(DeviceState::WindowCovering {
+                position: fields.next()?.parse().ok()?,
+                tilt: fields.next()?.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn states(&self) -> impl Iterator<Item = (&DevicePath, &DeviceState)> {
+        self.states.iter()
+    }
+
+    /// Replaces the state kept for the device, if any.
+    pub fn set(&mut self, path: DevicePath, state: DeviceState) {
+        self.states.insert(path, state);
+    }
+
+    /// Keeps only the states of the devices for which `keep` returns true.
+    pub fn retain(&mut self, mut keep: impl FnMut(&DevicePath) -> bool) {
+        self.states.retain(|path, _| keep(path));
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smart_device::{Socket, WindowCovering};
+
+    #[test]
+    fn test_capture_and_apply() {
+        let path = DevicePath::new("R", "W");
+        let mut covering = WindowCovering::new(0_f64, 0_f64).into();
+        assert!(DeviceState::capture(&Device::new_thermometer()).is_none());
+        let state = DeviceState::WindowCovering {
+            position: 80_f64,
+            tilt: 20_f64,
+        };
+        state.apply_to(&path, &mut covering).unwrap();
+        assert_eq!(Some(state), DeviceState::capture(&covering));
+        assert_eq!(
+            Err(DeviceError::KindMismatch("R/W".into())),
+            DeviceState::Lock { locked: true }.apply_to(&path, &mut covering)
+        );
+        let mut socket = Socket::new(220_f64, 10_f64, false)
+            .unwrap()
+            .power_limit(Some(1000_f64))
+            .unwrap()
+            .into();
+        assert!(DeviceState::Socket { on: true }
+            .apply_to(&path, &mut socket)
+            .is_err());
+    }
+
+    #[test]
+    fn test_fields() {
+        for state in [
+            DeviceState::Socket { on: true },
+            DeviceState::Lock { locked: false },
+            DeviceState::WindowCovering {
+                position: 50_f64,
+                tilt: 12.5,
+            },
+        ] {
+            let fields = state.fields();
+            assert_eq!(
+                Some(state),
+                DeviceState::from_fields(&mut fields.iter().map(String::as_str))
+            );
+        }
+        assert!(DeviceState::from_fields(&mut ["lock", "jammed"].into_iter()).is_none());
+        assert!(DeviceState::from_fields(&mut ["thermometer", "20"].into_iter()).is_none());
+    }
+}
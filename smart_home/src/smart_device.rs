@@ -1,53 +1,169 @@
 #![allow(unused, dead_code)]
 
+use crate::error::{SocketError, SocketResult};
+use crate::units::{Current, Energy, Frequency, Power, Temperature, Units, Voltage};
 use std::fmt::format;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::time::Duration;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 #[non_exhaustive]
 pub enum Device {
     Socket(Socket),
     Thermometer(Thermometer),
+    Lock(Lock),
+    WindowCovering(WindowCovering),
     Unknown,
 }
 
-#[derive(Debug, PartialEq)]
+/// Socket with an electrical load. The load draws current only while the socket is on.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Socket {
     voltage: f64,
     current: f64,
+    frequency: f64,
+    power_factor: f64,
     on: bool,
+    /// Consumed energy in kWh.
+    energy: f64,
+    peak_power: f64,
+    power_limit: Option<f64>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Thermometer {
     temperature: f64,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockState {
+    Locked,
+    Unlocked,
+    Jammed,
+}
+
+/// Door lock. The commanded state is reached only after the operation time elapses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lock {
+    target: LockState,
+    state: LockState,
+    remaining: Duration,
+}
+
+/// Blinds or shutters. Position and tilt are percents, 0 means closed and 100 means open.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowCovering {
+    target_position: f64,
+    position: f64,
+    target_tilt: f64,
+    tilt: f64,
+}
+
+/// Typed snapshot of socket readings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SocketReadings {
+    pub voltage: Voltage,
+    pub current: Current,
+    pub frequency: Frequency,
+    pub power: Power,
+    pub peak_power: Power,
+    pub energy: Energy,
+}
+
 pub trait DeviceInfo {
-    fn device_info(&self) -> Vec<String>;
+    /// Values in default units (°C, W, kWh).
+    fn device_info(&self) -> Vec<String> {
+        self.device_info_in(&Units::default())
+    }
+
+    /// Values converted to the units preferred by a client.
+    fn device_info_in(&self, units: &Units) -> Vec<String>;
+
+    /// Human readable description with unit symbols.
+    fn describe(&self, units: &Units) -> String {
+        self.device_info_in(units).join(" ")
+    }
 }
 
 impl Device {
     pub fn new_socket() -> Self {
-        Device::Socket(Socket::new(220_f64, 0_f64, false))
+        Device::Socket(Socket::default())
     }
 
     pub fn new_thermometer() -> Self {
         Device::Thermometer(Thermometer::new(20_f64))
     }
 
+    pub fn new_lock() -> Self {
+        Device::Lock(Lock::new(false))
+    }
+
+    pub fn new_window_covering() -> Self {
+        Device::WindowCovering(WindowCovering::new(0_f64, 0_f64))
+    }
+
     pub fn report(&self) -> String {
-        String::from("Device...")
+        self.describe(&Units::default())
+    }
+
+    /// Kind of the device as it is named in device info.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Device::Socket(_) => "socket",
+            Device::Thermometer(_) => "thermometer",
+            Device::Lock(_) => "lock",
+            Device::WindowCovering(_) => "window covering",
+            _ => "unknown",
+        }
+    }
+
+    /// Discrete state of the device if it has one.
+    pub fn state(&self) -> Option<&'static str> {
+        match self {
+            Device::Socket(socket) => Some(if socket.is_on() { "on" } else { "off" }),
+            Device::Lock(lock) => Some(lock.get_state().as_str()),
+            Device::WindowCovering(covering) => Some(if covering.is_moving() {
+                "moving"
+            } else {
+                "stopped"
+            }),
+            _ => None,
+        }
+    }
+
+    /// Moves actuators towards their commanded state and meters consumed energy
+    /// as `elapsed` time has passed.
+    pub fn advance(&mut self, elapsed: Duration) {
+        match self {
+            Device::Lock(lock) => lock.advance(elapsed),
+            Device::WindowCovering(covering) => covering.advance(elapsed),
+            Device::Socket(socket) => socket.advance(elapsed),
+            _ => {}
+        }
     }
 }
 
 impl DeviceInfo for Device {
-    fn device_info(&self) -> Vec<String> {
+    fn device_info_in(&self, units: &Units) -> Vec<String> {
         match self {
-            Device::Socket(s) => s.device_info(),
-            Device::Thermometer(t) => t.device_info(),
+            Device::Socket(s) => s.device_info_in(units),
+            Device::Thermometer(t) => t.device_info_in(units),
+            Device::Lock(l) => l.device_info_in(units),
+            Device::WindowCovering(w) => w.device_info_in(units),
             _ => vec![String::from("Unknown device.")],
         }
     }
+
+    fn describe(&self, units: &Units) -> String {
+        match self {
+            Device::Socket(s) => s.describe(units),
+            Device::Thermometer(t) => t.describe(units),
+            Device::Lock(l) => l.describe(units),
+            Device::WindowCovering(w) => w.describe(units),
+            _ => String::from("Unknown device."),
+        }
+    }
 }
 
 impl From<Socket> for Device {
@@ -62,33 +178,133 @@ impl From<Thermometer> for Device {
     }
 }
 
+impl From<Lock> for Device {
+    fn from(l: Lock) -> Self {
+        Device::Lock(l)
+    }
+}
+
+impl From<WindowCovering> for Device {
+    fn from(w: WindowCovering) -> Self {
+        Device::WindowCovering(w)
+    }
+}
+
 impl Socket {
-    pub fn new(voltage: f64, current: f64, on: bool) -> Self {
-        Self {
-            voltage,
-            current,
+    pub const VOLTAGE_RANGE: RangeInclusive<f64> = 0_f64..=480_f64;
+    pub const CURRENT_RANGE: RangeInclusive<f64> = 0_f64..=32_f64;
+    pub const FREQUENCY_RANGE: RangeInclusive<f64> = 45_f64..=65_f64;
+    pub const POWER_FACTOR_RANGE: RangeInclusive<f64> = 0_f64..=1_f64;
+
+    pub fn new(voltage: f64, current: f64, on: bool) -> SocketResult<Self> {
+        let mut socket = Self {
             on,
+            ..Default::default()
+        };
+        socket.update(voltage, current, socket.frequency, socket.power_factor)?;
+        Ok(socket)
+    }
+
+    pub fn energy(mut self, energy: f64) -> SocketResult<Self> {
+        if !(energy.is_finite() && energy >= 0_f64) {
+            return Err(SocketError::Energy(energy));
         }
+        self.energy = energy;
+        Ok(self)
+    }
+
+    pub fn peak_power(mut self, peak_power: f64) -> SocketResult<Self> {
+        if !(peak_power.is_finite() && peak_power >= 0_f64) {
+            return Err(SocketError::PeakPower(peak_power));
+        }
+        self.peak_power = peak_power;
+        Ok(self)
+    }
+
+    pub fn power_limit(mut self, power_limit: Option<f64>) -> SocketResult<Self> {
+        self.set_power_limit(power_limit)?;
+        Ok(self)
     }
 
     pub fn get_voltage(&self) -> f64 {
         self.voltage
     }
 
-    pub fn set_voltage(&mut self, voltage: f64) {
-        self.voltage = voltage;
+    pub fn set_voltage(&mut self, voltage: f64) -> SocketResult<()> {
+        self.update(voltage, self.current, self.frequency, self.power_factor)
     }
 
+    /// Current flowing through the socket, zero while it is off.
     pub fn get_current(&self) -> f64 {
+        if self.on {
+            self.current
+        } else {
+            0_f64
+        }
+    }
+
+    /// Current drawn by the load when the socket is on.
+    pub fn get_load_current(&self) -> f64 {
         self.current
     }
 
-    pub fn set_current(&mut self, current: f64) {
+    /// Sets the current drawn by the load when the socket is on.
+    pub fn set_current(&mut self, current: f64) -> SocketResult<()> {
+        self.update(self.voltage, current, self.frequency, self.power_factor)
+    }
+
+    pub fn get_frequency(&self) -> f64 {
+        self.frequency
+    }
+
+    pub fn set_frequency(&mut self, frequency: f64) -> SocketResult<()> {
+        self.update(self.voltage, self.current, frequency, self.power_factor)
+    }
+
+    pub fn get_power_factor(&self) -> f64 {
+        self.power_factor
+    }
+
+    pub fn set_power_factor(&mut self, power_factor: f64) -> SocketResult<()> {
+        self.update(self.voltage, self.current, self.frequency, power_factor)
+    }
+
+    /// Validates all the parameters first, so the socket is left untouched on error.
+    pub fn update(
+        &mut self,
+        voltage: f64,
+        current: f64,
+        frequency: f64,
+        power_factor: f64,
+    ) -> SocketResult<()> {
+        if !Self::VOLTAGE_RANGE.contains(&voltage) {
+            return Err(SocketError::Voltage(voltage));
+        }
+        if !Self::CURRENT_RANGE.contains(&current) {
+            return Err(SocketError::Current(current));
+        }
+        if !Self::FREQUENCY_RANGE.contains(&frequency) {
+            return Err(SocketError::Frequency(frequency));
+        }
+        if !Self::POWER_FACTOR_RANGE.contains(&power_factor) {
+            return Err(SocketError::PowerFactor(power_factor));
+        }
+        self.voltage = voltage;
         self.current = current;
+        self.frequency = frequency;
+        self.power_factor = power_factor;
+        self.check_power();
+        Ok(())
     }
 
+    /// Real power, W.
     pub fn get_current_power(&self) -> f64 {
-        self.current * self.voltage
+        self.get_apparent_power() * self.power_factor
+    }
+
+    /// Apparent power, VA.
+    pub fn get_apparent_power(&self) -> f64 {
+        self.get_current() * self.voltage
     }
 
     pub fn is_on(&self) -> bool {
@@ -97,18 +313,119 @@ impl Socket {
 
     pub fn switch(&mut self, on: bool) {
         self.on = on;
+        self.check_power();
+    }
+
+    pub fn readings(&self) -> SocketReadings {
+        SocketReadings {
+            voltage: Voltage(self.voltage),
+            current: Current(self.get_current()),
+            frequency: Frequency(self.frequency),
+            power: Power::watts(self.get_current_power()),
+            peak_power: Power::watts(self.peak_power),
+            energy: Energy::kilowatt_hours(self.energy),
+        }
+    }
+
+    /// Energy consumed while the socket was on, kWh.
+    pub fn get_energy(&self) -> f64 {
+        self.energy
+    }
+
+    pub fn reset_energy(&mut self) {
+        self.energy = 0_f64;
+        self.peak_power = 0_f64;
+    }
+
+    pub fn get_peak_power(&self) -> f64 {
+        self.peak_power
+    }
+
+    pub fn get_power_limit(&self) -> Option<f64> {
+        self.power_limit
+    }
+
+    /// The socket switches itself off as soon as the power exceeds the limit.
+    /// The limit must be finite and greater than zero.
+    pub fn set_power_limit(&mut self, power_limit: Option<f64>) -> SocketResult<()> {
+        if let Some(limit) = power_limit.filter(|limit| !(limit.is_finite() && *limit > 0_f64)) {
+            return Err(SocketError::PowerLimit(limit));
+        }
+        self.power_limit = power_limit;
+        self.check_power();
+        Ok(())
+    }
+
+    pub fn advance(&mut self, elapsed: Duration) {
+        if self.on {
+            let hours = elapsed.as_secs_f64() / 3600_f64;
+            self.energy += self.get_current_power() * hours / 1000_f64;
+        }
+    }
+
+    fn check_power(&mut self) {
+        if !self.on {
+            return;
+        }
+        let power = self.get_current_power();
+        match self.power_limit {
+            Some(limit) if power > limit => self.on = false,
+            _ => self.peak_power = self.peak_power.max(power),
+        }
+    }
+}
+
+impl Default for Socket {
+    fn default() -> Self {
+        Self {
+            voltage: 220_f64,
+            current: 0_f64,
+            frequency: 50_f64,
+            power_factor: 1_f64,
+            on: false,
+            energy: 0_f64,
+            peak_power: 0_f64,
+            power_limit: None,
+        }
     }
 }
 
 impl DeviceInfo for Socket {
-    fn device_info(&self) -> Vec<String> {
+    fn device_info_in(&self, units: &Units) -> Vec<String> {
         let mut result = vec![];
         result.push("socket".into());
         result.push((if self.on { "on" } else { "off" }).into());
         result.push(format!("{}", self.current));
         result.push(format!("{}", self.voltage));
+        result.push(format!(
+            "{}",
+            Energy::kilowatt_hours(self.energy).value(units.energy)
+        ));
+        result.push(format!(
+            "{}",
+            Power::watts(self.peak_power).value(units.power)
+        ));
+        match self.power_limit {
+            Some(limit) => result.push(format!("{}", Power::watts(limit).value(units.power))),
+            None => result.push("none".into()),
+        }
+        result.push(format!("{}", self.frequency));
+        result.push(format!("{}", self.power_factor));
         result
     }
+
+    fn describe(&self, units: &Units) -> String {
+        let readings = self.readings();
+        format!(
+            "socket {}, {}, {}, {}, {}, {}",
+            if self.on { "on" } else { "off" },
+            readings.current,
+            readings.voltage,
+            readings.frequency,
+            readings.power.display(units.power),
+            readings.energy.display(units.energy),
+        )
+    }
 }
 
 impl Thermometer {
@@ -121,22 +438,265 @@ impl Thermometer {
         self
     }
 
+    /// Temperature in °C.
     pub fn get_temperature(&self) -> f64 {
         self.temperature
     }
 
+    pub fn reading(&self) -> Temperature {
+        Temperature::celsius(self.temperature)
+    }
+
     pub fn set_temperature(&mut self, temperature: f64) {
         self.temperature = temperature;
     }
 }
 
 impl DeviceInfo for Thermometer {
-    fn device_info(&self) -> Vec<String> {
+    fn device_info_in(&self, units: &Units) -> Vec<String> {
         let mut result = vec![];
         result.push("thermometer".into());
-        result.push(format!("{}", self.temperature));
+        result.push(format!("{}", self.reading().value(units.temperature)));
         result
     }
+
+    fn describe(&self, units: &Units) -> String {
+        format!("thermometer {}", self.reading().display(units.temperature))
+    }
+}
+
+impl LockState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LockState::Locked => "locked",
+            LockState::Unlocked => "unlocked",
+            LockState::Jammed => "jammed",
+        }
+    }
+}
+
+impl FromStr for LockState {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "locked" => Ok(LockState::Locked),
+            "unlocked" => Ok(LockState::Unlocked),
+            "jammed" => Ok(LockState::Jammed),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Lock {
+    pub const OPERATION_TIME: Duration = Duration::from_secs(2);
+
+    pub fn new(locked: bool) -> Self {
+        let state = if locked {
+            LockState::Locked
+        } else {
+            LockState::Unlocked
+        };
+        Self {
+            target: state,
+            state,
+            remaining: Duration::ZERO,
+        }
+    }
+
+    /// Restores a lock caught in the middle of an operation.
+    pub fn from_state(target: LockState, state: LockState, remaining: Duration) -> Self {
+        Self {
+            target,
+            state,
+            remaining,
+        }
+    }
+
+    pub fn get_target(&self) -> LockState {
+        self.target
+    }
+
+    pub fn get_state(&self) -> LockState {
+        self.state
+    }
+
+    pub fn get_remaining(&self) -> Duration {
+        self.remaining
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.state == LockState::Locked
+    }
+
+    pub fn in_progress(&self) -> bool {
+        !self.remaining.is_zero()
+    }
+
+    pub fn lock(&mut self) {
+        self.command(LockState::Locked);
+    }
+
+    pub fn unlock(&mut self) {
+        self.command(LockState::Unlocked);
+    }
+
+    /// The bolt got stuck. The lock stays jammed until it is commanded again.
+    pub fn jam(&mut self) {
+        self.state = LockState::Jammed;
+        self.remaining = Duration::ZERO;
+    }
+
+    pub fn advance(&mut self, elapsed: Duration) {
+        if self.in_progress() {
+            self.remaining = self.remaining.saturating_sub(elapsed);
+            if self.remaining.is_zero() {
+                self.state = self.target;
+            }
+        }
+    }
+
+    fn command(&mut self, target: LockState) {
+        self.target = target;
+        self.remaining = if self.state == target {
+            Duration::ZERO
+        } else {
+            Self::OPERATION_TIME
+        };
+    }
+}
+
+impl DeviceInfo for Lock {
+    fn device_info_in(&self, _units: &Units) -> Vec<String> {
+        let mut result = vec![];
+        result.push("lock".into());
+        result.push(self.target.as_str().into());
+        result.push(self.state.as_str().into());
+        result.push(format!("{}", self.remaining.as_millis()));
+        result
+    }
+
+    fn describe(&self, _units: &Units) -> String {
+        if self.in_progress() {
+            format!(
+                "lock {}, {} in progress",
+                self.state.as_str(),
+                self.target.as_str()
+            )
+        } else {
+            format!("lock {}", self.state.as_str())
+        }
+    }
+}
+
+impl WindowCovering {
+    /// Percents per second.
+    pub const POSITION_SPEED: f64 = 20_f64;
+    /// Percents per second.
+    pub const TILT_SPEED: f64 = 50_f64;
+
+    pub fn new(position: f64, tilt: f64) -> Self {
+        let position = clamp_percent(position);
+        let tilt = clamp_percent(tilt);
+        Self {
+            target_position: position,
+            position,
+            target_tilt: tilt,
+            tilt,
+        }
+    }
+
+    /// Restores a covering caught while moving.
+    pub fn from_state(target_position: f64, position: f64, target_tilt: f64, tilt: f64) -> Self {
+        Self {
+            target_position: clamp_percent(target_position),
+            position: clamp_percent(position),
+            target_tilt: clamp_percent(target_tilt),
+            tilt: clamp_percent(tilt),
+        }
+    }
+
+    pub fn get_position(&self) -> f64 {
+        self.position
+    }
+
+    pub fn get_target_position(&self) -> f64 {
+        self.target_position
+    }
+
+    pub fn set_target_position(&mut self, position: f64) {
+        self.target_position = clamp_percent(position);
+    }
+
+    pub fn get_tilt(&self) -> f64 {
+        self.tilt
+    }
+
+    pub fn get_target_tilt(&self) -> f64 {
+        self.target_tilt
+    }
+
+    pub fn set_target_tilt(&mut self, tilt: f64) {
+        self.target_tilt = clamp_percent(tilt);
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.position != self.target_position || self.tilt != self.target_tilt
+    }
+
+    pub fn stop(&mut self) {
+        self.target_position = self.position;
+        self.target_tilt = self.tilt;
+    }
+
+    pub fn advance(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        self.position = move_towards(
+            self.position,
+            self.target_position,
+            Self::POSITION_SPEED * seconds,
+        );
+        self.tilt = move_towards(self.tilt, self.target_tilt, Self::TILT_SPEED * seconds);
+    }
+}
+
+impl DeviceInfo for WindowCovering {
+    fn device_info_in(&self, _units: &Units) -> Vec<String> {
+        let mut result = vec![];
+        result.push("window covering".into());
+        result.push(format!("{}", self.target_position));
+        result.push(format!("{}", self.position));
+        result.push(format!("{}", self.target_tilt));
+        result.push(format!("{}", self.tilt));
+        result
+    }
+
+    fn describe(&self, _units: &Units) -> String {
+        format!(
+            "window covering {} % open, tilt {} %{}",
+            self.position,
+            self.tilt,
+            if self.is_moving() { ", moving" } else { "" }
+        )
+    }
+}
+
+fn clamp_percent(value: f64) -> f64 {
+    if value.is_nan() {
+        0_f64
+    } else {
+        value.clamp(0_f64, 100_f64)
+    }
+}
+
+fn move_towards(from: f64, to: f64, step: f64) -> f64 {
+    if (to - from).abs() <= step {
+        to
+    } else if to > from {
+        from + step
+    } else {
+        from - step
+    }
 }
 
 #[cfg(test)]
@@ -151,8 +711,8 @@ mod tests {
             assert_eq!(0_f64, socket.current);
             assert!(!socket.is_on());
             assert_eq!(0_f64, socket.get_current_power());
-            socket.set_voltage(225_f64);
-            socket.set_current(3_f64);
+            socket.set_voltage(225_f64).unwrap();
+            socket.set_current(3_f64).unwrap();
             socket.switch(true);
             assert_eq!(225_f64, socket.voltage);
             assert_eq!(3_f64, socket.current);
@@ -174,4 +734,183 @@ mod tests {
             panic!("Device::new_thermometer gives unexpected result.");
         }
     }
+
+    #[test]
+    fn test_lock() {
+        let mut device = Device::new_lock();
+        if let Device::Lock(lock) = &mut device {
+            assert_eq!(LockState::Unlocked, lock.get_state());
+            assert!(!lock.in_progress());
+            lock.lock();
+            assert_eq!(LockState::Locked, lock.get_target());
+            assert_eq!(LockState::Unlocked, lock.get_state());
+            assert!(lock.in_progress());
+        } else {
+            panic!("Device::new_lock gives unexpected result.");
+        }
+        device.advance(Duration::from_secs(1));
+        if let Device::Lock(lock) = &mut device {
+            assert!(lock.in_progress());
+            assert!(!lock.is_locked());
+        }
+        device.advance(Duration::from_secs(1));
+        if let Device::Lock(lock) = &mut device {
+            assert!(!lock.in_progress());
+            assert!(lock.is_locked());
+            lock.unlock();
+            lock.jam();
+            assert_eq!(LockState::Jammed, lock.get_state());
+            assert!(!lock.in_progress());
+            lock.unlock();
+            lock.advance(Lock::OPERATION_TIME);
+            assert_eq!(LockState::Unlocked, lock.get_state());
+        }
+    }
+
+    #[test]
+    fn test_window_covering() {
+        let mut covering = WindowCovering::new(0_f64, 150_f64);
+        assert_eq!(100_f64, covering.get_tilt());
+        assert!(!covering.is_moving());
+        covering.set_target_position(50_f64);
+        covering.set_target_tilt(0_f64);
+        assert!(covering.is_moving());
+        covering.advance(Duration::from_secs(1));
+        assert_eq!(20_f64, covering.get_position());
+        assert_eq!(50_f64, covering.get_tilt());
+        covering.advance(Duration::from_secs(2));
+        assert_eq!(50_f64, covering.get_position());
+        assert_eq!(0_f64, covering.get_tilt());
+        assert!(!covering.is_moving());
+        covering.set_target_position(0_f64);
+        covering.advance(Duration::from_millis(500));
+        covering.stop();
+        assert_eq!(40_f64, covering.get_target_position());
+        assert!(!covering.is_moving());
+    }
+
+    #[test]
+    fn test_socket_metering() {
+        let mut socket = Socket::new(200_f64, 5_f64, true).unwrap();
+        assert_eq!(1000_f64, socket.get_peak_power());
+        socket.advance(Duration::from_secs(1800));
+        assert!((socket.get_energy() - 0.5_f64).abs() < 1e-9);
+        socket.switch(false);
+        socket.advance(Duration::from_secs(1800));
+        assert!((socket.get_energy() - 0.5_f64).abs() < 1e-9);
+        socket.reset_energy();
+        assert_eq!(0_f64, socket.get_energy());
+        assert_eq!(0_f64, socket.get_peak_power());
+    }
+
+    #[test]
+    fn test_socket_power_limit() {
+        let mut socket = Socket::new(220_f64, 1_f64, true)
+            .unwrap()
+            .power_limit(Some(1000_f64))
+            .unwrap();
+        assert!(socket.is_on());
+        socket.set_current(4_f64).unwrap();
+        assert!(socket.is_on());
+        assert_eq!(880_f64, socket.get_peak_power());
+        socket.set_current(5_f64).unwrap();
+        assert!(!socket.is_on());
+        assert_eq!(880_f64, socket.get_peak_power());
+        socket.switch(true);
+        assert!(!socket.is_on());
+        socket.set_power_limit(None).unwrap();
+        socket.switch(true);
+        assert!(socket.is_on());
+        assert_eq!(1100_f64, socket.get_peak_power());
+    }
+
+    #[test]
+    fn test_socket_bad_values() {
+        let socket = || Socket::new(220_f64, 1_f64, true).unwrap();
+        for limit in [0_f64, -1_f64, f64::NAN, f64::INFINITY] {
+            let mut socket = socket();
+            assert!(socket.set_power_limit(Some(limit)).is_err());
+            assert_eq!(None, socket.get_power_limit());
+            assert!(socket.is_on());
+        }
+        for value in [-1_f64, f64::NAN, f64::INFINITY] {
+            assert!(socket().energy(value).is_err());
+            assert!(socket().peak_power(value).is_err());
+        }
+        let socket = socket().energy(0_f64).unwrap().peak_power(220_f64).unwrap();
+        assert_eq!(220_f64, socket.get_peak_power());
+    }
+
+    #[test]
+    fn test_socket_validation() {
+        assert_eq!(
+            Err(SocketError::Voltage(-1_f64)),
+            Socket::new(-1_f64, 0_f64, false)
+        );
+        assert_eq!(
+            Err(SocketError::Current(f64::INFINITY)),
+            Socket::new(220_f64, f64::INFINITY, false)
+        );
+        assert!(Socket::new(f64::NAN, 0_f64, false).is_err());
+        let mut socket = Socket::new(220_f64, 2_f64, true).unwrap();
+        assert_eq!(
+            Err(SocketError::Frequency(100_f64)),
+            socket.set_frequency(100_f64)
+        );
+        assert_eq!(
+            Err(SocketError::PowerFactor(1.5)),
+            socket.set_power_factor(1.5)
+        );
+        assert!(socket.update(230_f64, 100_f64, 60_f64, 0.5).is_err());
+        assert_eq!(220_f64, socket.get_voltage());
+        assert_eq!(50_f64, socket.get_frequency());
+        assert_eq!(1_f64, socket.get_power_factor());
+    }
+
+    #[test]
+    fn test_socket_power() {
+        let mut socket = Socket::new(200_f64, 5_f64, false).unwrap();
+        assert_eq!(5_f64, socket.get_load_current());
+        assert_eq!(0_f64, socket.get_current());
+        assert_eq!(0_f64, socket.get_apparent_power());
+        // The load is kept while the socket is off.
+        assert_eq!("5", socket.device_info()[2]);
+        socket.switch(true);
+        socket.set_power_factor(0.8).unwrap();
+        assert_eq!(5_f64, socket.get_current());
+        assert_eq!(1000_f64, socket.get_apparent_power());
+        assert!((socket.get_current_power() - 800_f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_device_info_in_units() {
+        use crate::units::{EnergyUnit, PowerUnit, TemperatureUnit};
+
+        let units = Units {
+            temperature: TemperatureUnit::Fahrenheit,
+            power: PowerUnit::Kilowatt,
+            energy: EnergyUnit::WattHour,
+        };
+        let thermometer = Thermometer::new(20_f64);
+        assert_eq!(vec!["thermometer", "20"], thermometer.device_info());
+        assert_eq!(
+            vec!["thermometer", "68"],
+            thermometer.device_info_in(&units)
+        );
+        assert_eq!("thermometer 68 °F", thermometer.describe(&units));
+        let socket = Socket::new(200_f64, 5_f64, true)
+            .unwrap()
+            .energy(1.5)
+            .unwrap()
+            .power_limit(Some(2000_f64))
+            .unwrap();
+        let info = socket.device_info_in(&units);
+        assert_eq!("1500", info[4]);
+        assert_eq!("1", info[5]);
+        assert_eq!("2", info[6]);
+        assert_eq!(
+            "socket on, 5 A, 200 V, 50 Hz, 1000 W, 1.5 kWh",
+            Device::Socket(socket).report()
+        );
+    }
 }
@@ -1,10 +1,15 @@
+use crate::metadata::{DeviceMetadata, RoomMetadata};
 use crate::smart_device::Device;
+use crate::stats::Stats;
 use std::collections::{hash_map::Entry, HashMap};
+use std::time::Duration;
 
 #[allow(dead_code, unused)]
 #[derive(Debug, PartialEq)]
 pub struct Room {
     devices: HashMap<String, Device>,
+    metadata: RoomMetadata,
+    device_metadata: HashMap<String, DeviceMetadata>,
 }
 
 #[allow(dead_code, unused)]
@@ -12,9 +17,27 @@ impl Room {
     pub fn new() -> Self {
         Room {
             devices: HashMap::new(),
+            metadata: RoomMetadata::default(),
+            device_metadata: HashMap::new(),
         }
     }
 
+    pub fn metadata(&self) -> &RoomMetadata {
+        &self.metadata
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut RoomMetadata {
+        &mut self.metadata
+    }
+
+    pub fn device_metadata(&self, device_name: &str) -> Option<&DeviceMetadata> {
+        self.device_metadata.get(device_name)
+    }
+
+    pub fn device_metadata_mut(&mut self, device_name: &str) -> Option<&mut DeviceMetadata> {
+        self.device_metadata.get_mut(device_name)
+    }
+
     pub fn device_names_list(&self) -> impl Iterator<Item = &String> {
         self.devices.keys()
     }
@@ -26,11 +49,16 @@ impl Room {
     pub fn add_device(&mut self, unique_name: &str, device: Device) -> Option<&Device> {
         match self.devices.entry(unique_name.into()) {
             Entry::Occupied(_) => None,
-            Entry::Vacant(entry) => Some(entry.insert(device)),
+            Entry::Vacant(entry) => {
+                self.device_metadata
+                    .insert(unique_name.into(), DeviceMetadata::default());
+                Some(entry.insert(device))
+            }
         }
     }
 
     pub fn remove_device(&mut self, device_name: &str) -> Option<Device> {
+        self.device_metadata.remove(device_name);
         self.devices.remove(device_name)
     }
 
@@ -41,6 +69,45 @@ impl Room {
     pub fn get_device_by_name_mut(&mut self, device_name: &str) -> Option<&mut Device> {
         self.devices.get_mut(device_name)
     }
+
+    pub fn stats(&self) -> Stats {
+        Stats::collect(self.devices.values())
+    }
+
+    /// Average temperature of the thermometers in the room, °C.
+    pub fn average_temperature(&self) -> Option<f64> {
+        self.stats().average_temperature
+    }
+
+    pub fn min_temperature(&self) -> Option<f64> {
+        self.stats().min_temperature
+    }
+
+    pub fn max_temperature(&self) -> Option<f64> {
+        self.stats().max_temperature
+    }
+
+    /// Power of the sockets that are on, W.
+    pub fn total_power(&self) -> f64 {
+        self.stats().total_power
+    }
+
+    /// Energy consumed by all sockets in the room, kWh.
+    pub fn total_energy(&self) -> f64 {
+        self.devices
+            .values()
+            .map(|device| match device {
+                Device::Socket(socket) => socket.get_energy(),
+                _ => 0_f64,
+            })
+            .sum()
+    }
+
+    pub fn advance(&mut self, elapsed: Duration) {
+        for device in self.devices.values_mut() {
+            device.advance(elapsed);
+        }
+    }
 }
 
 impl Default for Room {
@@ -52,6 +119,7 @@ impl Default for Room {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::smart_device::{Socket, Thermometer};
 
     #[test]
     fn test_room() {
@@ -98,4 +166,56 @@ mod tests {
         assert!(room.device_names_list().next().is_none());
         assert!(room.device_list().next().is_none())
     }
+
+    #[test]
+    fn test_total_energy() {
+        let mut room = Room::new();
+        room.add_device(
+            "S1",
+            Socket::new(220_f64, 0_f64, false)
+                .unwrap()
+                .energy(1.5)
+                .unwrap()
+                .into(),
+        );
+        room.add_device(
+            "S2",
+            Socket::new(220_f64, 0_f64, false)
+                .unwrap()
+                .energy(2_f64)
+                .unwrap()
+                .into(),
+        );
+        room.add_device("T", Device::new_thermometer());
+        assert_eq!(3.5, room.total_energy());
+    }
+
+    #[test]
+    fn test_device_metadata() {
+        let mut room = Room::new();
+        room.add_device("S", Device::new_socket());
+        assert_eq!(Some(&DeviceMetadata::default()), room.device_metadata("S"));
+        room.device_metadata_mut("S").unwrap().model = Some("X1".into());
+        assert_eq!(
+            Some("X1"),
+            room.device_metadata("S").unwrap().model.as_deref()
+        );
+        room.remove_device("S");
+        assert!(room.device_metadata("S").is_none());
+        assert!(room.device_metadata_mut("No device").is_none());
+    }
+
+    #[test]
+    fn test_aggregation() {
+        let mut room = Room::new();
+        assert!(room.average_temperature().is_none());
+        room.add_device("T1", Thermometer::new(24_f64).into());
+        room.add_device("T2", Thermometer::new(26_f64).into());
+        room.add_device("S", Socket::new(220_f64, 5_f64, true).unwrap().into());
+        assert_eq!(Some(25_f64), room.average_temperature());
+        assert_eq!(Some(24_f64), room.min_temperature());
+        assert_eq!(Some(26_f64), room.max_temperature());
+        assert_eq!(1100_f64, room.total_power());
+        assert_eq!(2, room.stats().count("thermometer"));
+    }
 }
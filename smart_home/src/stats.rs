@@ -0,0 +1,178 @@
+use crate::smart_device::Device;
+use crate::units::{Power, PowerUnit, Temperature, TemperatureUnit, Units};
+use std::collections::BTreeMap;
+
+/// Aggregated readings of a set of devices: a room, a zone or the whole home.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    /// °C, `None` if there are no thermometers.
+    pub average_temperature: Option<f64>,
+    pub min_temperature: Option<f64>,
+    pub max_temperature: Option<f64>,
+    /// Power of the sockets that are on, W.
+    pub total_power: f64,
+    /// Number of devices by kind.
+    pub kinds: BTreeMap<String, usize>,
+    /// Number of devices by kind and state like `socket on`.
+    pub states: BTreeMap<String, usize>,
+}
+
+const KIND_PREFIX: &str = "kind ";
+const STATE_PREFIX: &str = "state ";
+
+impl Stats {
+    pub fn collect<'a>(devices: impl Iterator<Item = &'a Device>) -> Self {
+        let mut stats = Stats::default();
+        let mut temperature_sum = 0_f64;
+        let mut thermometers = 0_usize;
+        for device in devices {
+            *stats.kinds.entry(device.kind().into()).or_default() += 1;
+            if let Some(state) = device.state() {
+                let key = format!("{} {}", device.kind(), state);
+                *stats.states.entry(key).or_default() += 1;
+            }
+            match device {
+                Device::Thermometer(thermometer) => {
+                    let temperature = thermometer.get_temperature();
+                    temperature_sum += temperature;
+                    thermometers += 1;
+                    stats.min_temperature = Some(
+                        stats
+                            .min_temperature
+                            .map_or(temperature, |t| t.min(temperature)),
+                    );
+                    stats.max_temperature = Some(
+                        stats
+                            .max_temperature
+                            .map_or(temperature, |t| t.max(temperature)),
+                    );
+                }
+                Device::Socket(socket) if socket.is_on() => {
+                    stats.total_power += socket.get_current_power();
+                }
+                _ => {}
+            }
+        }
+        if thermometers > 0 {
+            stats.average_temperature = Some(temperature_sum / thermometers as f64);
+        }
+        stats
+    }
+
+    pub fn count(&self, kind: &str) -> usize {
+        self.kinds.get(kind).copied().unwrap_or_default()
+    }
+
+    pub fn count_in_state(&self, kind: &str, state: &str) -> usize {
+        self.states
+            .get(&format!("{kind} {state}"))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// `name, value` pairs with values converted to the units.
+    pub fn fields_in(&self, units: &Units) -> Vec<String> {
+        let temperature = |t: Option<f64>| match t {
+            Some(t) => Temperature::celsius(t).value(units.temperature).to_string(),
+            None => String::from("none"),
+        };
+        let mut result = vec![
+            String::from("average temperature"),
+            temperature(self.average_temperature),
+            String::from("min temperature"),
+            temperature(self.min_temperature),
+            String::from("max temperature"),
+            temperature(self.max_temperature),
+            String::from("total power"),
+            Power::watts(self.total_power)
+                .value(units.power)
+                .to_string(),
+        ];
+        for (kind, count) in self.kinds.iter() {
+            result.push(format!("{KIND_PREFIX}{kind}"));
+            result.push(count.to_string());
+        }
+        for (state, count) in self.states.iter() {
+            result.push(format!("{STATE_PREFIX}{state}"));
+            result.push(count.to_string());
+        }
+        result
+    }
+
+    /// Inverse of [`Stats::fields_in`]. Unknown names are skipped.
+    pub fn from_fields<'a>(fields: &mut impl Iterator<Item = &'a str>, units: &Units) -> Self {
+        let temperature = |value: &str| {
+            value
+                .parse()
+                .ok()
+                .map(|t| Temperature::new(t, units.temperature).value(TemperatureUnit::Celsius))
+        };
+        let mut stats = Stats::default();
+        while let (Some(name), Some(value)) = (fields.next(), fields.next()) {
+            match name {
+                "average temperature" => stats.average_temperature = temperature(value),
+                "min temperature" => stats.min_temperature = temperature(value),
+                "max temperature" => stats.max_temperature = temperature(value),
+                "total power" => {
+                    stats.total_power = Power::new(value.parse().unwrap_or_default(), units.power)
+                        .value(PowerUnit::Watt)
+                }
+                _ => {
+                    let count = value.parse().unwrap_or_default();
+                    if let Some(kind) = name.strip_prefix(KIND_PREFIX) {
+                        stats.kinds.insert(kind.into(), count);
+                    } else if let Some(state) = name.strip_prefix(STATE_PREFIX) {
+                        stats.states.insert(state.into(), count);
+                    }
+                }
+            }
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smart_device::{Socket, Thermometer};
+
+    #[test]
+    fn test_collect() {
+        let devices = [
+            Device::from(Thermometer::new(24_f64)),
+            Device::from(Thermometer::new(26_f64)),
+            Device::from(Socket::new(220_f64, 5_f64, true).unwrap()),
+            Device::from(Socket::new(220_f64, 5_f64, false).unwrap()),
+            Device::new_lock(),
+        ];
+        let stats = Stats::collect(devices.iter());
+        assert_eq!(Some(25_f64), stats.average_temperature);
+        assert_eq!(Some(24_f64), stats.min_temperature);
+        assert_eq!(Some(26_f64), stats.max_temperature);
+        assert_eq!(1100_f64, stats.total_power);
+        assert_eq!(2, stats.count("socket"));
+        assert_eq!(0, stats.count("window covering"));
+        assert_eq!(1, stats.count_in_state("socket", "on"));
+        assert_eq!(1, stats.count_in_state("lock", "unlocked"));
+        assert_eq!(Stats::default(), Stats::collect(std::iter::empty()));
+    }
+
+    #[test]
+    fn test_fields() {
+        let devices = [
+            Device::from(Thermometer::new(20_f64)),
+            Device::from(Socket::new(200_f64, 5_f64, true).unwrap()),
+        ];
+        let stats = Stats::collect(devices.iter());
+        let units = Units {
+            temperature: TemperatureUnit::Fahrenheit,
+            power: PowerUnit::Kilowatt,
+            ..Default::default()
+        };
+        let fields = stats.fields_in(&units);
+        assert_eq!("68", fields[1]);
+        assert_eq!("1", fields[7]);
+        let restored = Stats::from_fields(&mut fields.iter().map(String::as_str), &units);
+        assert_eq!(stats, restored);
+    }
+}
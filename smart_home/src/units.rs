@@ -0,0 +1,316 @@
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PowerUnit {
+    #[default]
+    Watt,
+    Kilowatt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EnergyUnit {
+    WattHour,
+    #[default]
+    KilowattHour,
+}
+
+/// Units preferred by a client. Devices always keep their values in °C, W and kWh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Units {
+    pub temperature: TemperatureUnit,
+    pub power: PowerUnit,
+    pub energy: EnergyUnit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Temperature {
+    celsius: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Power {
+    watts: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Energy {
+    kilowatt_hours: f64,
+}
+
+/// Voltage, V.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Voltage(pub f64);
+
+/// Current, A.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Current(pub f64);
+
+/// Frequency, Hz.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Frequency(pub f64);
+
+/// Relative humidity, %.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Humidity(pub f64);
+
+impl TemperatureUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "celsius",
+            TemperatureUnit::Fahrenheit => "fahrenheit",
+            TemperatureUnit::Kelvin => "kelvin",
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "°C",
+            TemperatureUnit::Fahrenheit => "°F",
+            TemperatureUnit::Kelvin => "K",
+        }
+    }
+}
+
+impl FromStr for TemperatureUnit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "celsius" => Ok(TemperatureUnit::Celsius),
+            "fahrenheit" => Ok(TemperatureUnit::Fahrenheit),
+            "kelvin" => Ok(TemperatureUnit::Kelvin),
+            _ => Err(()),
+        }
+    }
+}
+
+impl PowerUnit {
+    pub fn as_str(&self) -> &'static str {
+        self.symbol()
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            PowerUnit::Watt => "W",
+            PowerUnit::Kilowatt => "kW",
+        }
+    }
+}
+
+impl FromStr for PowerUnit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "W" => Ok(PowerUnit::Watt),
+            "kW" => Ok(PowerUnit::Kilowatt),
+            _ => Err(()),
+        }
+    }
+}
+
+impl EnergyUnit {
+    pub fn as_str(&self) -> &'static str {
+        self.symbol()
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            EnergyUnit::WattHour => "Wh",
+            EnergyUnit::KilowattHour => "kWh",
+        }
+    }
+}
+
+impl FromStr for EnergyUnit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Wh" => Ok(EnergyUnit::WattHour),
+            "kWh" => Ok(EnergyUnit::KilowattHour),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Temperature {
+    pub fn new(value: f64, unit: TemperatureUnit) -> Self {
+        let celsius = match unit {
+            TemperatureUnit::Celsius => value,
+            TemperatureUnit::Fahrenheit => (value - 32_f64) * 5_f64 / 9_f64,
+            TemperatureUnit::Kelvin => value - 273.15,
+        };
+        Self { celsius }
+    }
+
+    pub fn celsius(celsius: f64) -> Self {
+        Self { celsius }
+    }
+
+    pub fn value(&self, unit: TemperatureUnit) -> f64 {
+        match unit {
+            TemperatureUnit::Celsius => self.celsius,
+            TemperatureUnit::Fahrenheit => self.celsius * 9_f64 / 5_f64 + 32_f64,
+            TemperatureUnit::Kelvin => self.celsius + 273.15,
+        }
+    }
+
+    pub fn display(&self, unit: TemperatureUnit) -> String {
+        format!("{} {}", self.value(unit), unit.symbol())
+    }
+}
+
+impl Power {
+    pub fn new(value: f64, unit: PowerUnit) -> Self {
+        let watts = match unit {
+            PowerUnit::Watt => value,
+            PowerUnit::Kilowatt => value * 1000_f64,
+        };
+        Self { watts }
+    }
+
+    pub fn watts(watts: f64) -> Self {
+        Self { watts }
+    }
+
+    pub fn value(&self, unit: PowerUnit) -> f64 {
+        match unit {
+            PowerUnit::Watt => self.watts,
+            PowerUnit::Kilowatt => self.watts / 1000_f64,
+        }
+    }
+
+    pub fn display(&self, unit: PowerUnit) -> String {
+        format!("{} {}", self.value(unit), unit.symbol())
+    }
+}
+
+impl Energy {
+    pub fn new(value: f64, unit: EnergyUnit) -> Self {
+        let kilowatt_hours = match unit {
+            EnergyUnit::WattHour => value / 1000_f64,
+            EnergyUnit::KilowattHour => value,
+        };
+        Self { kilowatt_hours }
+    }
+
+    pub fn kilowatt_hours(kilowatt_hours: f64) -> Self {
+        Self { kilowatt_hours }
+    }
+
+    pub fn value(&self, unit: EnergyUnit) -> f64 {
+        match unit {
+            EnergyUnit::WattHour => self.kilowatt_hours * 1000_f64,
+            EnergyUnit::KilowattHour => self.kilowatt_hours,
+        }
+    }
+
+    pub fn display(&self, unit: EnergyUnit) -> String {
+        format!("{} {}", self.value(unit), unit.symbol())
+    }
+}
+
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display(TemperatureUnit::Celsius))
+    }
+}
+
+impl fmt::Display for Power {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display(PowerUnit::Watt))
+    }
+}
+
+impl fmt::Display for Energy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display(EnergyUnit::KilowattHour))
+    }
+}
+
+impl fmt::Display for Voltage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} V", self.0)
+    }
+}
+
+impl fmt::Display for Current {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} A", self.0)
+    }
+}
+
+impl fmt::Display for Frequency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} Hz", self.0)
+    }
+}
+
+impl fmt::Display for Humidity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} %", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temperature() {
+        let t = Temperature::new(212_f64, TemperatureUnit::Fahrenheit);
+        assert!((t.value(TemperatureUnit::Celsius) - 100_f64).abs() < 1e-9);
+        assert!((t.value(TemperatureUnit::Kelvin) - 373.15).abs() < 1e-9);
+        let t = Temperature::new(0_f64, TemperatureUnit::Kelvin);
+        assert!((t.value(TemperatureUnit::Celsius) + 273.15).abs() < 1e-9);
+        assert_eq!("20 °C", Temperature::celsius(20_f64).to_string());
+        assert_eq!(
+            "68 °F",
+            Temperature::celsius(20_f64).display(TemperatureUnit::Fahrenheit)
+        );
+    }
+
+    #[test]
+    fn test_power_and_energy() {
+        assert_eq!(
+            1500_f64,
+            Power::new(1.5, PowerUnit::Kilowatt).value(PowerUnit::Watt)
+        );
+        assert_eq!(
+            "1.5 kW",
+            Power::watts(1500_f64).display(PowerUnit::Kilowatt)
+        );
+        assert_eq!(
+            0.25,
+            Energy::new(250_f64, EnergyUnit::WattHour).value(EnergyUnit::KilowattHour)
+        );
+        assert_eq!(
+            "250 Wh",
+            Energy::kilowatt_hours(0.25).display(EnergyUnit::WattHour)
+        );
+    }
+
+    #[test]
+    fn test_unit_names() {
+        for unit in [
+            TemperatureUnit::Celsius,
+            TemperatureUnit::Fahrenheit,
+            TemperatureUnit::Kelvin,
+        ] {
+            assert_eq!(Ok(unit), unit.as_str().parse());
+        }
+        assert_eq!(Ok(PowerUnit::Kilowatt), "kW".parse());
+        assert_eq!(Ok(EnergyUnit::WattHour), "Wh".parse());
+        assert!("kelvins".parse::<TemperatureUnit>().is_err());
+    }
+}
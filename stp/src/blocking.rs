@@ -0,0 +1,124 @@
+//! Client on std sockets for programs without an async runtime.
+
+use crate::client::RequestResult;
+use crate::error::{ConnectError, ConnectResult, RecvError, RecvResult, SendResult};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use tracing::{debug, trace, warn};
+
+pub struct StpClient {
+    stream: TcpStream,
+}
+
+impl StpClient {
+    pub fn connect<Addrs>(addr: Addrs) -> ConnectResult<Self>
+    where
+        Addrs: ToSocketAddrs,
+    {
+        let stream = TcpStream::connect(addr).inspect_err(|e| {
+            warn!(error = %e, "connect failed");
+        })?;
+        let peer = stream.peer_addr()?;
+        debug!(%peer, "connected");
+        Self::try_handshake(stream).inspect_err(|e| {
+            warn!(%peer, error = %e, "handshake failed");
+        })
+    }
+
+    fn try_handshake(mut s: TcpStream) -> ConnectResult<Self> {
+        s.write_all(b"clnt")?;
+        let mut buf = [0; 4];
+        s.read_exact(&mut buf)?;
+        if &buf != b"serv" {
+            let msg = format!("received: {:?}", buf);
+            return Err(ConnectError::BadHandshake(msg));
+        }
+        debug!("handshake done");
+        Ok(Self { stream: s })
+    }
+
+    pub fn send_request<R: AsRef<str>>(&self, req: R) -> RequestResult {
+        send_string(req, &self.stream)?;
+        let response = recv_string(&self.stream)?;
+        Ok(response)
+    }
+
+    /// Receives a message the server sends on its own, without a request.
+    pub fn recv_message(&self) -> RecvResult {
+        recv_string(&self.stream)
+    }
+}
+
+fn send_string<D: AsRef<str>>(d: D, mut w: &TcpStream) -> SendResult {
+    let bytes = d.as_ref().as_bytes();
+    let len = bytes.len() as u32;
+    w.write_all(&len.to_be_bytes())?;
+    w.write_all(bytes)?;
+    trace!(bytes = len, "frame sent");
+    Ok(())
+}
+
+fn recv_string(mut r: &TcpStream) -> RecvResult {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    let len = u32::from_be_bytes(buf);
+
+    let mut buf = vec![0; len as _];
+    r.read_exact(&mut buf)?;
+    trace!(bytes = len, "frame received");
+    String::from_utf8(buf).map_err(|_| {
+        warn!("frame is not UTF-8");
+        RecvError::BadEncoding
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::StpServer;
+    use std::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_request_and_message() {
+        let server = StpServer::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let serve = tokio::spawn(async move {
+            let peer = server.accept().await.unwrap();
+            let request = peer.recv_request().await.unwrap();
+            peer.send_response(format!("Ok///{request}")).await.unwrap();
+            peer.send_response("Event///notification///test///hello")
+                .await
+                .unwrap();
+        });
+        tokio::task::spawn_blocking(move || {
+            let client = StpClient::connect(addr).unwrap();
+            assert_eq!("Ok///room list", client.send_request("room list").unwrap());
+            assert_eq!(
+                "Event///notification///test///hello",
+                client.recv_message().unwrap()
+            );
+            // The server has closed the connection.
+            assert!(matches!(client.recv_message(), Err(RecvError::Io(_))));
+        })
+        .await
+        .unwrap();
+        serve.await.unwrap();
+    }
+
+    #[test]
+    fn test_bad_handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let serve = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).unwrap();
+            stream.write_all(b"nope").unwrap();
+        });
+        assert!(matches!(
+            StpClient::connect(addr),
+            Err(ConnectError::BadHandshake(_))
+        ));
+        serve.join().unwrap();
+    }
+}
@@ -1,6 +1,7 @@
-use crate::error::{ConnectError, ConnectResult, RecvError, SendError};
+use crate::error::{ConnectError, ConnectResult, RecvError, RecvResult, SendError};
 use thiserror::Error;
 use tokio::net::{TcpStream, ToSocketAddrs};
+use tracing::{debug, warn};
 
 pub struct StpClient {
     stream: TcpStream,
@@ -11,8 +12,14 @@ impl StpClient {
     where
         Addrs: ToSocketAddrs,
     {
-        let stream = TcpStream::connect(addr).await?;
-        Self::try_handshake(stream).await
+        let stream = TcpStream::connect(addr).await.inspect_err(|e| {
+            warn!(error = %e, "connect failed");
+        })?;
+        let peer = stream.peer_addr()?;
+        debug!(%peer, "connected");
+        Self::try_handshake(stream).await.inspect_err(|e| {
+            warn!(%peer, error = %e, "handshake failed");
+        })
     }
 
     async fn try_handshake(s: TcpStream) -> ConnectResult<Self> {
@@ -23,6 +30,7 @@ impl StpClient {
             let msg = format!("received: {:?}", buf);
             return Err(ConnectError::BadHandshake(msg));
         }
+        debug!("handshake done");
         Ok(Self { stream: s })
     }
 
@@ -31,6 +39,11 @@ impl StpClient {
         let response = super::recv_string(&self.stream).await?;
         Ok(response)
     }
+
+    /// Receives a message the server sends on its own, without a request.
+    pub async fn recv_message(&self) -> RecvResult {
+        super::recv_string(&self.stream).await
+    }
 }
 
 pub type RequestResult = Result<String, RequestError>;
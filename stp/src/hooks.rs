@@ -0,0 +1,90 @@
+//! Callbacks reporting the traffic of a server, e.g. to collect metrics.
+
+/// Every method does nothing by default. Frame sizes include the length prefix.
+pub trait StpHooks: Send + Sync {
+    fn connection_opened(&self) {}
+
+    fn connection_closed(&self) {}
+
+    fn handshake_failed(&self) {}
+
+    fn frame_sent(&self, _bytes: usize) {}
+
+    fn frame_received(&self, _bytes: usize) {}
+}
+
+pub(crate) struct NoHooks;
+
+impl StpHooks for NoHooks {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::StpClient;
+    use crate::server::StpServer;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpStream;
+
+    #[derive(Default)]
+    struct Counters {
+        opened: AtomicUsize,
+        closed: AtomicUsize,
+        failed: AtomicUsize,
+        sent: AtomicUsize,
+        received: AtomicUsize,
+    }
+
+    impl StpHooks for Counters {
+        fn connection_opened(&self) {
+            self.opened.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn connection_closed(&self) {
+            self.closed.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn handshake_failed(&self) {
+            self.failed.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn frame_sent(&self, bytes: usize) {
+            self.sent.fetch_add(bytes, Ordering::SeqCst);
+        }
+
+        fn frame_received(&self, bytes: usize) {
+            self.received.fetch_add(bytes, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hooks() {
+        let counters = Arc::new(Counters::default());
+        let server = StpServer::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .hooks(counters.clone());
+        let addr = server.local_addr().unwrap();
+
+        let client = tokio::spawn(async move {
+            let client = StpClient::connect(addr).await.unwrap();
+            client.send_request("ping").await.unwrap()
+        });
+        let peer = server.accept().await.unwrap();
+        let request = peer.recv_request().await.unwrap();
+        peer.send_response(format!("Ok///{request}")).await.unwrap();
+        assert_eq!("Ok///ping", client.await.unwrap());
+        assert_eq!(1, counters.opened.load(Ordering::SeqCst));
+        assert_eq!(4 + 4, counters.received.load(Ordering::SeqCst));
+        assert_eq!(4 + 9, counters.sent.load(Ordering::SeqCst));
+        drop(peer);
+        assert_eq!(1, counters.closed.load(Ordering::SeqCst));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"nope").await.unwrap();
+        assert!(server.accept().await.is_err());
+        assert_eq!(1, counters.failed.load(Ordering::SeqCst));
+        assert_eq!(1, counters.opened.load(Ordering::SeqCst));
+    }
+}
@@ -1,9 +1,15 @@
 use crate::error::{RecvError, RecvResult, SendResult};
 use std::io;
 use tokio::net::TcpStream;
+use tracing::{trace, warn};
 
+/// Size of the length prefix of a frame.
+const LENGTH_BYTES: usize = 4;
+
+pub mod blocking;
 pub mod client;
 pub mod error;
+pub mod hooks;
 pub mod server;
 
 async fn read_exact_async(s: &TcpStream, buf: &mut [u8]) -> io::Result<()> {
@@ -11,7 +17,8 @@ async fn read_exact_async(s: &TcpStream, buf: &mut [u8]) -> io::Result<()> {
     while have_read < buf.len() {
         s.readable().await?;
         match s.try_read(&mut buf[have_read..]) {
-            Ok(0) => break,
+            // The peer has closed the connection.
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
             Ok(n) => {
                 have_read += n;
             }
@@ -48,6 +55,7 @@ async fn send_string<D: AsRef<str>>(d: D, w: &TcpStream) -> SendResult {
     let len_bytes = len.to_be_bytes();
     write_all_async(w, &len_bytes).await?;
     write_all_async(w, bytes).await?;
+    trace!(bytes = len, "frame sent");
     Ok(())
 }
 
@@ -58,5 +66,9 @@ async fn recv_string(r: &TcpStream) -> RecvResult {
 
     let mut buf = vec![0; len as _];
     read_exact_async(r, &mut buf).await?;
-    String::from_utf8(buf).map_err(|_| RecvError::BadEncoding)
+    trace!(bytes = len, "frame received");
+    String::from_utf8(buf).map_err(|_| {
+        warn!("frame is not UTF-8");
+        RecvError::BadEncoding
+    })
 }
@@ -1,11 +1,16 @@
 use crate::error::{ConnectError, ConnectResult, RecvResult, SendResult};
+use crate::hooks::{NoHooks, StpHooks};
+use crate::LENGTH_BYTES;
 use std::io;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use thiserror::Error;
 use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tracing::{debug, info, warn};
 
 pub struct StpServer {
     tcp: TcpListener,
+    hooks: Arc<dyn StpHooks>,
 }
 
 pub type BindResult = Result<StpServer, BindError>;
@@ -22,16 +27,36 @@ impl StpServer {
         Addrs: ToSocketAddrs,
     {
         let tcp = TcpListener::bind(addrs).await?;
-        Ok(Self { tcp })
+        info!(addr = %tcp.local_addr()?, "listening");
+        Ok(Self {
+            tcp,
+            hooks: Arc::new(NoHooks),
+        })
+    }
+
+    /// Reports the traffic of the server and its connections.
+    pub fn hooks(mut self, hooks: Arc<dyn StpHooks>) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp.local_addr()
     }
 
     /// Blocking iterator for incoming connections.
     pub async fn accept(&self) -> ConnectResult<StpConnection> {
-        let (stream, _) = self.tcp.accept().await?;
-        Self::try_handshake(stream).await
+        let (stream, peer) = self.tcp.accept().await?;
+        debug!(%peer, "accepted");
+        let connection = self.try_handshake(stream).await.inspect_err(|e| {
+            warn!(%peer, error = %e, "handshake failed");
+            self.hooks.handshake_failed();
+        })?;
+        self.hooks.connection_opened();
+        Ok(connection)
     }
 
-    async fn try_handshake(stream: TcpStream) -> ConnectResult<StpConnection> {
+    async fn try_handshake(&self, stream: TcpStream) -> ConnectResult<StpConnection> {
         let mut buf = [0; 4];
         super::read_exact_async(&stream, &mut buf).await?;
         if &buf != b"clnt" {
@@ -39,24 +64,40 @@ impl StpServer {
             return Err(ConnectError::BadHandshake(msg));
         }
         super::write_all_async(&stream, b"serv").await?;
-        Ok(StpConnection { stream })
+        debug!("handshake done");
+        Ok(StpConnection {
+            stream,
+            hooks: Arc::clone(&self.hooks),
+        })
     }
 }
 
 pub struct StpConnection {
     stream: TcpStream,
+    hooks: Arc<dyn StpHooks>,
 }
 
 impl StpConnection {
     pub async fn send_response<Resp: AsRef<str>>(&self, response: Resp) -> SendResult {
-        super::send_string(response, &self.stream).await
+        let bytes = response.as_ref().len() + LENGTH_BYTES;
+        super::send_string(response, &self.stream).await?;
+        self.hooks.frame_sent(bytes);
+        Ok(())
     }
 
     pub async fn recv_request(&self) -> RecvResult {
-        crate::recv_string(&self.stream).await
+        let request = crate::recv_string(&self.stream).await?;
+        self.hooks.frame_received(request.len() + LENGTH_BYTES);
+        Ok(request)
     }
 
     pub fn peer_addr(&self) -> io::Result<SocketAddr> {
         self.stream.peer_addr()
     }
 }
+
+impl Drop for StpConnection {
+    fn drop(&mut self) {
+        self.hooks.connection_closed();
+    }
+}